@@ -0,0 +1,217 @@
+use crate::{ERROR, load_builder};
+use clap::Args;
+use console::{Emoji, style};
+use spectre::*;
+use std::{
+    fs::{File, create_dir_all, remove_dir_all, write},
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+#[derive(Args, Debug)]
+pub struct FuzzArgs {
+    /// Builder file with a `[generate]` section
+    #[clap(default_value = "spectre.toml")]
+    builder: PathBuf,
+
+    #[clap(long, default_value_t = 100, help = "Number of scenarios to try")]
+    iterations: u64,
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Random seed of the first scenario, the following ones count up from it"
+    )]
+    seed: u64,
+    #[clap(
+        long,
+        help = "Shell command checking the witnesses, called with the witness paths appended, fails on non-zero exit; without it the witnesses are verified in-process"
+    )]
+    checker: Option<String>,
+    #[clap(
+        long,
+        help = "Skip scenarios the node fails to trace instead of counting them as failures"
+    )]
+    skip_trace_errors: bool,
+    #[clap(
+        long,
+        default_value = "fuzz",
+        help = "Directory for the shrunk failing builders"
+    )]
+    out_dir: PathBuf,
+}
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    /// The scenario could not be built, or traced with `--skip-trace-errors`, so it was not
+    /// checked.
+    Skipped(String),
+}
+
+pub async fn run(args: FuzzArgs) {
+    let template = load_builder(&args.builder);
+    if template.generate.is_none() {
+        eprintln!(
+            "{ERROR}{}",
+            style("builder has no [generate] section to fuzz").bold()
+        );
+        exit(1);
+    }
+
+    create_dir_all(&args.out_dir)
+        .inspect_err(|e| {
+            eprintln!(
+                "{ERROR}{}",
+                style(format!("error creating directory: {}", e)).bold()
+            );
+            exit(1);
+        })
+        .unwrap();
+    let work_dir = args.out_dir.join("work");
+
+    let mut failures = vec![];
+    for seed in (0..args.iterations).map(|i| args.seed.wrapping_add(i)) {
+        let mut builder = template.clone();
+        builder.system.random_seed = Some(seed);
+        let scenario = builder
+            .expand()
+            .inspect_err(|e| {
+                eprintln!(
+                    "{ERROR}{}",
+                    style(format!("error expanding builder: {}", e)).bold()
+                );
+                exit(1);
+            })
+            .unwrap();
+
+        match check(scenario.clone(), &args, &work_dir).await {
+            Outcome::Pass => eprintln!("{}seed {seed} passed", Emoji("✅  ", "ok ")),
+            Outcome::Skipped(reason) => {
+                eprintln!("{}seed {seed} skipped: {reason}", Emoji("⚠️  ", "?? "))
+            }
+            Outcome::Fail(reason) => {
+                eprintln!(
+                    "{}seed {seed} failed: {reason}, shrinking {} transactions",
+                    Emoji("💥  ", "!! "),
+                    scenario.transactions.len()
+                );
+                let args = &args;
+                let work_dir = work_dir.as_path();
+                let minimal = shrink(scenario, |candidate| async move {
+                    matches!(check(candidate, args, work_dir).await, Outcome::Fail(_))
+                })
+                .await;
+
+                let path = args.out_dir.join(format!("failure-{seed}.toml"));
+                write(&path, toml::to_string(&minimal).unwrap())
+                    .inspect_err(|e| {
+                        eprintln!(
+                            "{ERROR}{}",
+                            style(format!("error writing file: {}", e)).bold()
+                        );
+                        exit(1);
+                    })
+                    .unwrap();
+                eprintln!(
+                    "{}shrunk to {} transactions: {}",
+                    Emoji("🔍  ", "-> "),
+                    minimal.transactions.len(),
+                    path.display()
+                );
+                failures.push(path);
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        eprintln!(
+            "{}no failures in {} scenarios",
+            Emoji("✨  ", ":-) "),
+            args.iterations
+        );
+    } else {
+        eprintln!(
+            "{ERROR}{}",
+            style(format!(
+                "{} of {} scenarios failed",
+                failures.len(),
+                args.iterations
+            ))
+            .bold()
+        );
+        exit(1);
+    }
+}
+
+/// Builds and traces the scenario, then checks its witnesses.
+async fn check(scenario: SpectreBuilder, args: &FuzzArgs, work_dir: &Path) -> Outcome {
+    let spectre = match scenario.build() {
+        Ok(spectre) => spectre,
+        Err(e) => return Outcome::Skipped(format!("error building spectre: {e}")),
+    };
+    let report = match spectre.trace().await {
+        Ok(report) => report,
+        Err(e) if args.skip_trace_errors => {
+            return Outcome::Skipped(format!("error when dump witness: {e}"));
+        }
+        Err(e) => return Outcome::Fail(format!("error when dump witness: {e}")),
+    };
+    match &args.checker {
+        Some(checker) => run_checker(&report, checker, work_dir).await,
+        None => verify(&report),
+    }
+}
+
+/// Re-executes every witness in-process against the block the node sealed.
+fn verify(report: &TraceReport) -> Outcome {
+    let mismatches = report
+        .verify()
+        .into_iter()
+        .flat_map(|verification| {
+            let number = verification.number;
+            verification
+                .mismatches
+                .into_iter()
+                .map(move |mismatch| format!("block #{number}: {mismatch}"))
+        })
+        .collect::<Vec<_>>();
+    if mismatches.is_empty() {
+        Outcome::Pass
+    } else {
+        Outcome::Fail(mismatches.join(", "))
+    }
+}
+
+/// Writes the witnesses to `work_dir` and runs the checker on them.
+async fn run_checker(report: &TraceReport, checker: &str, work_dir: &Path) -> Outcome {
+    let _ = remove_dir_all(work_dir);
+    if let Err(e) = create_dir_all(work_dir) {
+        return Outcome::Skipped(format!("error creating directory: {e}"));
+    }
+    let mut paths = vec![];
+    for (idx, block) in report.blocks.iter().enumerate() {
+        let path = work_dir.join(format!("witness-{idx}.json"));
+        let written = File::create(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                serde_json::to_writer_pretty(file, &block.witness).map_err(|e| e.to_string())
+            });
+        if let Err(e) = written {
+            return Outcome::Skipped(format!("error writing witness: {e}"));
+        }
+        paths.push(path);
+    }
+
+    let status = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{checker} \"$@\""))
+        .arg("spectre-checker")
+        .args(&paths)
+        .status()
+        .await;
+    match status {
+        Ok(status) if status.success() => Outcome::Pass,
+        Ok(status) => Outcome::Fail(format!("checker exited with {status}")),
+        Err(e) => Outcome::Skipped(format!("error running checker: {e}")),
+    }
+}
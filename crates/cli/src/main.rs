@@ -1,89 +1,230 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use console::{Emoji, style};
-use spectre::*;
+use spectre::{backend::TraceKind, *};
 use std::{
-    fs::{File, read_to_string},
+    fmt::Display,
+    fs::{File, read, read_to_string},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::exit,
 };
 
+mod fuzz;
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[clap(default_value = "spectre.toml")]
     builder: PathBuf,
     #[clap(default_value = "witness.json")]
     out: PathBuf,
 
-    #[clap(long, help = "Create a new builder file")]
-    new: bool,
+    #[clap(
+        long,
+        help = "Print the builder with wallets and generated transactions expanded, then exit"
+    )]
+    expand: bool,
+    #[clap(
+        long,
+        help = "Print the code of allocs, mnemonic as written with offsets, hex disassembled"
+    )]
+    show_code: bool,
+    #[clap(
+        long,
+        help = "Fail on code warnings: opcodes or precompiles the chain does not offer yet, stack errors"
+    )]
+    strict: bool,
+    #[clap(
+        long,
+        help = "Also write the trace report with receipts and block metadata as JSON next to the witnesses"
+    )]
+    report: bool,
+    #[clap(
+        long,
+        help = "Re-execute every witness statelessly and refuse to write any that does not match its block"
+    )]
+    verify: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a new builder file
+    New {
+        #[clap(default_value = "spectre.toml")]
+        out: PathBuf,
+        #[clap(
+            long,
+            default_value = "full",
+            help = "Template to start from: full, minimal, or a scenario (erc20, weth, create2-factory, proxy)"
+        )]
+        template: String,
+    },
+    /// Disassemble bytecode into mnemonics accepted in builder files
+    Disasm {
+        /// Hex bytecode, or a file containing hex or raw bytecode
+        input: String,
+        #[clap(long, help = "Print as a `code` entry to paste into an [[alloc]]")]
+        toml: bool,
+    },
+    /// Trace generated scenarios in a loop and shrink the ones failing verification
+    Fuzz(fuzz::FuzzArgs),
+    /// Dump witnesses of existing blocks from an RPC endpoint
+    Mainnet {
+        /// RPC endpoint: an http(s) URL or an IPC socket path
+        rpc: String,
+        /// Block number, or an inclusive range `start-end`
+        blocks: String,
+        #[clap(default_value = "witness.json")]
+        out: PathBuf,
+        #[clap(long, default_value_t = 4, help = "Blocks requested at the same time")]
+        concurrency: usize,
+        #[clap(
+            long,
+            default_value_t = 5,
+            help = "Retries of a request failing in transport, with exponential backoff"
+        )]
+        retries: u32,
+    },
 }
 
 static ERROR: Emoji<'_, '_> = Emoji("❌  ", ":-( ");
-static TEMPLATE: &str = include_str!("../../../examples/full.toml");
+static TEMPLATES: &[(&str, &str)] = &[
+    ("full", include_str!("../../../examples/full.toml")),
+    ("minimal", include_str!("../../../examples/minimal.toml")),
+];
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
-    if args.new {
-        let mut file = File::create("spectre.toml")
-            .inspect_err(|e| {
-                eprintln!(
-                    "{ERROR}{}",
-                    style(format!("error creating file: {}", e)).bold()
-                );
-                exit(1);
-            })
-            .unwrap();
-        file.write_all(TEMPLATE.as_bytes())
+    match args.command {
+        Some(Command::New { out, template }) => return new(&out, &template),
+        Some(Command::Disasm { input, toml }) => return disasm(&input, toml),
+        Some(Command::Fuzz(args)) => return fuzz::run(args).await,
+        Some(Command::Mainnet {
+            rpc,
+            blocks,
+            out,
+            concurrency,
+            retries,
+        }) => {
+            let options = mainnet::DumpOptions {
+                concurrency,
+                retries,
+                ..Default::default()
+            };
+            return dump_mainnet(&rpc, &blocks, &out, options).await;
+        }
+        None => {}
+    }
+
+    let mut builder = load_builder(&args.builder);
+    builder.system.strict |= args.strict;
+
+    if args.expand {
+        let expanded = builder
+            .expand()
             .inspect_err(|e| {
                 eprintln!(
                     "{ERROR}{}",
-                    style(format!("error writing file: {}", e)).bold()
+                    style(format!("error expanding builder: {}", e)).bold()
                 );
                 exit(1);
             })
             .unwrap();
+        print!("{}", toml::to_string(&expanded).unwrap());
         return;
     }
 
-    let builder = read_to_string(&args.builder)
+    let spectre = builder
+        .build()
         .inspect_err(|e| {
             eprintln!(
                 "{ERROR}{}",
-                style(format!("error opening file: {}", e)).bold()
+                style(format!("error building spectre: {}", e)).bold()
             );
             exit(1);
         })
         .unwrap();
 
-    let builder: SpectreBuilder = toml::from_str(&builder)
+    if args.show_code {
+        eprintln!("{spectre:#}");
+    } else {
+        eprintln!("{spectre}");
+    }
+
+    let now = std::time::Instant::now();
+    let report = spectre
+        .trace()
+        .await
         .inspect_err(|e| {
             eprintln!(
                 "{ERROR}{}",
-                style(format!("error parsing builder: {}", e)).bold()
+                style(format!("error when dump witness: {}", e)).bold()
             );
             exit(1);
         })
         .unwrap();
 
-    let spectre = builder
-        .build()
+    eprintln!(
+        "{}witness dumped in {:?}",
+        Emoji("✨  ", ":-) "),
+        now.elapsed()
+    );
+
+    eprintln!("{report}");
+
+    if args.verify {
+        verify(&report);
+    }
+    write_witnesses(&args.out, &report.blocks);
+    write_traces(&args.out, &report.transactions);
+    if args.report {
+        let path = args.out.with_extension("report.json");
+        eprintln!("- report -> {}", path.display());
+        let file = File::create(&path)
+            .inspect_err(|e| {
+                eprintln!(
+                    "{ERROR}{}",
+                    style(format!("error creating file: {}", e)).bold()
+                );
+                exit(1);
+            })
+            .unwrap();
+        serde_json::to_writer_pretty(file, &report)
+            .inspect_err(|e| {
+                eprintln!(
+                    "{ERROR}{}",
+                    style(format!("error writing report: {}", e)).bold()
+                );
+                exit(1);
+            })
+            .unwrap();
+    }
+}
+
+async fn dump_mainnet(rpc: &str, blocks: &str, out: &Path, options: mainnet::DumpOptions) {
+    let blocks = mainnet::parse_block_range(blocks)
+        .inspect_err(|e| {
+            eprintln!("{ERROR}{}", style(e).bold());
+            exit(1);
+        })
+        .unwrap();
+    let provider = mainnet::connect(rpc)
+        .await
         .inspect_err(|e| {
             eprintln!(
                 "{ERROR}{}",
-                style(format!("error building spectre: {}", e)).bold()
+                style(format!("error connecting to {}: {}", rpc, e)).bold()
             );
             exit(1);
         })
         .unwrap();
 
-    eprintln!("{spectre}");
-
     let now = std::time::Instant::now();
-    let witnesses = spectre
-        .trace()
+    let witnesses = mainnet::dump_blocks(provider, blocks, options)
         .await
         .inspect_err(|e| {
             eprintln!(
@@ -99,8 +240,84 @@ async fn main() {
         Emoji("✨  ", ":-) "),
         now.elapsed()
     );
+    write_witnesses(out, &witnesses);
+}
+
+/// Verifies the witnesses of a trace, exiting if any block does not match.
+fn verify(report: &TraceReport) {
+    let now = std::time::Instant::now();
+    let verifications = report.verify();
+    let failed = verifications
+        .iter()
+        .filter(|verification| !verification.is_ok())
+        .collect::<Vec<_>>();
+    for verification in &failed {
+        eprintln!(
+            "{ERROR}{}",
+            style(format!(
+                "witness of block #{} does not verify:",
+                verification.number
+            ))
+            .bold()
+        );
+        for mismatch in &verification.mismatches {
+            eprintln!("- {}", style(mismatch).red());
+        }
+    }
+    if !failed.is_empty() {
+        exit(1);
+    }
+    eprintln!(
+        "{}{} witnesses verified in {:?}",
+        Emoji("🔍  ", ":-) "),
+        verifications.len(),
+        now.elapsed()
+    );
+}
 
-    let filename = args.out.file_name().unwrap().to_string_lossy();
+/// Where the `kind` trace of transaction `idx` is written, next to the witnesses at `out`.
+fn trace_path(out: &Path, idx: usize, hash: impl Display, kind: TraceKind) -> PathBuf {
+    let filename = out.file_name().unwrap().to_string_lossy();
+    out.with_file_name(format!("{filename}-tx{idx}-{hash}.{kind}.json"))
+}
+
+/// Writes the debug traces of each transaction next to `out`, named after its builder index and
+/// hash.
+fn write_traces(out: &Path, transactions: &[TracedTransaction]) {
+    for TracedTransaction {
+        idx,
+        outcome,
+        traces,
+    } in transactions
+    {
+        for (&kind, trace) in traces {
+            let path = trace_path(out, *idx, outcome.hash, kind);
+            eprintln!("- tx#{idx} {kind} -> {}", path.display());
+            let file = File::create(&path)
+                .inspect_err(|e| {
+                    eprintln!(
+                        "{ERROR}{}",
+                        style(format!("error creating file: {}", e)).bold()
+                    );
+                    exit(1);
+                })
+                .unwrap();
+            serde_json::to_writer_pretty(file, trace)
+                .inspect_err(|e| {
+                    eprintln!(
+                        "{ERROR}{}",
+                        style(format!("error writing debug trace: {}", e)).bold()
+                    );
+                    exit(1);
+                })
+                .unwrap();
+        }
+    }
+}
+
+/// Writes one witness to `out`, or one per block next to it when there are several.
+fn write_witnesses(out: &Path, witnesses: &[TracedBlock]) {
+    let filename = out.file_name().unwrap().to_string_lossy();
 
     let create_file = |path| {
         File::create(path)
@@ -126,16 +343,142 @@ async fn main() {
             .unwrap();
     };
 
+    let describe = |block: &TracedBlock| match block.hardfork {
+        Some(hardfork) => format!("#{} ({hardfork})", block.number),
+        None => format!("#{}", block.number),
+    };
+
     if witnesses.len() > 1 {
         eprintln!("{}more than one block used", Emoji("😮️  ", ":O "));
 
-        for (idx, witness) in witnesses.iter().enumerate() {
-            let path = args.out.with_file_name(format!("{filename}-{idx}"));
+        for (idx, block) in witnesses.iter().enumerate() {
+            let path = out.with_file_name(format!("{filename}-{idx}"));
+            eprintln!("- block {} -> {}", describe(block), path.display());
             let file = create_file(path);
-            write_witness(file, witness);
+            write_witness(file, &block.witness);
         }
     } else {
-        let file = create_file(args.out);
-        write_witness(file, &witnesses[0]);
+        eprintln!("- block {} -> {}", describe(&witnesses[0]), out.display());
+        let file = create_file(out.to_path_buf());
+        write_witness(file, &witnesses[0].witness);
+    }
+}
+
+fn new(out: &Path, template: &str) {
+    let template = TEMPLATES
+        .iter()
+        .find(|(name, _)| *name == template)
+        .map(|(_, template)| *template)
+        .or_else(|| template.parse::<Scenario>().ok().map(|s| s.template()))
+        .unwrap_or_else(|| {
+            eprintln!(
+                "{ERROR}{}",
+                style(format!("unknown template: {}", template)).bold()
+            );
+            exit(1);
+        });
+
+    let mut file = File::create(out)
+        .inspect_err(|e| {
+            eprintln!(
+                "{ERROR}{}",
+                style(format!("error creating file: {}", e)).bold()
+            );
+            exit(1);
+        })
+        .unwrap();
+    file.write_all(template.as_bytes())
+        .inspect_err(|e| {
+            eprintln!(
+                "{ERROR}{}",
+                style(format!("error writing file: {}", e)).bold()
+            );
+            exit(1);
+        })
+        .unwrap();
+}
+
+fn disasm(input: &str, toml: bool) {
+    let code = if Path::new(input).is_file() {
+        let content = read(input)
+            .inspect_err(|e| {
+                eprintln!(
+                    "{ERROR}{}",
+                    style(format!("error opening file: {}", e)).bold()
+                );
+                exit(1);
+            })
+            .unwrap();
+        std::str::from_utf8(&content)
+            .ok()
+            .and_then(|s| decode_hex(s).ok())
+            .unwrap_or(content)
+    } else {
+        decode_hex(input)
+            .inspect_err(|e| {
+                eprintln!(
+                    "{ERROR}{}",
+                    style(format!("error decoding hex: {}", e)).bold()
+                );
+                exit(1);
+            })
+            .unwrap()
+    };
+
+    let source = asm::disassemble(&code);
+    if toml {
+        print!("code = \"\"\"\n{source}\"\"\"\n");
+    } else {
+        print!("{source}");
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    let s = s.trim();
+    hex::decode(s.strip_prefix("0x").unwrap_or(s))
+}
+
+fn load_builder(path: &Path) -> SpectreBuilder {
+    let builder = read_to_string(path)
+        .inspect_err(|e| {
+            eprintln!(
+                "{ERROR}{}",
+                style(format!("error opening file: {}", e)).bold()
+            );
+            exit(1);
+        })
+        .unwrap();
+
+    let mut builder: SpectreBuilder = toml::from_str(&builder)
+        .inspect_err(|e| {
+            eprintln!(
+                "{ERROR}{}",
+                style(format!("error parsing builder: {}", e)).bold()
+            );
+            exit(1);
+        })
+        .unwrap();
+    builder.base_dir = path.parent().map(Path::to_path_buf);
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_path() {
+        let path = trace_path(
+            Path::new("out/witness.json"),
+            3,
+            "0xab",
+            TraceKind::CallTracer,
+        );
+        assert_eq!(
+            path,
+            Path::new("out/witness.json-tx3-0xab.call-tracer.json")
+        );
+        let path = trace_path(Path::new("w.json"), 0, "0xcd", TraceKind::StructLogs);
+        assert_eq!(path, Path::new("w.json-tx0-0xcd.struct-logs.json"));
     }
 }
@@ -0,0 +1,522 @@
+use alloy_dyn_abi::{DynSolType, DynSolValue, EventExt, JsonAbiExt, Specifier};
+use alloy_json_abi::{Function, JsonAbi, Param};
+use alloy_primitives::{Address, Bytes, LogData, U256, hex, keccak256};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArtifactError {
+    #[error("cannot read artifact {path:?}: {error}")]
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("invalid artifact {path:?}: {error}")]
+    Parse {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    #[error("invalid code in artifact {path:?}: {error}")]
+    InvalidCode {
+        path: PathBuf,
+        error: hex::FromHexError,
+    },
+    #[error("artifact {path:?} has unlinked library references")]
+    Unlinked { path: PathBuf },
+    #[error("artifact has no storage layout, build with `extra_output = [\"storageLayout\"]`")]
+    NoStorageLayout,
+    #[error("unknown storage variable {name}")]
+    UnknownVariable { name: String },
+    #[error(
+        "storage variable {name} of type {ty} cannot be set, only value types and mappings of them"
+    )]
+    UnsupportedVariable { name: String, ty: String },
+    #[error("unknown function {name}")]
+    UnknownFunction { name: String },
+    #[error("several functions are named {name}, give the full signature")]
+    AmbiguousFunction { name: String },
+    #[error("{name} takes {expected} arguments, got {got}")]
+    Arguments {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    #[error("invalid {ty} value {value:?}: {error}")]
+    InvalidValue {
+        value: String,
+        ty: String,
+        error: alloy_dyn_abi::Error,
+    },
+    #[error("ABI error: {0}")]
+    Abi(#[from] alloy_dyn_abi::Error),
+}
+
+/// A compiled contract, from a Foundry or Hardhat artifact.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub abi: JsonAbi,
+    /// Init code, the constructor arguments go after it.
+    pub bytecode: Bytes,
+    pub deployed_bytecode: Bytes,
+    /// Only in Foundry artifacts built with the `storageLayout` extra output.
+    pub storage_layout: Option<StorageLayout>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawArtifact {
+    abi: JsonAbi,
+    bytecode: RawBytecode,
+    deployed_bytecode: RawBytecode,
+    #[serde(default)]
+    storage_layout: Option<StorageLayout>,
+}
+
+/// Foundry nests the code in an object, Hardhat gives it as is.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum RawBytecode {
+    Foundry { object: String },
+    Hardhat(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageLayout {
+    pub storage: Vec<StorageVariable>,
+    #[serde(default)]
+    pub types: Option<HashMap<String, StorageType>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageVariable {
+    pub label: String,
+    pub slot: U256,
+    /// Byte offset inside the slot, for variables packed together.
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageType {
+    /// `inplace`, `mapping`, `dynamic_array` or `bytes`.
+    pub encoding: String,
+    pub label: String,
+    pub number_of_bytes: U256,
+    /// Key and value types of a mapping.
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Set for structs.
+    #[serde(default)]
+    pub members: Option<Vec<StorageVariable>>,
+}
+
+impl Artifact {
+    pub fn load(path: &Path) -> Result<Self, ArtifactError> {
+        let text = std::fs::read_to_string(path).map_err(|error| ArtifactError::Read {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let raw: RawArtifact =
+            serde_json::from_str(&text).map_err(|error| ArtifactError::Parse {
+                path: path.to_path_buf(),
+                error,
+            })?;
+        let code = |raw: RawBytecode| {
+            let (RawBytecode::Foundry { object } | RawBytecode::Hardhat(object)) = raw;
+            // libraries to link are left as `__$hash$__` placeholders
+            if object.contains("__") {
+                return Err(ArtifactError::Unlinked {
+                    path: path.to_path_buf(),
+                });
+            }
+            Bytes::from_str(&object).map_err(|error| ArtifactError::InvalidCode {
+                path: path.to_path_buf(),
+                error,
+            })
+        };
+        Ok(Artifact {
+            abi: raw.abi,
+            bytecode: code(raw.bytecode)?,
+            deployed_bytecode: code(raw.deployed_bytecode)?,
+            storage_layout: raw.storage_layout,
+        })
+    }
+
+    /// Writes storage variables into `storage`, keeping the other variables packed in their slots.
+    ///
+    /// Variables are named as declared, with `[key]` for each mapping level, e.g.
+    /// `balanceOf[alice]`. Address values and keys may be wallet or alloc names.
+    pub fn write_variables(
+        &self,
+        variables: &BTreeMap<String, String>,
+        names: &HashMap<String, Address>,
+        storage: &mut BTreeMap<U256, U256>,
+    ) -> Result<(), ArtifactError> {
+        let layout = self
+            .storage_layout
+            .as_ref()
+            .ok_or(ArtifactError::NoStorageLayout)?;
+        let no_types = HashMap::new();
+        let types = layout.types.as_ref().unwrap_or(&no_types);
+        for (name, value) in variables {
+            let unsupported = |ty: &str| ArtifactError::UnsupportedVariable {
+                name: name.clone(),
+                ty: ty.to_string(),
+            };
+            let (label, keys) = match name.split_once('[') {
+                Some((label, keys)) => (label, Some(keys)),
+                None => (name.as_str(), None),
+            };
+            let variable = layout
+                .storage
+                .iter()
+                .find(|variable| variable.label == label)
+                .ok_or_else(|| ArtifactError::UnknownVariable { name: name.clone() })?;
+
+            let (mut slot, mut offset, mut ty) = (variable.slot, variable.offset, &variable.ty);
+            let keys = keys.map(|keys| keys.trim_end_matches(']').split("]["));
+            for key in keys.into_iter().flatten() {
+                let mapping = types.get(ty).ok_or_else(|| unsupported(ty))?;
+                let (Some(key_type), Some(value_type)) = (&mapping.key, &mapping.value) else {
+                    return Err(unsupported(&mapping.label));
+                };
+                let key_type = types
+                    .get(key_type)
+                    .and_then(value_type_of)
+                    .ok_or_else(|| unsupported(&mapping.label))?;
+                let key = coerce(&key_type, key, names)?.abi_encode();
+                // solc keeps `mapping[key]` at keccak(key . slot)
+                slot = U256::from_be_bytes(keccak256([key, slot.to_be_bytes_vec()].concat()).0);
+                offset = 0;
+                ty = value_type;
+            }
+
+            let storage_type = types.get(ty).ok_or_else(|| unsupported(ty))?;
+            let value_type =
+                value_type_of(storage_type).ok_or_else(|| unsupported(&storage_type.label))?;
+            let size = storage_type
+                .number_of_bytes
+                .saturating_to::<usize>()
+                .min(32);
+            let word = coerce(&value_type, value, names)?.abi_encode();
+            // fixed bytes are left aligned in ABI encoding, every other value type right aligned
+            let bytes = match value_type {
+                DynSolType::FixedBytes(_) => &word[..size],
+                _ => &word[32 - size..],
+            };
+            let mask = (U256::MAX >> (256 - size * 8)) << (offset * 8);
+            let packed = U256::from_be_slice(bytes) << (offset * 8);
+            let current = storage.entry(slot).or_default();
+            *current = (*current & !mask) | packed;
+        }
+        Ok(())
+    }
+}
+
+/// The ABI type of a storage type holding a single value, `None` for anything else.
+fn value_type_of(ty: &StorageType) -> Option<DynSolType> {
+    if ty.encoding != "inplace" || ty.members.is_some() {
+        return None;
+    }
+    if ty.label.starts_with("contract ") || ty.label == "address payable" {
+        return Some(DynSolType::Address);
+    }
+    if ty.label.starts_with("enum ") {
+        return Some(DynSolType::Uint(
+            ty.number_of_bytes.saturating_to::<usize>() * 8,
+        ));
+    }
+    DynSolType::parse(&ty.label).ok().filter(|ty| {
+        matches!(
+            ty,
+            DynSolType::Address
+                | DynSolType::Bool
+                | DynSolType::Int(_)
+                | DynSolType::Uint(_)
+                | DynSolType::FixedBytes(_)
+        )
+    })
+}
+
+/// Parses a value of `ty` from its string, taking names for addresses.
+fn coerce(
+    ty: &DynSolType,
+    value: &str,
+    names: &HashMap<String, Address>,
+) -> Result<DynSolValue, ArtifactError> {
+    if let (DynSolType::Address, Some(address)) = (ty, names.get(value)) {
+        return Ok(DynSolValue::Address(*address));
+    }
+    ty.coerce_str(value)
+        .map_err(|error| ArtifactError::InvalidValue {
+            value: value.to_string(),
+            ty: ty.sol_type_name().into_owned(),
+            error,
+        })
+}
+
+fn encode_args(
+    name: &str,
+    params: &[Param],
+    args: &[String],
+    names: &HashMap<String, Address>,
+) -> Result<Vec<DynSolValue>, ArtifactError> {
+    if params.len() != args.len() {
+        return Err(ArtifactError::Arguments {
+            name: name.to_string(),
+            expected: params.len(),
+            got: args.len(),
+        });
+    }
+    params
+        .iter()
+        .zip(args)
+        .map(|(param, arg)| {
+            let ty = param.resolve()?;
+            coerce(&ty, arg, names)
+        })
+        .collect()
+}
+
+/// Calldata of `call`, a function name or, for overloaded ones, a full signature such as
+/// `transfer(address,uint256)`.
+pub fn encode_call(
+    abi: &JsonAbi,
+    call: &str,
+    args: &[String],
+    names: &HashMap<String, Address>,
+) -> Result<Bytes, ArtifactError> {
+    let call = call.split_whitespace().collect::<String>();
+    let functions: Vec<&Function> = if call.contains('(') {
+        abi.functions()
+            .filter(|function| function.signature() == call)
+            .collect()
+    } else {
+        abi.function(&call)
+            .map(|functions| functions.iter().collect())
+            .unwrap_or_default()
+    };
+    let function = match functions[..] {
+        [function] => function,
+        [] => return Err(ArtifactError::UnknownFunction { name: call }),
+        _ => return Err(ArtifactError::AmbiguousFunction { name: call }),
+    };
+    let values = encode_args(&call, &function.inputs, args, names)?;
+    Ok(function.abi_encode_input(&values)?.into())
+}
+
+/// Init code of the artifact followed by the encoded constructor arguments.
+pub fn encode_deployment(
+    artifact: &Artifact,
+    args: &[String],
+    names: &HashMap<String, Address>,
+) -> Result<Bytes, ArtifactError> {
+    let encoded = match artifact.abi.constructor() {
+        Some(constructor) => {
+            let values = encode_args("constructor", &constructor.inputs, args, names)?;
+            constructor.abi_encode_input(&values)?
+        }
+        None => encode_args("constructor", &[], args, names).map(|_| vec![])?,
+    };
+    Ok([artifact.bytecode.to_vec(), encoded].concat().into())
+}
+
+/// Describes a log emitted by a contract with this ABI, e.g. `Transfer(from: 0x.., to: 0x.., value: 1)`.
+pub fn decode_log(abi: &JsonAbi, log: &LogData) -> Option<String> {
+    let selector = log.topics().first()?;
+    let event = abi
+        .events()
+        .find(|event| !event.anonymous && event.selector() == *selector)?;
+    let decoded = event.decode_log(log, true).ok()?;
+    let (mut indexed, mut body) = (decoded.indexed.iter(), decoded.body.iter());
+    let values = event
+        .inputs
+        .iter()
+        .map(|input| {
+            let value = if input.indexed {
+                indexed.next()
+            } else {
+                body.next()
+            };
+            let value = value.map(format_value).unwrap_or_default();
+            format!("{}: {value}", input.name)
+        })
+        .collect::<Vec<_>>();
+    Some(format!("{}({})", event.name, values.join(", ")))
+}
+
+fn format_value(value: &DynSolValue) -> String {
+    let join = |values: &[DynSolValue]| {
+        values
+            .iter()
+            .map(format_value)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match value {
+        DynSolValue::Address(address) => address.to_string(),
+        DynSolValue::Bool(value) => value.to_string(),
+        DynSolValue::Int(value, _) => value.to_string(),
+        DynSolValue::Uint(value, _) => value.to_string(),
+        DynSolValue::FixedBytes(word, size) => hex::encode_prefixed(&word[..*size]),
+        DynSolValue::Bytes(bytes) => hex::encode_prefixed(bytes),
+        DynSolValue::String(value) => format!("{value:?}"),
+        DynSolValue::Array(values) | DynSolValue::FixedArray(values) => {
+            format!("[{}]", join(values))
+        }
+        DynSolValue::Tuple(values) => format!("({})", join(values)),
+        value => format!("{value:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::B256;
+
+    const ARTIFACT: &str = r#"{
+        "abi": [
+            {"type": "constructor", "inputs": [{"name": "supply", "type": "uint256"}], "stateMutability": "nonpayable"},
+            {"type": "function", "name": "transfer", "inputs": [{"name": "to", "type": "address"}, {"name": "amount", "type": "uint256"}], "outputs": [{"name": "", "type": "bool"}], "stateMutability": "nonpayable"},
+            {"type": "function", "name": "mint", "inputs": [{"name": "amount", "type": "uint256"}], "outputs": [], "stateMutability": "nonpayable"},
+            {"type": "function", "name": "mint", "inputs": [{"name": "to", "type": "address"}, {"name": "amount", "type": "uint256"}], "outputs": [], "stateMutability": "nonpayable"},
+            {"type": "event", "name": "Transfer", "inputs": [{"name": "from", "type": "address", "indexed": true}, {"name": "to", "type": "address", "indexed": true}, {"name": "value", "type": "uint256", "indexed": false}], "anonymous": false}
+        ],
+        "bytecode": {"object": "0x6080", "sourceMap": "", "linkReferences": {}},
+        "deployedBytecode": {"object": "0x00", "sourceMap": "", "linkReferences": {}},
+        "storageLayout": {
+            "storage": [
+                {"astId": 1, "contract": "Token", "label": "balanceOf", "offset": 0, "slot": "0", "type": "t_mapping(t_address,t_uint256)"},
+                {"astId": 2, "contract": "Token", "label": "owner", "offset": 0, "slot": "1", "type": "t_address"},
+                {"astId": 3, "contract": "Token", "label": "paused", "offset": 20, "slot": "1", "type": "t_bool"},
+                {"astId": 4, "contract": "Token", "label": "name", "offset": 0, "slot": "2", "type": "t_string_storage"}
+            ],
+            "types": {
+                "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+                "t_bool": {"encoding": "inplace", "label": "bool", "numberOfBytes": "1"},
+                "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"},
+                "t_string_storage": {"encoding": "bytes", "label": "string", "numberOfBytes": "32"},
+                "t_mapping(t_address,t_uint256)": {"encoding": "mapping", "key": "t_address", "label": "mapping(address => uint256)", "numberOfBytes": "32", "value": "t_uint256"}
+            }
+        }
+    }"#;
+
+    fn load(content: &str, file: &str) -> Result<Artifact, ArtifactError> {
+        let dir = std::env::temp_dir().join(format!("spectre-artifact-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(file), content).unwrap();
+        let artifact = Artifact::load(&dir.join(file));
+        std::fs::remove_file(dir.join(file)).unwrap();
+        artifact
+    }
+
+    #[test]
+    fn test_artifact() {
+        let artifact = load(ARTIFACT, "Token.json").unwrap();
+        assert_eq!(artifact.bytecode[..], [0x60, 0x80]);
+        assert_eq!(artifact.deployed_bytecode[..], [0x00]);
+
+        let alice = Address::repeat_byte(0x11);
+        let bob = Address::repeat_byte(0x22);
+        let names = HashMap::from([("alice".to_string(), alice)]);
+        let word = |value: U256| B256::from(value).to_vec();
+        let address = |address: Address| B256::left_padding_from(address.as_slice()).to_vec();
+
+        let args = ["alice".to_string(), "5".to_string()];
+        assert_eq!(
+            encode_call(&artifact.abi, "transfer", &args, &names).unwrap()[..],
+            [
+                vec![0xa9, 0x05, 0x9c, 0xbb],
+                address(alice),
+                word(U256::from(5))
+            ]
+            .concat()
+        );
+        assert!(matches!(
+            encode_call(&artifact.abi, "mint", &args, &names),
+            Err(ArtifactError::AmbiguousFunction { .. })
+        ));
+        let mint = encode_call(&artifact.abi, "mint(address, uint256)", &args, &names).unwrap();
+        assert_eq!(mint[..4], keccak256("mint(address,uint256)")[..4]);
+        assert!(matches!(
+            encode_call(&artifact.abi, "transfer", &args[..1], &names),
+            Err(ArtifactError::Arguments {
+                expected: 2,
+                got: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            encode_call(&artifact.abi, "burn", &[], &names),
+            Err(ArtifactError::UnknownFunction { .. })
+        ));
+        assert_eq!(
+            encode_deployment(&artifact, &["1000".to_string()], &names).unwrap()[..],
+            [vec![0x60, 0x80], word(U256::from(1000))].concat()
+        );
+
+        let variables = BTreeMap::from([
+            ("owner".to_string(), "alice".to_string()),
+            ("paused".to_string(), "true".to_string()),
+            (format!("balanceOf[{bob}]"), "7".to_string()),
+        ]);
+        let mut storage = BTreeMap::from([(U256::from(1), U256::MAX)]);
+        artifact
+            .write_variables(&variables, &names, &mut storage)
+            .unwrap();
+        let balance_slot = keccak256([address(bob), word(U256::ZERO)].concat());
+        assert_eq!(
+            storage,
+            BTreeMap::from([
+                (
+                    U256::from(1),
+                    // bytes past `paused` keep their value
+                    (U256::MAX << 168)
+                        | (U256::from(1) << 160)
+                        | U256::from_be_slice(alice.as_slice())
+                ),
+                (U256::from_be_bytes(balance_slot.0), U256::from(7)),
+            ])
+        );
+        let variables = BTreeMap::from([("name".to_string(), "spectre".to_string())]);
+        assert!(matches!(
+            artifact.write_variables(&variables, &names, &mut storage),
+            Err(ArtifactError::UnsupportedVariable { .. })
+        ));
+
+        let log = LogData::new_unchecked(
+            vec![
+                keccak256("Transfer(address,address,uint256)"),
+                B256::left_padding_from(alice.as_slice()),
+                B256::left_padding_from(bob.as_slice()),
+            ],
+            word(U256::from(5)).into(),
+        );
+        assert_eq!(
+            decode_log(&artifact.abi, &log).unwrap(),
+            format!("Transfer(from: {alice}, to: {bob}, value: 5)")
+        );
+
+        // hardhat keeps the code as a plain string, without a storage layout
+        let hardhat = r#"{"abi": [], "bytecode": "0x6080", "deployedBytecode": "0x00"}"#;
+        let artifact = load(hardhat, "Hardhat.json").unwrap();
+        assert_eq!(artifact.bytecode[..], [0x60, 0x80]);
+        assert!(matches!(
+            artifact.write_variables(&variables, &names, &mut storage),
+            Err(ArtifactError::NoStorageLayout)
+        ));
+        let unlinked = r#"{"abi": [], "bytecode": "0x73__$f0e1d2$__", "deployedBytecode": "0x00"}"#;
+        assert!(matches!(
+            load(unlinked, "Unlinked.json"),
+            Err(ArtifactError::Unlinked { .. })
+        ));
+    }
+}
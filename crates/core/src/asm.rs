@@ -0,0 +1,1050 @@
+use crate::opcode::OpCode;
+use alloy_primitives::{
+    Bytes, U256,
+    bytes::{BufMut, BytesMut},
+    hex, ruint,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Nesting limit of includes and macro invocations.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CompileError {
+    #[error("invalid opcode {opcode} at {line}")]
+    InvalidOpcode { opcode: String, line: Line },
+    #[error("missing value for opcode {opcode} at {line}")]
+    MissingValue { opcode: String, line: Line },
+    #[error("invalid push value at {line}: {error}")]
+    InvalidPushValue {
+        line: Line,
+        error: ruint::ParseError,
+    },
+    #[error("invalid push value length at {line}: expected {expected}, got {length}")]
+    InvalidPushValueLength {
+        line: Line,
+        expected: usize,
+        length: usize,
+    },
+    #[error("undefined label @{label} at {line}")]
+    UndefinedLabel { label: String, line: Line },
+    #[error("duplicate label @{label} at {line}, first defined at {first}")]
+    DuplicateLabel {
+        label: String,
+        line: Line,
+        first: Line,
+    },
+    #[error("invalid directive {directive} at {line}")]
+    InvalidDirective { directive: String, line: Line },
+    #[error("macro {name} at {line} is never closed with %end")]
+    UnclosedMacro { name: String, line: Line },
+    #[error("macro {name} takes {expected} arguments, got {got} at {line}")]
+    MacroArguments {
+        name: String,
+        expected: usize,
+        got: usize,
+        line: Line,
+    },
+    #[error("includes or macros nested too deep at {line}")]
+    TooDeep { line: Line },
+    #[error("cannot include {path:?} at {line}: {error}")]
+    Include {
+        path: PathBuf,
+        line: Line,
+        error: std::io::Error,
+    },
+    #[error("invalid data at {line}: {error}")]
+    InvalidData { line: Line, error: String },
+}
+
+/// A line of source, in the code itself or an included file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub file: Option<PathBuf>,
+    pub number: usize,
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "line {} of {}", self.number, file.display()),
+            None => write!(f, "line {}", self.number),
+        }
+    }
+}
+
+/// The bytes one line assembled into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub offset: usize,
+    pub len: usize,
+    pub line: Line,
+    /// Emitted by `DATA` or `STRING`, so never meant to run.
+    pub data: bool,
+}
+
+/// Maps assembled code back to the mnemonic it came from, see [`compile_mnemonic_mapped`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// In code order, a line expanded from a macro has one span per invocation.
+    spans: Vec<Span>,
+    /// The original text of the code, `None`, and of every included file.
+    sources: HashMap<Option<PathBuf>, Vec<String>>,
+}
+
+impl SourceMap {
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// The line the byte at `pc` was assembled from.
+    pub fn line(&self, pc: usize) -> Option<&Line> {
+        let idx = self
+            .spans
+            .partition_point(|span| span.offset + span.len <= pc);
+        self.spans
+            .get(idx)
+            .filter(|span| span.offset <= pc)
+            .map(|span| &span.line)
+    }
+
+    /// The byte ranges a line assembled into, several for a line of a macro used more than once.
+    pub fn pcs(&self, line: &Line) -> Vec<Range<usize>> {
+        self.spans
+            .iter()
+            .filter(|span| &span.line == line && span.len > 0)
+            .map(|span| span.offset..span.offset + span.len)
+            .collect()
+    }
+
+    /// The line as written, comment included.
+    pub fn text(&self, line: &Line) -> Option<&str> {
+        let lines = self.sources.get(&line.file)?;
+        lines
+            .get(line.number.checked_sub(1)?)
+            .map(|text| text.trim())
+    }
+
+    /// Describes `pc` by its line, e.g. `line 3: PUSH1 0x80 // argsSize`, to annotate traces.
+    pub fn annotate(&self, pc: usize) -> Option<String> {
+        let line = self.line(pc)?;
+        Some(match self.text(line) {
+            Some(text) => format!("{line}: {text}"),
+            None => line.to_string(),
+        })
+    }
+}
+
+/// An assembled line, before labels are resolved.
+enum Instruction<'a> {
+    /// `@label:`, emits a `JUMPDEST`.
+    Label(&'a str),
+    Op(u8),
+    /// `PUSHn value`, or `PUSH value` with the width picked from the value.
+    Push(usize, U256),
+    /// `PUSHn @label`, or `PUSH @label` when the width is `None`.
+    PushLabel(Option<usize>, &'a str),
+    /// `DATA 0x...` or `STRING "..."`, emitted as is.
+    Data(Vec<u8>),
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<(Line, String)>,
+}
+
+/// Expands directives into plain lines, keeping where each line came from.
+#[derive(Default)]
+struct Preprocessor {
+    defines: HashMap<String, String>,
+    macros: HashMap<String, Macro>,
+    /// Numbers macro invocations, to keep their local labels apart.
+    invocations: usize,
+    lines: Vec<(Line, String)>,
+    sources: HashMap<Option<PathBuf>, Vec<String>>,
+}
+
+/// Assembles one mnemonic per line, `//` starts a comment.
+///
+/// Labels are defined with `@name:`, which emits a `JUMPDEST`, and referenced with `PUSHn @name`.
+/// A bare `PUSH` picks the smallest width fitting its value or label offset. `DATA 0x...` and
+/// `STRING "..."` embed raw bytes.
+///
+/// Directives:
+/// - `%define NAME value` replaces the token `NAME` in the following lines.
+/// - `%macro name a, b` up to `%end` defines a macro, invoked as `name 0x01, 0x02`. Labels
+///   starting with `@.` are local to each invocation.
+/// - `%include "file.asm"` pastes a file, relative to the including file.
+///
+/// Includes in the code itself are relative to the working directory, see
+/// [`compile_mnemonic_in`].
+pub fn compile_mnemonic(codes: &str) -> Result<Bytes, CompileError> {
+    compile_mnemonic_in(codes, Path::new(""))
+}
+
+/// Same as [`compile_mnemonic`], with includes in the code relative to `base_dir`.
+pub fn compile_mnemonic_in(codes: &str, base_dir: &Path) -> Result<Bytes, CompileError> {
+    compile_mnemonic_mapped(codes, base_dir).map(|(code, _)| code)
+}
+
+/// Same as [`compile_mnemonic_in`], also returning the [`SourceMap`] from program counters to
+/// lines.
+pub fn compile_mnemonic_mapped(
+    codes: &str,
+    base_dir: &Path,
+) -> Result<(Bytes, SourceMap), CompileError> {
+    compile(codes, None, base_dir)
+}
+
+/// Same as [`compile_mnemonic_mapped`], for code read from `path`: lines are reported in that file
+/// and includes are relative to it.
+pub fn compile_mnemonic_file(codes: &str, path: &Path) -> Result<(Bytes, SourceMap), CompileError> {
+    compile(codes, Some(path), path.parent().unwrap_or(Path::new("")))
+}
+
+fn compile(
+    codes: &str,
+    file: Option<&Path>,
+    dir: &Path,
+) -> Result<(Bytes, SourceMap), CompileError> {
+    let mut preprocessor = Preprocessor::default();
+    preprocessor.source(codes, file, dir, 0)?;
+    let (code, spans) = assemble(&preprocessor.lines)?;
+    let source_map = SourceMap {
+        spans,
+        sources: preprocessor.sources,
+    };
+    Ok((code, source_map))
+}
+
+impl Preprocessor {
+    fn source(
+        &mut self,
+        source: &str,
+        file: Option<&Path>,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), CompileError> {
+        self.sources.insert(
+            file.map(Path::to_path_buf),
+            source.split('\n').map(str::to_string).collect(),
+        );
+        let mut open: Option<(String, Line, Macro)> = None;
+        for (idx, text) in source.split('\n').enumerate() {
+            let line = Line {
+                file: file.map(Path::to_path_buf),
+                number: idx + 1,
+            };
+            let text = strip_comment(text).trim();
+            if text.is_empty() {
+                continue;
+            }
+            if let Some((name, start, mut definition)) = open.take() {
+                if text == "%end" {
+                    self.macros.insert(name, definition);
+                } else {
+                    definition.body.push((line, text.to_string()));
+                    open = Some((name, start, definition));
+                }
+                continue;
+            }
+
+            let (directive, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            let rest = rest.trim();
+            match directive {
+                "%define" => {
+                    let Some((name, value)) = rest.split_once(char::is_whitespace) else {
+                        return Err(CompileError::InvalidDirective {
+                            directive: text.to_string(),
+                            line,
+                        });
+                    };
+                    let value = self.substitute(value.trim(), &HashMap::new());
+                    self.defines.insert(name.to_string(), value);
+                }
+                "%macro" => {
+                    let (name, params) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    if name.is_empty() {
+                        return Err(CompileError::InvalidDirective {
+                            directive: text.to_string(),
+                            line,
+                        });
+                    }
+                    let definition = Macro {
+                        params: split_args(params),
+                        body: vec![],
+                    };
+                    open = Some((name.to_string(), line, definition));
+                }
+                "%include" => {
+                    if depth >= MAX_DEPTH {
+                        return Err(CompileError::TooDeep { line });
+                    }
+                    let path = dir.join(parse_string(rest).map_err(|error| {
+                        CompileError::InvalidData {
+                            line: line.clone(),
+                            error,
+                        }
+                    })?);
+                    let included =
+                        std::fs::read_to_string(&path).map_err(|error| CompileError::Include {
+                            path: path.clone(),
+                            line: line.clone(),
+                            error,
+                        })?;
+                    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                    self.source(&included, Some(&path), &dir, depth + 1)?;
+                }
+                _ if directive.starts_with('%') => {
+                    return Err(CompileError::InvalidDirective {
+                        directive: directive.to_string(),
+                        line,
+                    });
+                }
+                _ => self.line(line, text, &HashMap::new(), depth)?,
+            }
+        }
+        match open {
+            Some((name, line, _)) => Err(CompileError::UnclosedMacro { name, line }),
+            None => Ok(()),
+        }
+    }
+
+    /// Substitutes defines and arguments, then expands a macro invocation or keeps the line.
+    fn line(
+        &mut self,
+        line: Line,
+        text: &str,
+        args: &HashMap<String, String>,
+        depth: usize,
+    ) -> Result<(), CompileError> {
+        if text.split_whitespace().next() == Some("STRING") {
+            self.lines.push((line, text.to_string()));
+            return Ok(());
+        }
+        let text = self.substitute(text, args);
+        let (name, rest) = text.split_once(char::is_whitespace).unwrap_or((&text, ""));
+        if name.starts_with('%') {
+            return Err(CompileError::InvalidDirective {
+                directive: name.to_string(),
+                line,
+            });
+        }
+        let Some(definition) = self.macros.get(name) else {
+            self.lines.push((line, text));
+            return Ok(());
+        };
+
+        let values = split_args(rest);
+        if values.len() != definition.params.len() {
+            return Err(CompileError::MacroArguments {
+                name: name.to_string(),
+                expected: definition.params.len(),
+                got: values.len(),
+                line,
+            });
+        }
+        if depth >= MAX_DEPTH {
+            return Err(CompileError::TooDeep { line });
+        }
+        let args = definition
+            .params
+            .iter()
+            .cloned()
+            .zip(values)
+            .collect::<HashMap<_, _>>();
+        let body = definition.body.clone();
+        self.invocations += 1;
+        let suffix = format!(".{}", self.invocations);
+        for (line, text) in body {
+            let text = localize_labels(&text, &suffix);
+            self.line(line, &text, &args, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn substitute(&self, text: &str, args: &HashMap<String, String>) -> String {
+        text.split_whitespace()
+            .map(|token| {
+                let (token, comma) = match token.strip_suffix(',') {
+                    Some(token) => (token, ","),
+                    None => (token, ""),
+                };
+                let value = args
+                    .get(token)
+                    .or_else(|| self.defines.get(token))
+                    .map(String::as_str)
+                    .unwrap_or(token);
+                format!("{value}{comma}")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Gives `@.name` labels in a macro body a per-invocation suffix.
+fn localize_labels(text: &str, suffix: &str) -> String {
+    text.split_whitespace()
+        .map(|token| match token.strip_prefix("@.") {
+            Some(label) => match label.strip_suffix(':') {
+                Some(label) => format!("@.{label}{suffix}:"),
+                None => format!("@.{label}{suffix}"),
+            },
+            None => token.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn split_args(args: &str) -> Vec<String> {
+    args.split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Cuts a `//` comment, unless it is inside a string.
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '/' if !quoted && text[idx..].starts_with("//") => return &text[..idx],
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Parses a double-quoted string with `\n`, `\t`, `\0`, `\"`, `\\` and `\xNN` escapes.
+fn parse_string(text: &str) -> Result<String, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, got {text}"))?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('0') => out.push('\0'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('x') => {
+                let code = chars.by_ref().take(2).collect::<String>();
+                let byte = u8::from_str_radix(&code, 16)
+                    .map_err(|_| format!("invalid escape \\x{code}"))?;
+                out.push(char::from(byte));
+            }
+            other => return Err(format!("invalid escape \\{}", other.unwrap_or(' '))),
+        }
+    }
+    Ok(out)
+}
+
+fn assemble(lines: &[(Line, String)]) -> Result<(Bytes, Vec<Span>), CompileError> {
+    let mut instructions = vec![];
+    let mut labels: HashMap<&str, &Line> = HashMap::new();
+    for (line, text) in lines {
+        let (token, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+        if let Some(label) = token.strip_prefix('@').and_then(|t| t.strip_suffix(':')) {
+            if let Some(first) = labels.insert(label, line) {
+                return Err(CompileError::DuplicateLabel {
+                    label: label.to_string(),
+                    line: line.clone(),
+                    first: first.clone(),
+                });
+            }
+            instructions.push((line, Instruction::Label(label)));
+            continue;
+        }
+
+        let opcode = token.to_ascii_uppercase();
+        let invalid_data = |error: String| CompileError::InvalidData {
+            line: line.clone(),
+            error,
+        };
+        let width = match opcode.as_str() {
+            "DATA" => {
+                let data = rest.strip_prefix("0x").unwrap_or(rest);
+                let data = hex::decode(data).map_err(|e| invalid_data(e.to_string()))?;
+                instructions.push((line, Instruction::Data(data)));
+                continue;
+            }
+            "STRING" => {
+                let string = parse_string(rest).map_err(invalid_data)?;
+                instructions.push((line, Instruction::Data(string.into_bytes())));
+                continue;
+            }
+            "PUSH" => None,
+            _ => {
+                let Some(op) = OpCode::from_mnemonic(&opcode) else {
+                    return Err(CompileError::InvalidOpcode {
+                        opcode,
+                        line: line.clone(),
+                    });
+                };
+                if op.immediate == 0 {
+                    instructions.push((line, Instruction::Op(op.byte)));
+                    continue;
+                }
+                Some(op.immediate as usize)
+            }
+        };
+        let value = rest
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| CompileError::MissingValue {
+                opcode: opcode.clone(),
+                line: line.clone(),
+            })?;
+        if let Some(label) = value.strip_prefix('@') {
+            instructions.push((line, Instruction::PushLabel(width, label)));
+            continue;
+        }
+        let value = U256::from_str(value).map_err(|e| CompileError::InvalidPushValue {
+            line: line.clone(),
+            error: e,
+        })?;
+        let length = push_width(value);
+        let width = width.unwrap_or(length);
+        if length > width {
+            return Err(CompileError::InvalidPushValueLength {
+                line: line.clone(),
+                expected: width,
+                length,
+            });
+        }
+        instructions.push((line, Instruction::Push(width, value)));
+    }
+
+    for (line, instruction) in &instructions {
+        if let Instruction::PushLabel(_, label) = instruction
+            && !labels.contains_key(label)
+        {
+            return Err(CompileError::UndefinedLabel {
+                label: label.to_string(),
+                line: (*line).clone(),
+            });
+        }
+    }
+
+    // widths of bare label pushes only grow, so this settles after a few rounds
+    let mut widths = vec![1; instructions.len()];
+    let offsets = loop {
+        let mut offset = 0;
+        let mut offsets = HashMap::new();
+        for ((_, instruction), width) in instructions.iter().zip(&widths) {
+            offset += match instruction {
+                Instruction::Label(label) => {
+                    offsets.insert(*label, offset);
+                    1
+                }
+                Instruction::Op(_) => 1,
+                Instruction::Push(width, _) => 1 + width,
+                Instruction::PushLabel(explicit, _) => 1 + explicit.unwrap_or(*width),
+                Instruction::Data(data) => data.len(),
+            };
+        }
+        let mut settled = true;
+        for ((_, instruction), width) in instructions.iter().zip(widths.iter_mut()) {
+            if let Instruction::PushLabel(None, label) = instruction {
+                let needed = push_width(U256::from(offsets[label]));
+                if needed > *width {
+                    *width = needed;
+                    settled = false;
+                }
+            }
+        }
+        if settled {
+            break offsets;
+        }
+    };
+
+    let mut code = BytesMut::new();
+    let mut spans = Vec::with_capacity(instructions.len());
+    for ((line, instruction), width) in instructions.iter().zip(&widths) {
+        let offset = code.len();
+        match instruction {
+            Instruction::Label(_) => code.put_u8(0x5b),
+            Instruction::Op(byte) => code.put_u8(*byte),
+            Instruction::Data(data) => code.put_slice(data),
+            Instruction::Push(width, value) => put_push(&mut code, *width, *value),
+            Instruction::PushLabel(explicit, label) => {
+                let value = U256::from(offsets[label]);
+                let width = explicit.unwrap_or(*width);
+                let length = push_width(value);
+                if length > width {
+                    return Err(CompileError::InvalidPushValueLength {
+                        line: (*line).clone(),
+                        expected: width,
+                        length,
+                    });
+                }
+                put_push(&mut code, width, value);
+            }
+        }
+        spans.push(Span {
+            offset,
+            len: code.len() - offset,
+            line: (*line).clone(),
+            data: matches!(instruction, Instruction::Data(_)),
+        });
+    }
+    Ok((Bytes::from(code.freeze()), spans))
+}
+
+fn put_push(code: &mut BytesMut, width: usize, value: U256) {
+    code.put_u8(0x5f + width as u8);
+    let value: [u8; 32] = value.to_be_bytes();
+    code.extend(&value[32 - width..]);
+}
+
+/// Smallest `PUSHn` width holding the value, at least one byte.
+fn push_width(value: U256) -> usize {
+    value.byte_len().max(1)
+}
+
+/// A decoded piece of bytecode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Op {
+        offset: usize,
+        opcode: &'static OpCode,
+        /// Immediate bytes of `PUSHn`, cut short if the code ends early.
+        immediate: Bytes,
+    },
+    /// A byte that is not a defined opcode.
+    Invalid { offset: usize, byte: u8 },
+    /// Trailing solc CBOR metadata, never executed.
+    Metadata { offset: usize, data: Bytes },
+}
+
+/// Decodes bytecode into opcodes, splitting off trailing solc metadata.
+pub fn decode(code: &[u8]) -> Vec<Decoded> {
+    let metadata = metadata_offset(code);
+    let end = metadata.unwrap_or(code.len());
+    let mut decoded = vec![];
+    let mut offset = 0;
+    while offset < end {
+        let byte = code[offset];
+        match OpCode::from_byte(byte) {
+            Some(opcode) => {
+                let immediate = &code[offset + 1..(offset + 1 + opcode.immediate as usize).min(end)];
+                decoded.push(Decoded::Op {
+                    offset,
+                    opcode,
+                    immediate: Bytes::copy_from_slice(immediate),
+                });
+                offset += 1 + opcode.immediate as usize;
+            }
+            None => {
+                decoded.push(Decoded::Invalid { offset, byte });
+                offset += 1;
+            }
+        }
+    }
+    if let Some(offset) = metadata {
+        decoded.push(Decoded::Metadata {
+            offset,
+            data: Bytes::copy_from_slice(&code[offset..]),
+        });
+    }
+    decoded
+}
+
+/// Disassembles bytecode into the dialect of [`compile_mnemonic`].
+///
+/// Every `JUMPDEST` becomes a label, and pushes directly followed by a `JUMP` or `JUMPI`
+/// reference the label they jump to. Invalid bytes, truncated pushes and trailing metadata are
+/// flagged with comments and kept as `DATA`, so the output assembles back into the same code.
+pub fn disassemble(code: &[u8]) -> String {
+    let decoded = decode(code);
+    let label = |offset: usize| format!("@L_{offset:04x}");
+    let labels = decoded
+        .iter()
+        .filter_map(|item| match item {
+            Decoded::Op { offset, opcode, .. } if opcode.mnemonic == "JUMPDEST" => Some(*offset),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let mut out = String::new();
+    for (idx, item) in decoded.iter().enumerate() {
+        match item {
+            Decoded::Op {
+                offset, opcode, ..
+            } if opcode.mnemonic == "JUMPDEST" => {
+                writeln!(out, "{}:", label(*offset)).unwrap();
+            }
+            Decoded::Op {
+                opcode, immediate, ..
+            } if opcode.immediate > 0 => {
+                if immediate.len() < opcode.immediate as usize {
+                    writeln!(out, "// truncated {opcode}: 0x{}", hex::encode(immediate)).unwrap();
+                    writeln!(out, "DATA 0x{:02x}{}", opcode.byte, hex::encode(immediate)).unwrap();
+                    continue;
+                }
+                let target = U256::from_be_slice(immediate);
+                let jumps = matches!(
+                    decoded.get(idx + 1),
+                    Some(Decoded::Op { opcode, .. }) if matches!(opcode.mnemonic, "JUMP" | "JUMPI")
+                );
+                match usize::try_from(target) {
+                    Ok(target) if jumps && labels.contains(&target) => {
+                        writeln!(out, "{opcode} {}", label(target)).unwrap()
+                    }
+                    _ => writeln!(out, "{opcode} 0x{}", hex::encode(immediate)).unwrap(),
+                }
+            }
+            Decoded::Op { opcode, .. } => writeln!(out, "{opcode}").unwrap(),
+            Decoded::Invalid { offset, byte } => {
+                writeln!(out, "// invalid opcode 0x{byte:02x} at 0x{offset:04x}").unwrap();
+                writeln!(out, "DATA 0x{byte:02x}").unwrap();
+            }
+            Decoded::Metadata { data, .. } => {
+                writeln!(out, "// metadata").unwrap();
+                writeln!(out, "DATA 0x{}", hex::encode(data)).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Finds solc's CBOR metadata, a map ending with its big-endian `u16` length.
+fn metadata_offset(code: &[u8]) -> Option<usize> {
+    let len = code.len().checked_sub(2)?;
+    let cbor_len = u16::from_be_bytes([code[len], code[len + 1]]) as usize;
+    let offset = len.checked_sub(cbor_len)?;
+    let cbor = &code[offset..len];
+    let is_map = matches!(cbor.first(), Some(0xa1..=0xa5));
+    let has_key = [&b"ipfs"[..], b"bzzr0", b"bzzr1", b"solc"]
+        .iter()
+        .any(|key| cbor.windows(key.len()).any(|window| window == *key));
+    (is_map && has_key).then_some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_mnemonic() {
+        let code = compile_mnemonic(
+            r#"
+            PUSH1 0x60
+            PUSH1 0x40
+            MSTORE
+            PUSH1 0x00
+            RETURN
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            code,
+            Bytes::from_static(&[0x60, 0x60, 0x60, 0x40, 0x52, 0x60, 0x00, 0xf3])
+        );
+
+        let code = compile_mnemonic(
+            r#"
+            PUSH0
+            PUSH1 0x01
+            DUP1
+            SWAP1
+            LOG0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            code,
+            Bytes::from_static(&[0x5f, 0x60, 0x01, 0x80, 0x90, 0xa0])
+        );
+
+        let code = compile_mnemonic(
+            r#"
+            basefee
+            prevrandao
+            DIFFICULTY
+            PUSH0
+            BLOBHASH
+            BLOBBASEFEE
+            PUSH2 0x1234
+            PUSH32 0xff
+            MCOPY
+            "#,
+        )
+        .unwrap();
+        let mut expected = vec![0x48, 0x44, 0x44, 0x5f, 0x49, 0x4a, 0x61, 0x12, 0x34, 0x7f];
+        expected.extend([0; 31]);
+        expected.extend([0xff, 0x5e]);
+        assert_eq!(code, Bytes::from(expected));
+    }
+
+    #[test]
+    fn test_compile_labels() {
+        let code = compile_mnemonic(
+            r#"
+            PUSH1 0x03
+            @loop:
+            PUSH1 0x01
+            SWAP1
+            SUB
+            DUP1
+            PUSH @loop
+            JUMPI
+            PUSH2 @end
+            JUMP
+            @end:
+            STOP
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            code,
+            Bytes::from_static(&[
+                0x60, 0x03, 0x5b, 0x60, 0x01, 0x90, 0x03, 0x80, 0x60, 0x02, 0x57, 0x61, 0x00, 0x0f,
+                0x56, 0x5b, 0x00
+            ])
+        );
+
+        // a forward reference past 0xff needs a wider push, which shifts the label again
+        let code = compile_mnemonic(&format!(
+            "PUSH @end\nJUMP\n{}@end:\nPUSH 0x1234",
+            "JUMPDEST\n".repeat(253)
+        ))
+        .unwrap();
+        assert_eq!(code[..4], [0x61, 0x01, 0x01, 0x56]);
+        assert_eq!(code[0x101..], [0x5b, 0x61, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_compile_mnemonic_errors() {
+        assert!(matches!(
+            compile_mnemonic("PUSH @nowhere"),
+            Err(CompileError::UndefinedLabel {
+                line: Line { number: 1, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            compile_mnemonic("@a:\nSTOP\n@a:"),
+            Err(CompileError::DuplicateLabel {
+                line: Line { number: 3, .. },
+                first: Line { number: 1, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            compile_mnemonic("PUSHX 0x01"),
+            Err(CompileError::InvalidOpcode { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("PUSH33 0x01"),
+            Err(CompileError::InvalidOpcode { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("DUP0"),
+            Err(CompileError::InvalidOpcode { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("PUSH1"),
+            Err(CompileError::MissingValue { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("PUSH1 0x100"),
+            Err(CompileError::InvalidPushValueLength {
+                expected: 1,
+                length: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_compile_directives() {
+        let code = compile_mnemonic(
+            r#"
+%define SLOT 0x07
+%macro sload_add slot, value // comments may follow directives
+PUSH value
+PUSH slot
+SLOAD
+ADD
+PUSH @.skip
+JUMP
+INVALID
+@.skip:
+%end
+sload_add SLOT, 0x01
+sload_add 0x08, SLOT
+"#,
+        )
+        .unwrap();
+        let expansion = |slot, value, skip| {
+            vec![0x60, value, 0x60, slot, 0x54, 0x01, 0x60, skip, 0x56, 0xfe, 0x5b]
+        };
+        assert_eq!(
+            code[..],
+            [expansion(0x07, 0x01, 0x0a), expansion(0x08, 0x07, 0x15)].concat()
+        );
+
+        // data counts towards label offsets
+        let code = compile_mnemonic(
+            "PUSH @end\nJUMP\nDATA 0xdeadbeef\nSTRING \"a// \\\"b\\\"\\n\"\n@end:",
+        )
+        .unwrap();
+        assert_eq!(code[..], *b"\x60\x0f\x56\xde\xad\xbe\xefa// \"b\"\n\x5b");
+
+        let dir = std::env::temp_dir().join(format!("spectre-asm-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("lib/inner.asm"), "%define ONE 0x01\nSTOP\n").unwrap();
+        std::fs::write(
+            dir.join("lib/outer.asm"),
+            "%include \"inner.asm\"\n%macro one\nPUSH ONE\n%end\n",
+        )
+        .unwrap();
+        let code = compile_mnemonic_in("%include \"lib/outer.asm\"\none", &dir).unwrap();
+        assert_eq!(code[..], [0x00, 0x60, 0x01]);
+        std::fs::write(dir.join("bad.asm"), "STOP\n\nPUSHX 0x01\n").unwrap();
+        let err = compile_mnemonic_in("STOP\n%include \"bad.asm\"", &dir).unwrap_err();
+        assert!(matches!(
+            &err,
+            CompileError::InvalidOpcode { line: Line { number: 3, file: Some(file) }, .. }
+                if file.ends_with("bad.asm")
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source = r#"%macro double
+DUP1 // keep a copy
+ADD
+%end
+PUSH1 0x02 // start
+double
+double
+@input:
+DATA 0x0102"#;
+        let (code, source_map) = compile_mnemonic_mapped(source, Path::new("")).unwrap();
+        assert_eq!(
+            code[..],
+            [0x60, 0x02, 0x80, 0x01, 0x80, 0x01, 0x5b, 0x01, 0x02]
+        );
+        let line = |number| Line { file: None, number };
+
+        assert_eq!(source_map.line(1), Some(&line(5)));
+        assert_eq!(source_map.line(4), Some(&line(2)));
+        assert_eq!(source_map.line(8), Some(&line(9)));
+        assert_eq!(source_map.line(9), None);
+        assert_eq!(source_map.pcs(&line(2)), [2..3, 4..5]);
+        assert_eq!(source_map.pcs(&line(4)), []);
+        assert!(source_map.spans()[6].data);
+
+        assert_eq!(
+            source_map.annotate(0).as_deref(),
+            Some("line 5: PUSH1 0x02 // start")
+        );
+        assert_eq!(
+            source_map.annotate(4).as_deref(),
+            Some("line 2: DUP1 // keep a copy")
+        );
+    }
+
+    #[test]
+    fn test_compile_directive_errors() {
+        assert!(matches!(
+            compile_mnemonic("%macro m a\nSTOP\n%end\nm"),
+            Err(CompileError::MacroArguments {
+                expected: 1,
+                got: 0,
+                line: Line { number: 4, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            compile_mnemonic("STOP\n%macro m\nSTOP"),
+            Err(CompileError::UnclosedMacro {
+                line: Line { number: 2, .. },
+                ..
+            })
+        ));
+        // errors inside a macro point at its definition
+        assert!(matches!(
+            compile_mnemonic("%macro m\nSTOP\nPUSHX 0x01\n%end\nm"),
+            Err(CompileError::InvalidOpcode {
+                line: Line { number: 3, .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            compile_mnemonic("%macro m\nm\n%end\nm"),
+            Err(CompileError::TooDeep { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("%end"),
+            Err(CompileError::InvalidDirective { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("%include \"/nonexistent/spectre.asm\""),
+            Err(CompileError::Include { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("DATA 0xabc"),
+            Err(CompileError::InvalidData { .. })
+        ));
+        assert!(matches!(
+            compile_mnemonic("STRING \"\\q\""),
+            Err(CompileError::InvalidData { .. })
+        ));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let source = r#"PUSH1 0x03
+@L_0002:
+PUSH1 0x01
+SWAP1
+SUB
+DUP1
+PUSH1 @L_0002
+JUMPI
+PUSH2 0x0010
+STOP
+"#;
+        let code = compile_mnemonic(source).unwrap();
+        assert_eq!(disassemble(&code), source);
+
+        // an invalid byte, solc metadata and a push running past the end
+        let mut code = vec![0x0c, 0x00];
+        code.extend([0xa1, 0x64, b's', b'o', b'l', b'c', 0x43, 0x00, 0x08, 0x1c, 0x00, 0x0a]);
+        let decoded = decode(&code);
+        assert_eq!(decoded[0], Decoded::Invalid { offset: 0, byte: 0x0c });
+        assert!(matches!(decoded[2], Decoded::Metadata { offset: 2, .. }));
+        let source = disassemble(&code);
+        assert_eq!(
+            source,
+            "// invalid opcode 0x0c at 0x0000\nDATA 0x0c\nSTOP\n// metadata\nDATA 0xa164736f6c634300081c000a\n"
+        );
+        assert_eq!(compile_mnemonic(&source).unwrap(), code);
+        let source = disassemble(&[0x61, 0x01]);
+        assert_eq!(source, "// truncated PUSH2: 0x01\nDATA 0x6101\n");
+        assert_eq!(compile_mnemonic(&source).unwrap()[..], [0x61, 0x01]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Chains [`Spectre::trace`](crate::Spectre::trace) runs scheduled transactions on.
+//!
+//! A [`WitnessBackend`] starts from the built genesis, takes signed transactions, seals them into
+//! blocks and dumps the witness of each block. Which one runs is chosen with
+//! `[system] backend = "..."`, geth attaches to a running node when `[system] rpc-url` is set,
+//! revm needs no node at all, or forks one when `[system] fork-url` is set.
+
+use crate::core::{SpectreError, TxLabel};
+#[cfg(not(feature = "scroll"))]
+use alloy_consensus::TxEnvelope;
+use alloy_genesis::Genesis;
+use alloy_primitives::{Address, B256, Log, TxHash};
+use alloy_signer_local::PrivateKeySigner;
+use sbv_primitives::types::BlockWitness;
+#[cfg(feature = "scroll")]
+use scroll_alloy_consensus::ScrollTxEnvelope as TxEnvelope;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, future::Future};
+
+mod geth;
+mod memory;
+#[cfg(test)]
+pub(crate) mod mock;
+
+pub use geth::GethBackend;
+pub use memory::RevmBackend;
+
+/// Backends selectable from `[system] backend`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// A geth dev node spawned for the run, see `[system] geth-path`.
+    #[default]
+    Geth,
+    /// Executes in-process on revm, with Scroll rules under the `scroll` feature.
+    Revm,
+}
+
+/// Debug traces collected for every sent transaction, selected with `[system] traces`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TraceKind {
+    /// Opcode-level steps of the default struct logger.
+    StructLogs,
+    /// Call frames, from geth's `callTracer`.
+    CallTracer,
+    /// Accounts touched and their state before the transaction, from geth's `prestateTracer`.
+    PrestateTracer,
+}
+
+impl Display for TraceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TraceKind::StructLogs => "struct-logs",
+            TraceKind::CallTracer => "call-tracer",
+            TraceKind::PrestateTracer => "prestate-tracer",
+        })
+    }
+}
+
+/// Account unlocking the miner of a running node, so blocks are sealed on demand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MinerCredentials {
+    pub address: Address,
+    pub password: String,
+}
+
+/// A running node to trace on instead of spawning one.
+#[derive(Debug, Clone)]
+pub struct AttachOptions {
+    /// `ipc://` socket path or `http(s)://` URL.
+    pub rpc_url: String,
+    /// Without credentials the node is expected to seal blocks on its own.
+    pub miner: Option<MinerCredentials>,
+    /// Funds the genesis accounts, which cannot be written to a running chain.
+    pub funding_key: Option<PrivateKeySigner>,
+}
+
+/// A running chain the revm backend forks instead of starting from the genesis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkOptions {
+    /// `ipc://` socket path or `http(s)://` URL of a node serving `eth_getProof`.
+    pub rpc_url: String,
+    /// Block executed on, the latest one if unset.
+    pub block: Option<u64>,
+}
+
+/// What sealing did with a submitted transaction, from its receipt.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TxOutcome {
+    pub hash: TxHash,
+    pub block_number: u64,
+    /// Index of the transaction in its block.
+    pub position: u64,
+    pub success: bool,
+    pub gas_used: u64,
+    pub effective_gas_price: u128,
+    /// Address of the contract a deployment created.
+    pub contract_address: Option<Address>,
+    pub logs: Vec<Log>,
+}
+
+/// Header fields of a sealed block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub number: u64,
+    pub hash: B256,
+    pub state_root: B256,
+    pub receipts_root: B256,
+    pub timestamp: u64,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub tx_count: usize,
+    pub base_fee: Option<u64>,
+}
+
+/// The step a transaction was executing when it stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionStep {
+    pub pc: usize,
+    pub op: String,
+    /// Call depth, 1 for the code the transaction itself runs.
+    pub depth: u64,
+}
+
+/// A chain transactions are executed on to produce block witnesses.
+///
+/// Operations run in order: [`apply_genesis`](Self::apply_genesis) once, then for each stage of
+/// transactions [`submit`](Self::submit) them and [`seal`](Self::seal) the blocks they land in.
+pub trait WitnessBackend {
+    /// Starts the chain from `genesis`, without sealing blocks until asked to.
+    fn apply_genesis(
+        &mut self,
+        genesis: Genesis,
+    ) -> impl Future<Output = Result<(), SpectreError>> + Send;
+
+    /// Nonce of the next transaction sent by `address`.
+    fn nonce(&self, address: Address) -> impl Future<Output = Result<u64, SpectreError>> + Send;
+
+    /// Queues a signed transaction for the next sealed block, failing with
+    /// [`SpectreError::TxRejected`] when the chain refuses it.
+    fn submit(
+        &mut self,
+        tx: TxEnvelope,
+        label: TxLabel,
+    ) -> impl Future<Output = Result<TxHash, SpectreError>> + Send;
+
+    /// Seals every queued transaction into one or more blocks, returning their outcomes in the
+    /// order they were submitted. A transaction left unmined fails with
+    /// [`SpectreError::TxDropped`] or [`SpectreError::TxStuck`].
+    fn seal(&mut self) -> impl Future<Output = Result<Vec<TxOutcome>, SpectreError>> + Send;
+
+    /// Header fields of the latest sealed block.
+    fn head(&self) -> impl Future<Output = Result<BlockInfo, SpectreError>> + Send;
+
+    /// Header fields of a sealed block.
+    fn block(&self, number: u64) -> impl Future<Output = Result<BlockInfo, SpectreError>> + Send;
+
+    /// Dumps the witness of a sealed block.
+    fn dump_witness(
+        &self,
+        number: u64,
+    ) -> impl Future<Output = Result<BlockWitness, SpectreError>> + Send;
+
+    /// A debug trace of a sealed transaction, as the node returns it.
+    fn debug_trace(
+        &self,
+        hash: TxHash,
+        kind: TraceKind,
+    ) -> impl Future<Output = Result<serde_json::Value, SpectreError>> + Send;
+
+    /// The last step a sealed transaction executed, `None` if it ran no code.
+    fn last_step(
+        &self,
+        hash: TxHash,
+    ) -> impl Future<Output = Result<Option<ExecutionStep>, SpectreError>> + Send;
+}
@@ -0,0 +1,736 @@
+use super::{
+    AttachOptions, BlockInfo, ExecutionStep, MinerCredentials, TraceKind, TxEnvelope, TxOutcome,
+    WitnessBackend,
+};
+use crate::{
+    core::{RejectReason, SpectreError, TxLabel},
+    hardfork::Hardfork,
+};
+use alloy_consensus::{BlockHeader, SignableTransaction, TxLegacy};
+use alloy_genesis::{ChainConfig, Genesis};
+use alloy_json_rpc::RpcError;
+use alloy_network::{BlockResponse, HeaderResponse, ReceiptResponse, TxSignerSync};
+use alloy_primitives::{Address, TxHash, TxKind, U64};
+use alloy_provider::{Provider, ProviderBuilder, RootProvider};
+use alloy_rpc_types_eth::Filter;
+use alloy_transport::TransportErrorKind;
+use sbv_primitives::types::{BlockWitness, Network};
+use sbv_utils::rpc::ProviderExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use testnet::{TestNetBuilder, TestNetProvider};
+
+const TRANSFER_GAS_LIMIT: u64 = 21_000;
+const DEFAULT_TX_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Polls without a new block after which the node is taken to have stopped sealing, 10 seconds.
+const IDLE_POLLS: u32 = 40;
+/// JSON-RPC error code of a method the node does not serve.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Runs transactions on a geth node, sealing blocks by starting its miner.
+///
+/// The node is either a dev node spawned from the genesis, or one already running that Spectre
+/// attaches to.
+#[derive(Debug, Default)]
+pub struct GethBackend {
+    geth_path: Option<PathBuf>,
+    attach: Option<AttachOptions>,
+    tx_timeout: Option<Duration>,
+    node: Option<Node>,
+    pending: Vec<(TxLabel, TxHash)>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Spawned(TestNetProvider),
+    Attached {
+        provider: RootProvider<Network>,
+        miner: Option<MinerCredentials>,
+    },
+}
+
+impl GethBackend {
+    /// Spawns geth from `geth_path`, or the one found in `PATH` if unset.
+    pub fn new(geth_path: Option<PathBuf>) -> Self {
+        GethBackend {
+            geth_path,
+            ..Default::default()
+        }
+    }
+
+    /// Attaches to a running node. The genesis cannot be written there, its accounts are funded
+    /// instead.
+    pub fn attach(options: AttachOptions) -> Self {
+        GethBackend {
+            attach: Some(options),
+            ..Default::default()
+        }
+    }
+
+    /// How long sealing waits for its transactions while the node keeps sealing blocks without
+    /// them, a minute by default.
+    pub fn tx_timeout(mut self, timeout: Duration) -> Self {
+        self.tx_timeout = Some(timeout);
+        self
+    }
+
+    /// The node, once the genesis is applied.
+    pub fn provider(&self) -> Option<&RootProvider<Network>> {
+        self.node.as_ref().map(Node::provider)
+    }
+
+    fn node(&self) -> Result<&Node, SpectreError> {
+        self.node.as_ref().ok_or(SpectreError::GenesisNotApplied)
+    }
+
+    /// Tops up the balances of the genesis accounts from the funding key.
+    async fn fund(
+        &mut self,
+        genesis: &Genesis,
+        options: &AttachOptions,
+    ) -> Result<(), SpectreError> {
+        let provider = self.node()?.provider().clone();
+        // pending transfers are not counted by the node's nonce
+        let mut nonce = None;
+        for (&address, account) in &genesis.alloc {
+            let balance = provider.get_balance(address).await?;
+            if balance >= account.balance {
+                continue;
+            }
+            let Some(signer) = &options.funding_key else {
+                return Err(SpectreError::NoFundingKey { address });
+            };
+            let next = match nonce {
+                Some(nonce) => nonce,
+                None => provider.get_transaction_count(signer.address()).await?,
+            };
+            nonce = Some(next + 1);
+            let mut tx = TxLegacy {
+                chain_id: Some(genesis.config.chain_id),
+                nonce: next,
+                gas_price: provider.get_gas_price().await?,
+                gas_limit: TRANSFER_GAS_LIMIT,
+                to: TxKind::Call(address),
+                value: account.balance - balance,
+                input: Default::default(),
+            };
+            let sig = signer.sign_transaction_sync(&mut tx).unwrap();
+            debug!(%address, value = %tx.value, "funding");
+            let label = TxLabel::Funding(address);
+            self.submit(TxEnvelope::Legacy(tx.into_signed(sig)), label)
+                .await?;
+        }
+        for outcome in self.seal().await? {
+            if !outcome.success {
+                return Err(SpectreError::FundingFailed { hash: outcome.hash });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Node {
+    fn provider(&self) -> &RootProvider<Network> {
+        match self {
+            Node::Spawned(provider) => provider.root(),
+            Node::Attached { provider, .. } => provider,
+        }
+    }
+
+    async fn start_miner(&self) -> Result<(), SpectreError> {
+        let unavailable = |e| method_error(e, SpectreError::MinerControlUnavailable);
+        match self {
+            Node::Spawned(provider) => provider.start_miner().await.map_err(unavailable)?,
+            Node::Attached {
+                provider,
+                miner: Some(miner),
+            } => {
+                let params = (miner.address, miner.password.clone(), 0);
+                provider
+                    .raw_request::<_, bool>("personal_unlockAccount".into(), params)
+                    .await
+                    .map_err(unavailable)?;
+                provider
+                    .raw_request::<_, serde_json::Value>("miner_start".into(), ())
+                    .await
+                    .map_err(unavailable)?;
+            }
+            // the node seals on its own
+            Node::Attached { miner: None, .. } => {}
+        }
+        Ok(())
+    }
+
+    async fn stop_miner(&self) -> Result<(), SpectreError> {
+        let unavailable = |e| method_error(e, SpectreError::MinerControlUnavailable);
+        match self {
+            Node::Spawned(provider) => provider.stop_miner().await.map_err(unavailable)?,
+            Node::Attached {
+                provider,
+                miner: Some(_),
+            } => {
+                provider
+                    .raw_request::<_, serde_json::Value>("miner_stop".into(), ())
+                    .await
+                    .map_err(unavailable)?;
+            }
+            Node::Attached { miner: None, .. } => {}
+        }
+        Ok(())
+    }
+}
+
+impl WitnessBackend for GethBackend {
+    async fn apply_genesis(&mut self, genesis: Genesis) -> Result<(), SpectreError> {
+        let Some(options) = self.attach.clone() else {
+            let mut builder = TestNetBuilder::default();
+            if let Some(geth_path) = self.geth_path.clone() {
+                builder = builder.geth_path(geth_path);
+            }
+            let provider = builder.genesis(genesis).build().await?;
+            provider.stop_miner().await?;
+            self.node = Some(Node::Spawned(provider));
+            return Ok(());
+        };
+
+        let provider = ProviderBuilder::<_, _, Network>::default()
+            .on_builtin(&options.rpc_url)
+            .await?;
+        let chain_id = provider.get_chain_id().await?;
+        if chain_id != genesis.config.chain_id {
+            return Err(SpectreError::ChainIdMismatch {
+                expected: genesis.config.chain_id,
+                actual: chain_id,
+            });
+        }
+        check_fork_schedule(&provider, &genesis.config).await?;
+        let node = Node::Attached {
+            provider,
+            miner: options.miner.clone(),
+        };
+        node.stop_miner().await?;
+        self.node = Some(node);
+        self.fund(&genesis, &options).await
+    }
+
+    async fn nonce(&self, address: Address) -> Result<u64, SpectreError> {
+        Ok(self
+            .node()?
+            .provider()
+            .get_transaction_count(address)
+            .await?)
+    }
+
+    async fn submit(&mut self, tx: TxEnvelope, label: TxLabel) -> Result<TxHash, SpectreError> {
+        let hash = match self.node()?.provider().send_tx_envelope(tx).await {
+            Ok(pending_tx) => *pending_tx.tx_hash(),
+            Err(RpcError::ErrorResp(payload)) => {
+                return Err(SpectreError::TxRejected {
+                    tx: label,
+                    reason: RejectReason::from_message(&payload.message),
+                });
+            }
+            Err(source) => return Err(SpectreError::TxRequest { tx: label, source }),
+        };
+        self.pending.push((label, hash));
+        Ok(hash)
+    }
+
+    async fn seal(&mut self) -> Result<Vec<TxOutcome>, SpectreError> {
+        let node = self.node.as_ref().ok_or(SpectreError::GenesisNotApplied)?;
+        let timeout = self.tx_timeout.unwrap_or(DEFAULT_TX_TIMEOUT);
+        collect_outcomes(node, std::mem::take(&mut self.pending), timeout).await
+    }
+
+    async fn head(&self) -> Result<BlockInfo, SpectreError> {
+        let number = self.node()?.provider().get_block_number().await?;
+        self.block(number).await
+    }
+
+    async fn block(&self, number: u64) -> Result<BlockInfo, SpectreError> {
+        let block = self
+            .node()?
+            .provider()
+            .get_block_by_number(number.into())
+            .await?
+            .ok_or(SpectreError::MissingBlock(number))?;
+        Ok(BlockInfo {
+            number,
+            hash: block.header().hash(),
+            state_root: block.header().state_root(),
+            receipts_root: block.header().receipts_root(),
+            timestamp: block.header().timestamp(),
+            gas_limit: block.header().gas_limit(),
+            gas_used: block.header().gas_used(),
+            tx_count: block.transactions().len(),
+            base_fee: block.header().base_fee_per_gas(),
+        })
+    }
+
+    async fn dump_witness(&self, number: u64) -> Result<BlockWitness, SpectreError> {
+        self.node()?
+            .provider()
+            .dump_block_witness(number.into())
+            .await?
+            .ok_or(SpectreError::MissingWitness(number))
+    }
+
+    async fn debug_trace(
+        &self,
+        hash: TxHash,
+        kind: TraceKind,
+    ) -> Result<serde_json::Value, SpectreError> {
+        let options = trace_options(kind);
+        Ok(self
+            .node()?
+            .provider()
+            .raw_request::<_, serde_json::Value>("debug_traceTransaction".into(), (hash, options))
+            .await?)
+    }
+
+    async fn last_step(&self, hash: TxHash) -> Result<Option<ExecutionStep>, SpectreError> {
+        let options = serde_json::json!({
+            "disableStack": true,
+            "disableStorage": true,
+        });
+        let trace = self
+            .node()?
+            .provider()
+            .raw_request::<_, serde_json::Value>("debug_traceTransaction".into(), (hash, options))
+            .await?;
+        let step = trace["structLogs"]
+            .as_array()
+            .and_then(|steps| steps.last())
+            .map(|step| ExecutionStep {
+                pc: step["pc"].as_u64().unwrap_or_default() as usize,
+                op: step["op"].as_str().unwrap_or_default().to_string(),
+                depth: step["depth"].as_u64().unwrap_or_default(),
+            });
+        Ok(step)
+    }
+}
+
+/// Maps a method the node does not serve to `unavailable`, other errors as they are.
+fn method_error(
+    e: RpcError<TransportErrorKind>,
+    unavailable: fn(String) -> SpectreError,
+) -> SpectreError {
+    match e.as_error_resp() {
+        Some(resp) if resp.code == METHOD_NOT_FOUND => unavailable(resp.message.to_string()),
+        _ => e.into(),
+    }
+}
+
+/// Fails unless the node activates every fork when the genesis does. Only the chain id can be
+/// checked otherwise, so the schedule is read from the node's `admin_nodeInfo`.
+async fn check_fork_schedule(
+    provider: &RootProvider<Network>,
+    config: &ChainConfig,
+) -> Result<(), SpectreError> {
+    let info = provider
+        .raw_request::<_, serde_json::Value>("admin_nodeInfo".into(), ())
+        .await
+        .map_err(|e| method_error(e, SpectreError::ForkScheduleUnavailable))?;
+    let node_config = info
+        .pointer("/protocols/eth/config")
+        .cloned()
+        .ok_or_else(|| {
+            SpectreError::ForkScheduleUnavailable("no eth chain config in the node info".into())
+        })?;
+    compare_fork_schedules(config, &serde_json::from_value(node_config)?)
+}
+
+fn compare_fork_schedules(
+    expected: &ChainConfig,
+    actual: &ChainConfig,
+) -> Result<(), SpectreError> {
+    for &fork in Hardfork::ALL {
+        let (expected, actual) = (fork.activation(expected), fork.activation(actual));
+        if expected != actual {
+            return Err(SpectreError::ForkScheduleMismatch {
+                fork,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Options of `debug_traceTransaction` for `kind`.
+fn trace_options(kind: TraceKind) -> serde_json::Value {
+    match kind {
+        // spelled out, the defaults of the struct logger changed between geth versions
+        TraceKind::StructLogs => serde_json::json!({
+            "enableMemory": false,
+            "disableStack": false,
+            "disableStorage": false,
+            "enableReturnData": true,
+        }),
+        TraceKind::CallTracer => serde_json::json!({ "tracer": "callTracer" }),
+        TraceKind::PrestateTracer => serde_json::json!({ "tracer": "prestateTracer" }),
+    }
+}
+
+/// Mines the pending transactions and collects their receipts and logs.
+async fn collect_outcomes(
+    node: &Node,
+    pending: Vec<(TxLabel, TxHash)>,
+    timeout: Duration,
+) -> Result<Vec<TxOutcome>, SpectreError> {
+    let provider = node.provider();
+    node.start_miner().await?;
+    let receipts = wait_for_receipts(provider, &pending, timeout).await;
+    node.stop_miner().await?;
+
+    let mut outcomes = vec![];
+    let mut blocks = BTreeSet::new();
+    for (receipt, block_number) in receipts? {
+        blocks.insert(block_number);
+        outcomes.push(TxOutcome {
+            hash: receipt.transaction_hash(),
+            block_number,
+            position: receipt.transaction_index().unwrap_or_default(),
+            success: receipt.status(),
+            gas_used: receipt.gas_used(),
+            effective_gas_price: receipt.effective_gas_price(),
+            contract_address: receipt.contract_address(),
+            logs: vec![],
+        });
+    }
+
+    for &number in &blocks {
+        let filter = Filter::new().from_block(number).to_block(number);
+        for log in provider.get_logs(&filter).await? {
+            let outcome = outcomes
+                .iter_mut()
+                .find(|outcome| Some(outcome.hash) == log.transaction_hash);
+            if let Some(outcome) = outcome {
+                outcome.logs.push(log.inner);
+            }
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Polls the receipts of `pending` until every one is mined, with the block it landed in.
+///
+/// A transaction fails as dropped once the pool is empty without it mined, and as stuck once the
+/// node seals no block for [`IDLE_POLLS`] polls, or keeps sealing others past `timeout`.
+async fn wait_for_receipts(
+    provider: &RootProvider<Network>,
+    pending: &[(TxLabel, TxHash)],
+    timeout: Duration,
+) -> Result<Vec<(<Network as alloy_network::Network>::ReceiptResponse, u64)>, SpectreError> {
+    let started = Instant::now();
+    let mut receipts = pending.iter().map(|_| None).collect::<Vec<_>>();
+    let mut head = provider.get_block_number().await?;
+    let mut idle_polls = 0;
+    loop {
+        // before the receipts: a transaction leaves the pool as its block is imported
+        let pooled = pool_size(provider).await?;
+        for ((label, hash), mined) in pending.iter().zip(&mut receipts) {
+            if mined.is_some() {
+                continue;
+            }
+            let receipt = provider
+                .get_transaction_receipt(*hash)
+                .await
+                .map_err(|source| SpectreError::TxRequest {
+                    tx: label.clone(),
+                    source,
+                })?;
+            *mined = receipt.and_then(|receipt| {
+                let number = receipt.block_number()?;
+                Some((receipt, number))
+            });
+        }
+        let Some(idx) = receipts.iter().position(Option::is_none) else {
+            return Ok(receipts.into_iter().flatten().collect());
+        };
+
+        let (label, hash) = pending[idx].clone();
+        if pooled == 0 {
+            return Err(SpectreError::TxDropped { tx: label, hash });
+        }
+        let number = provider.get_block_number().await?;
+        if number > head {
+            head = number;
+            idle_polls = 0;
+        } else {
+            idle_polls += 1;
+        }
+        if idle_polls >= IDLE_POLLS || started.elapsed() >= timeout {
+            return Err(match pool_section(provider, hash).await? {
+                Some(section) => SpectreError::TxStuck {
+                    tx: label,
+                    hash,
+                    section,
+                    waited: started.elapsed(),
+                },
+                None => SpectreError::TxDropped { tx: label, hash },
+            });
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Transactions in the pool, pending and queued, from `txpool_status`.
+async fn pool_size(provider: &RootProvider<Network>) -> Result<u64, SpectreError> {
+    let status = provider
+        .raw_request::<_, BTreeMap<String, U64>>("txpool_status".into(), ())
+        .await
+        .map_err(|e| method_error(e, SpectreError::TxPoolUnavailable))?;
+    Ok(status.values().map(|count| count.to::<u64>()).sum())
+}
+
+/// The section of the pool holding `hash` from `txpool_content`: `pending` when it can be mined,
+/// `queued` when an earlier nonce is missing.
+async fn pool_section(
+    provider: &RootProvider<Network>,
+    hash: TxHash,
+) -> Result<Option<&'static str>, SpectreError> {
+    let content = provider
+        .raw_request::<_, serde_json::Value>("txpool_content".into(), ())
+        .await
+        .map_err(|e| method_error(e, SpectreError::TxPoolUnavailable))?;
+    // by sender, then by nonce
+    let holds = |section: &str| {
+        content[section]
+            .as_object()
+            .into_iter()
+            .flat_map(|senders| senders.values())
+            .filter_map(|txs| txs.as_object())
+            .flat_map(|txs| txs.values())
+            .any(|tx| tx["hash"].as_str() == Some(hash.to_string().as_str()))
+    };
+    Ok(["pending", "queued"]
+        .into_iter()
+        .find(|section| holds(section)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Hardfork, Spectre, SpectreBuilder,
+        backend::{ForkOptions, RevmBackend},
+        builder::tests::build_with,
+        hardfork::ForkCondition,
+    };
+    use alloy_genesis::GenesisAccount;
+    use alloy_primitives::U256;
+    use alloy_signer_local::PrivateKeySigner;
+
+    #[test]
+    fn test_compare_fork_schedules() {
+        let config = |hardfork: Hardfork| {
+            let builder: SpectreBuilder = toml::from_str(&format!(
+                r#"
+                [genesis]
+                timestamp = 1000
+                [chain]
+                hardfork = "{hardfork}"
+                [[wallet]]
+                name = "alice"
+                [[transactions]]
+                from = "alice"
+                to = "alice"
+                gas-price = "1 Gwei"
+                gas-limit = 21000
+                "#
+            ))
+            .unwrap();
+            builder.build().unwrap().genesis.config
+        };
+        let latest = config(Hardfork::default());
+        assert!(compare_fork_schedules(&latest, &latest).is_ok());
+
+        let previous = Hardfork::ALL[Hardfork::ALL.len() - 2];
+        let result = compare_fork_schedules(&latest, &config(previous));
+        let Err(SpectreError::ForkScheduleMismatch {
+            fork,
+            expected,
+            actual,
+        }) = result
+        else {
+            panic!("{result:?}");
+        };
+        assert_eq!(fork, Hardfork::default());
+        assert!(matches!(expected, Some(ForkCondition::Timestamp(_))));
+        assert_eq!(actual, None);
+    }
+
+    #[test]
+    fn test_trace_options() {
+        let options = trace_options(TraceKind::StructLogs);
+        assert_eq!(options.get("tracer"), None);
+        assert_eq!(options["enableMemory"], false);
+        assert_eq!(options["disableStack"], false);
+        assert_eq!(options["disableStorage"], false);
+        assert_eq!(options["enableReturnData"], true);
+        assert_eq!(trace_options(TraceKind::CallTracer)["tracer"], "callTracer");
+        let options = trace_options(TraceKind::PrestateTracer);
+        assert_eq!(options["tracer"], "prestateTracer");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs geth"]
+    async fn test_attach() {
+        let funder = PrivateKeySigner::random();
+        let builder: SpectreBuilder = toml::from_str(&format!(
+            r#"
+            [[wallet]]
+            name = "funder"
+            secret = "{}"
+            [[alloc]]
+            address = "funder"
+            balance = "10 ether"
+            [[transactions]]
+            from = "funder"
+            to = "funder"
+            gas-price = "1 Gwei"
+            gas-limit = 21000
+            "#,
+            funder.to_bytes()
+        ))
+        .unwrap();
+        let genesis = builder.build().unwrap().genesis;
+        let mut spawned = GethBackend::default();
+        spawned.apply_genesis(genesis.clone()).await.unwrap();
+        let Some(Node::Spawned(node)) = &spawned.node else {
+            unreachable!()
+        };
+        let (address, password) = node.miner_credentials();
+
+        // an account only the funding key can pay for on the running chain
+        let recipient = Address::with_last_byte(0x42);
+        let mut attached_genesis = genesis.clone();
+        let account = GenesisAccount::default().with_balance(U256::from(1_000_000));
+        attached_genesis.alloc.insert(recipient, account);
+        let mut attached = GethBackend::attach(AttachOptions {
+            rpc_url: format!("ipc://{}", node.ipc_path().display()),
+            miner: Some(MinerCredentials {
+                address,
+                password: password.to_string(),
+            }),
+            funding_key: Some(funder),
+        });
+        attached.apply_genesis(attached_genesis).await.unwrap();
+
+        let provider = attached.provider().unwrap();
+        let balance = provider.get_balance(recipient).await.unwrap();
+        assert_eq!(balance, U256::from(1_000_000));
+        let head = attached.head().await.unwrap();
+        assert_eq!(head.number, 1);
+        assert_eq!(head.tx_count, 1);
+        assert_eq!(spawned.head().await.unwrap(), head);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs geth"]
+    async fn test_stuck_in_queue() {
+        let alice = PrivateKeySigner::random();
+        let builder: SpectreBuilder = toml::from_str(&format!(
+            r#"
+            [[wallet]]
+            name = "alice"
+            secret = "{}"
+            [[alloc]]
+            address = "alice"
+            balance = "1 ether"
+            [[transactions]]
+            from = "alice"
+            to = "alice"
+            gas-price = "1 Gwei"
+            gas-limit = 21000
+            "#,
+            alice.to_bytes()
+        ))
+        .unwrap();
+        let genesis = builder.build().unwrap().genesis;
+        let mut backend = GethBackend::default();
+        backend.apply_genesis(genesis.clone()).await.unwrap();
+
+        // nonce 0 is never sent, so the node has nothing to seal
+        let mut tx = TxLegacy {
+            chain_id: Some(genesis.config.chain_id),
+            nonce: 1,
+            gas_price: 1_000_000_000,
+            gas_limit: TRANSFER_GAS_LIMIT,
+            to: TxKind::Call(alice.address()),
+            value: U256::ZERO,
+            input: Default::default(),
+        };
+        let sig = alice.sign_transaction_sync(&mut tx).unwrap();
+        let label = TxLabel::Scheduled { idx: 0, name: None };
+        let hash = backend
+            .submit(TxEnvelope::Legacy(tx.into_signed(sig)), label.clone())
+            .await
+            .unwrap();
+
+        let result = backend.seal().await;
+        let Err(SpectreError::TxStuck {
+            tx,
+            hash: stuck,
+            section,
+            waited,
+        }) = result
+        else {
+            panic!("{result:?}");
+        };
+        assert_eq!((tx, stuck, section), (label, hash, "queued"));
+        assert!(waited < DEFAULT_TX_TIMEOUT);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs geth"]
+    async fn test_fork() {
+        let counter = Address::repeat_byte(0xc0);
+        // seeded, so both builds have the same alice
+        let build = |alloc: &str| -> Spectre {
+            let extra = format!("[system]\nrandom-seed = 1\n{alloc}");
+            build_with("", &format!("to = \"{counter}\""), &extra).unwrap()
+        };
+        let spectre = build(&format!(
+            r#"
+            [[alloc]]
+            address = "alice"
+            balance = "1 ether"
+            [[alloc]]
+            address = "{counter}"
+            code = "PUSH0\nSLOAD\nPUSH1 0x01\nADD\nPUSH0\nSSTORE"
+            "#
+        ));
+        let config = spectre.genesis.config.clone();
+        let mut spawned = GethBackend::default();
+        spectre.trace_with(&mut spawned).await.unwrap();
+        let Some(Node::Spawned(node)) = &spawned.node else {
+            unreachable!()
+        };
+        let forked_head = spawned.head().await.unwrap();
+
+        // alice's account and the counter are fetched from the node
+        let mut spectre = build("");
+        spectre.genesis.config = config;
+        let mut forked = RevmBackend::fork(ForkOptions {
+            rpc_url: format!("ipc://{}", node.ipc_path().display()),
+            block: None,
+        });
+        let report = spectre.trace_with(&mut forked).await.unwrap();
+
+        assert_eq!(report.blocks[0].number, forked_head.number + 1);
+        assert_eq!(
+            report.blocks[0].witness.pre_state_root,
+            forked_head.state_root
+        );
+        assert_eq!(forked.storage(counter, U256::ZERO), U256::from(2));
+        for verification in report.verify() {
+            assert!(verification.is_ok(), "{verification:?}");
+        }
+    }
+}
@@ -0,0 +1,1219 @@
+use super::{
+    BlockInfo, ExecutionStep, ForkOptions, TraceKind, TxEnvelope, TxOutcome, WitnessBackend,
+};
+#[cfg(feature = "scroll")]
+use crate::hardfork::{ForkCondition, Hardfork};
+use crate::{
+    core::{RejectReason, SpectreError, TxLabel, now},
+    mainnet::connect,
+    opcode::OpCode,
+    verify::receipts_root,
+};
+use alloy_consensus::{
+    Eip658Value, Header, Receipt, ReceiptEnvelope, ReceiptWithBloom, Transaction, Typed2718,
+    constants::EMPTY_WITHDRAWALS, proofs::calculate_transaction_root,
+};
+#[cfg(not(feature = "scroll"))]
+use alloy_eips::eip1559::BaseFeeParams;
+use alloy_eips::{
+    eip1559::INITIAL_BASE_FEE,
+    eip2718::Encodable2718,
+    eip2935::HISTORY_STORAGE_ADDRESS,
+    eip4788::{BEACON_ROOTS_ADDRESS, SYSTEM_ADDRESS},
+    eip7002::WITHDRAWAL_REQUEST_PREDEPLOY_ADDRESS,
+    eip7251::CONSOLIDATION_REQUEST_PREDEPLOY_ADDRESS,
+    eip7685::EMPTY_REQUESTS_HASH,
+};
+use alloy_genesis::{ChainConfig, Genesis, GenesisAccount};
+use alloy_primitives::{Address, B256, Bytes, TxHash, U256, keccak256, logs_bloom};
+use alloy_provider::{Provider, RootProvider};
+use alloy_rlp::Decodable;
+use alloy_trie::{EMPTY_ROOT_HASH, KECCAK_EMPTY, TrieAccount};
+use revm::{
+    Database, DatabaseCommit, Evm, EvmContext, Inspector, inspector_handle_register,
+    interpreter::Interpreter,
+    primitives::{
+        Account as ChangedAccount, AccountInfo, AuthorizationList, BlobExcessGasAndPrice, BlockEnv,
+        Bytecode, EVMError, HashMap as ChangedAccounts, SpecId, TxEnv,
+    },
+};
+use sbv_primitives::types::{BlockWitness, Network};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use trie::{Blinded, Nodes, slot_key};
+
+mod trie;
+
+/// Runs transactions in-process on revm, so tracing needs no node.
+///
+/// The state is kept in memory, from the genesis on or forked from a running chain with
+/// [`RevmBackend::fork`]. Blocks are sealed the way the witness verifier re-executes them: under
+/// Scroll rules with the `scroll` feature, without the system contract calls of Cancun and Prague
+/// otherwise, so allocating code at their addresses breaks verification. Transactions are
+/// included in the order they were submitted, each block taking them while their gas limits fit.
+#[derive(Debug, Default)]
+pub struct RevmBackend {
+    fork: Option<ForkOptions>,
+    chain: Option<Chain>,
+    queued: Vec<(TxLabel, TxEnvelope, Address)>,
+}
+
+#[derive(Debug)]
+struct Chain {
+    config: ChainConfig,
+    /// Root of the latest state, which differs from the forked block's when allocs override it.
+    state_root: B256,
+    nodes: Nodes,
+    codes: HashMap<B256, Bytes>,
+    /// From the genesis, or from the forked block.
+    blocks: Vec<Block>,
+    upstream: Option<Upstream>,
+}
+
+/// The chain a forked state is fetched from, as of the forked block.
+#[derive(Debug)]
+struct Upstream {
+    provider: RootProvider<Network>,
+    number: u64,
+    /// Of the blocks before the forked one, read by `BLOCKHASH`.
+    block_hashes: BTreeMap<u64, B256>,
+}
+
+#[derive(Debug)]
+struct Block {
+    header: Header,
+    hash: B256,
+    transactions: Vec<ExecutedTx>,
+    /// `None` for the genesis or the forked block.
+    witness: Option<BlockWitness>,
+}
+
+/// A sealed transaction, with what the witness and failure reports need of it.
+#[derive(Debug)]
+pub(super) struct ExecutedTx {
+    tx: TxEnvelope,
+    sender: Address,
+    outcome: TxOutcome,
+    last_step: Option<ExecutionStep>,
+}
+
+/// State the forked chain has to be asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+enum Missing {
+    #[error("account {0}")]
+    Account(Address),
+    #[error("slot {1} of {0}")]
+    Storage(Address, U256),
+    #[error("hash of block #{0}")]
+    BlockHash(u64),
+    #[error("trie node {0}")]
+    Node(B256),
+}
+
+/// Why reading or executing on the state stopped.
+#[derive(Debug)]
+enum Interrupt {
+    Missing(Missing),
+    Failed(SpectreError),
+}
+
+impl From<Missing> for Interrupt {
+    fn from(missing: Missing) -> Self {
+        Interrupt::Missing(missing)
+    }
+}
+
+impl From<SpectreError> for Interrupt {
+    fn from(e: SpectreError) -> Self {
+        Interrupt::Failed(e)
+    }
+}
+
+/// Updates only walk paths a read revealed, except removals merging the sibling left.
+impl From<Blinded> for Interrupt {
+    fn from(Blinded(hash): Blinded) -> Self {
+        Interrupt::Missing(Missing::Node(hash))
+    }
+}
+
+/// Accounts and slots read or written while executing a block.
+#[derive(Debug, Default)]
+struct Touched {
+    accounts: BTreeSet<Address>,
+    storage: BTreeMap<Address, BTreeSet<U256>>,
+}
+
+impl RevmBackend {
+    /// Forks the chain served at `options.rpc_url`, executing on top of `options.block`.
+    ///
+    /// Accounts, storage and code are fetched with `eth_getProof` and `eth_getCode` as the
+    /// transactions reach them, so the first witness starts from the state root of the forked
+    /// block. Allocs are written over the accounts there, the first witness then starts from the
+    /// overridden state instead.
+    pub fn fork(options: ForkOptions) -> Self {
+        RevmBackend {
+            fork: Some(options),
+            ..Default::default()
+        }
+    }
+
+    /// A storage slot of `address` at the latest block, zero when unset or not fetched from the
+    /// forked chain.
+    pub fn storage(&self, address: Address, slot: U256) -> U256 {
+        self.chain
+            .as_ref()
+            .and_then(|chain| chain.storage(address, slot).ok())
+            .unwrap_or_default()
+    }
+
+    fn chain(&self) -> Result<&Chain, SpectreError> {
+        self.chain.as_ref().ok_or(SpectreError::GenesisNotApplied)
+    }
+}
+
+impl Block {
+    fn info(&self) -> BlockInfo {
+        BlockInfo {
+            number: self.header.number,
+            hash: self.hash,
+            state_root: self.header.state_root,
+            receipts_root: self.header.receipts_root,
+            timestamp: self.header.timestamp,
+            gas_limit: self.header.gas_limit,
+            gas_used: self.header.gas_used,
+            tx_count: self.transactions.len(),
+            base_fee: self.header.base_fee_per_gas,
+        }
+    }
+}
+
+impl Chain {
+    fn new(config: ChainConfig) -> Self {
+        Chain {
+            config,
+            state_root: EMPTY_ROOT_HASH,
+            nodes: Nodes::default(),
+            codes: HashMap::new(),
+            blocks: vec![],
+            upstream: None,
+        }
+    }
+
+    /// A chain whose first block is the forked one, without any of its state yet.
+    async fn fork(options: &ForkOptions, config: ChainConfig) -> Result<Self, SpectreError> {
+        let provider = connect(&options.rpc_url).await?;
+        let chain_id = provider.get_chain_id().await?;
+        if chain_id != config.chain_id {
+            return Err(SpectreError::ChainIdMismatch {
+                expected: config.chain_id,
+                actual: chain_id,
+            });
+        }
+        let number = match options.block {
+            Some(number) => number,
+            None => provider.get_block_number().await?,
+        };
+        let block = provider
+            .get_block_by_number(number.into())
+            .await?
+            .ok_or(SpectreError::MissingBlock(number))?;
+        let hash = block.header.hash;
+        let header = block.header.inner;
+        ensure_mpt(&config, number, header.timestamp)?;
+        debug!(number, %hash, "forked");
+
+        let mut chain = Chain::new(config);
+        chain.state_root = header.state_root;
+        chain.blocks.push(Block {
+            header,
+            hash,
+            transactions: vec![],
+            witness: None,
+        });
+        chain.upstream = Some(Upstream {
+            provider,
+            number,
+            block_hashes: BTreeMap::new(),
+        });
+        Ok(chain)
+    }
+
+    fn head(&self) -> &Header {
+        &self
+            .blocks
+            .last()
+            .expect("the genesis or the forked block is always there")
+            .header
+    }
+
+    fn block(&self, number: u64) -> Option<&Block> {
+        let first = self.blocks.first()?.header.number;
+        self.blocks.get(number.checked_sub(first)? as usize)
+    }
+
+    fn account(&self, address: Address) -> Result<Option<TrieAccount>, Missing> {
+        let account = self
+            .nodes
+            .get(self.state_root, keccak256(address))
+            .map_err(|_| Missing::Account(address))?;
+        Ok(account.map(|account| {
+            TrieAccount::decode(&mut account.as_slice()).expect("a leaf of the state trie")
+        }))
+    }
+
+    fn storage(&self, address: Address, slot: U256) -> Result<U256, Missing> {
+        let Some(account) = self.account(address)? else {
+            return Ok(U256::ZERO);
+        };
+        let value = self
+            .nodes
+            .get(account.storage_root, slot_key(slot))
+            .map_err(|_| Missing::Storage(address, slot))?;
+        Ok(value
+            .map(|value| U256::decode(&mut value.as_slice()).expect("a leaf of a storage trie"))
+            .unwrap_or_default())
+    }
+
+    fn code(&self, address: Address, code_hash: B256) -> Result<Option<Bytes>, Missing> {
+        match code_hash {
+            KECCAK_EMPTY => Ok(None),
+            hash => match self.codes.get(&hash) {
+                Some(code) => Ok(Some(code.clone())),
+                None => Err(Missing::Account(address)),
+            },
+        }
+    }
+
+    fn block_hash(&self, number: u64) -> Result<B256, Missing> {
+        if let Some(block) = self.block(number) {
+            return Ok(block.hash);
+        }
+        match &self.upstream {
+            Some(upstream) if number < upstream.number => upstream
+                .block_hashes
+                .get(&number)
+                .copied()
+                .ok_or(Missing::BlockHash(number)),
+            _ => Ok(B256::ZERO),
+        }
+    }
+
+    /// Runs `f` until it has the state it reads, fetching what it misses from the forked chain.
+    async fn with_state<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Chain) -> Result<T, Interrupt>,
+    ) -> Result<T, SpectreError> {
+        let mut fetched = None;
+        loop {
+            match f(self) {
+                Ok(value) => return Ok(value),
+                Err(Interrupt::Failed(e)) => return Err(e),
+                Err(Interrupt::Missing(missing)) if fetched == Some(missing) => {
+                    return Err(SpectreError::ForkedState(format!(
+                        "{missing} is not in what the node returned"
+                    )));
+                }
+                Err(Interrupt::Missing(missing)) => {
+                    self.fetch(missing).await?;
+                    fetched = Some(missing);
+                }
+            }
+        }
+    }
+
+    /// Fetches `missing` as of the forked block. What changed since is known already, so the
+    /// nodes of the forked block reveal the rest of the paths.
+    async fn fetch(&mut self, missing: Missing) -> Result<(), SpectreError> {
+        let Some(upstream) = &mut self.upstream else {
+            return Err(SpectreError::ForkedState(format!(
+                "{missing} is missing from a state built from the genesis"
+            )));
+        };
+        debug!(%missing, "fetching from the forked chain");
+        let provider = &upstream.provider;
+        match missing {
+            Missing::Account(address) | Missing::Storage(address, _) => {
+                let keys = match missing {
+                    Missing::Storage(_, slot) => vec![slot.into()],
+                    _ => vec![],
+                };
+                let proof = provider
+                    .get_proof(address, keys)
+                    .number(upstream.number)
+                    .await?;
+                self.nodes.reveal(proof.account_proof);
+                self.nodes
+                    .reveal(proof.storage_proof.into_iter().flat_map(|slot| slot.proof));
+                if proof.code_hash != KECCAK_EMPTY && !self.codes.contains_key(&proof.code_hash) {
+                    let code = provider
+                        .get_code_at(address)
+                        .number(upstream.number)
+                        .await?;
+                    if keccak256(&code) != proof.code_hash {
+                        return Err(SpectreError::ForkedState(format!(
+                            "the code of {address} does not match its hash"
+                        )));
+                    }
+                    self.codes.insert(proof.code_hash, code);
+                }
+            }
+            Missing::BlockHash(number) => {
+                let block = provider
+                    .get_block_by_number(number.into())
+                    .await?
+                    .ok_or(SpectreError::MissingBlock(number))?;
+                upstream.block_hashes.insert(number, block.header.hash);
+            }
+            // the sibling a removal merges, which no proof of a key holds
+            Missing::Node(hash) => {
+                let node = provider
+                    .raw_request::<_, Bytes>("debug_dbGet".into(), (hash,))
+                    .await
+                    .map_err(|e| {
+                        SpectreError::ForkedState(format!(
+                            "trie node {hash} needs debug_dbGet on a hash-based node database: {e}"
+                        ))
+                    })?;
+                if keccak256(&node) != hash {
+                    return Err(SpectreError::ForkedState(format!(
+                        "debug_dbGet returned another node than {hash}"
+                    )));
+                }
+                self.nodes.reveal([node]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Root of the state with `alloc` written over it: the balance always, the nonce, code and
+    /// storage slots an account sets. On a new account that is all of it.
+    fn override_accounts(
+        &mut self,
+        alloc: &BTreeMap<Address, GenesisAccount>,
+    ) -> Result<B256, Interrupt> {
+        let mut state_root = self.state_root;
+        for (&address, alloc) in alloc {
+            let mut account = self.account(address)?.unwrap_or_else(empty_account);
+            account.balance = alloc.balance;
+            if let Some(nonce) = alloc.nonce {
+                account.nonce = nonce;
+            }
+            if let Some(code) = &alloc.code {
+                account.code_hash = match code.is_empty() {
+                    true => KECCAK_EMPTY,
+                    false => {
+                        let hash = keccak256(code);
+                        self.codes.insert(hash, code.clone());
+                        hash
+                    }
+                };
+            }
+            for (slot, value) in alloc.storage.iter().flatten() {
+                let slot = U256::from_be_bytes(slot.0);
+                // reveals the path to the slot
+                self.storage(address, slot)?;
+                account.storage_root =
+                    self.write_slot(account.storage_root, slot, U256::from_be_bytes(value.0))?;
+            }
+            state_root =
+                self.nodes
+                    .insert(state_root, keccak256(address), alloy_rlp::encode(account))?;
+        }
+        Ok(state_root)
+    }
+
+    fn write_slot(&mut self, root: B256, slot: U256, value: U256) -> Result<B256, Blinded> {
+        match value.is_zero() {
+            true => self.nodes.remove(root, slot_key(slot)),
+            false => self
+                .nodes
+                .insert(root, slot_key(slot), alloy_rlp::encode(value)),
+        }
+    }
+
+    /// Header of the next block, before executing its transactions.
+    fn next_header(&self) -> Header {
+        let parent = self.head();
+        let mut header = Header {
+            parent_hash: self.blocks.last().unwrap().hash,
+            number: parent.number + 1,
+            // one second apart at least, like clique with a zero period
+            timestamp: now().max(parent.timestamp + 1),
+            gas_limit: parent.gas_limit,
+            base_fee_per_gas: next_base_fee(&self.config, parent),
+            ..Default::default()
+        };
+        fork_fields(&self.config, &mut header);
+        header
+    }
+
+    /// Executes `queued` on the latest state and seals them into a block, recording what the
+    /// witness has to prove.
+    fn execute(
+        &mut self,
+        mut header: Header,
+        queued: &[(TxLabel, TxEnvelope, Address)],
+    ) -> Result<Block, Interrupt> {
+        let spec_id = spec_id(&self.config, header.number, header.timestamp);
+        let block_env = block_env(&header, coinbase(&self.config, &header), spec_id);
+        let chain_id = self.config.chain_id;
+
+        let mut evm = Evm::builder()
+            .with_db(Recorder {
+                chain: self,
+                overlay: Overlay::default(),
+            })
+            .with_external_context(LastStep::default())
+            .with_spec_id(spec_id)
+            .modify_cfg_env(|cfg| cfg.chain_id = chain_id)
+            .modify_block_env(|block| *block = block_env)
+            .append_handler_register(inspector_handle_register)
+            .build();
+
+        let mut transactions = vec![];
+        let mut receipts = vec![];
+        let mut cumulative_gas_used = 0;
+        for (position, (label, tx, sender)) in queued.iter().enumerate() {
+            *evm.tx_mut() = tx_env(tx, *sender);
+            evm.context.external = LastStep::default();
+            let result = match evm.transact_commit() {
+                Ok(result) => result,
+                Err(EVMError::Database(missing)) => return Err(missing.into()),
+                Err(e) => {
+                    return Err(SpectreError::TxRejected {
+                        tx: label.clone(),
+                        reason: RejectReason::from_message(&e.to_string()),
+                    }
+                    .into());
+                }
+            };
+            cumulative_gas_used += result.gas_used();
+            let receipt = Receipt {
+                status: Eip658Value::Eip658(result.is_success()),
+                cumulative_gas_used,
+                logs: result.logs().to_vec(),
+            };
+            receipts.push(receipt_envelope(tx, receipt.with_bloom()));
+
+            let outcome = TxOutcome {
+                hash: tx.trie_hash(),
+                block_number: header.number,
+                position: position as u64,
+                success: result.is_success(),
+                gas_used: result.gas_used(),
+                effective_gas_price: tx.effective_gas_price(header.base_fee_per_gas),
+                // set for failed deployments too, like geth's receipts
+                contract_address: tx.kind().is_create().then(|| sender.create(tx.nonce())),
+                logs: result.logs().to_vec(),
+            };
+            let last_step = evm
+                .context
+                .external
+                .0
+                .take()
+                .map(|(pc, op, depth)| ExecutionStep {
+                    pc,
+                    op: OpCode::from_byte(op)
+                        .map(ToString::to_string)
+                        .unwrap_or_else(|| format!("opcode {op:#04x}")),
+                    depth,
+                });
+            transactions.push(ExecutedTx {
+                tx: tx.clone(),
+                sender: *sender,
+                outcome,
+                last_step,
+            });
+        }
+        let Overlay {
+            accounts,
+            storage,
+            wiped,
+            codes,
+            mut touched,
+        } = std::mem::take(&mut evm.db_mut().overlay);
+        drop(evm);
+
+        // read by the verifier's system calls, even when they do nothing
+        touched
+            .accounts
+            .extend(system_accounts(&self.config, header.timestamp));
+
+        // the paths to what the block read or wrote, before it changed them
+        let pre_state_root = self.state_root;
+        let mut states = BTreeSet::new();
+        let mut pre_codes = BTreeSet::new();
+        for &address in &touched.accounts {
+            let proof = self.nodes.proof(pre_state_root, keccak256(address));
+            states.extend(proof.map_err(|_| Missing::Account(address))?);
+            let Some(account) = self.account(address)? else {
+                continue;
+            };
+            pre_codes.extend(self.code(address, account.code_hash)?);
+            for &slot in touched.storage.get(&address).into_iter().flatten() {
+                let proof = self.nodes.proof(account.storage_root, slot_key(slot));
+                states.extend(proof.map_err(|_| Missing::Storage(address, slot))?);
+            }
+        }
+
+        // removals first, so the nodes a collapsing branch merges are read whatever order the
+        // verifier updates in
+        self.nodes.take_reads();
+        let mut state_root = pre_state_root;
+        for (&address, _) in accounts.iter().filter(|(_, info)| info.is_none()) {
+            state_root = self.nodes.remove(state_root, keccak256(address))?;
+        }
+        for (&address, info) in &accounts {
+            let Some(info) = info else {
+                continue;
+            };
+            let mut storage_root = match wiped.contains(&address) {
+                true => EMPTY_ROOT_HASH,
+                false => self
+                    .account(address)?
+                    .map_or(EMPTY_ROOT_HASH, |account| account.storage_root),
+            };
+            let (cleared, set): (Vec<_>, Vec<_>) = storage
+                .get(&address)
+                .into_iter()
+                .flatten()
+                .partition(|(_, value)| value.is_zero());
+            for (&slot, &value) in cleared.into_iter().chain(set) {
+                storage_root = self.write_slot(storage_root, slot, value)?;
+            }
+            let account = TrieAccount {
+                nonce: info.nonce,
+                balance: info.balance,
+                storage_root,
+                code_hash: info.code_hash,
+            };
+            state_root =
+                self.nodes
+                    .insert(state_root, keccak256(address), alloy_rlp::encode(account))?;
+        }
+        states.extend(self.nodes.take_reads());
+        self.codes.extend(codes);
+
+        header.gas_used = cumulative_gas_used;
+        header.state_root = state_root;
+        header.transactions_root = calculate_transaction_root(
+            &transactions
+                .iter()
+                .map(|executed| executed.tx.clone())
+                .collect::<Vec<_>>(),
+        );
+        header.receipts_root = receipts_root(&receipts);
+        header.logs_bloom = logs_bloom(receipts.iter().flat_map(|receipt| receipt.logs()));
+
+        let witness = block_witness(
+            chain_id,
+            &header,
+            pre_state_root,
+            &transactions,
+            states.into_iter().collect(),
+            pre_codes.into_iter().collect(),
+        );
+        self.state_root = state_root;
+        Ok(Block {
+            hash: header.hash_slow(),
+            header,
+            transactions,
+            witness: Some(witness),
+        })
+    }
+}
+
+impl WitnessBackend for RevmBackend {
+    async fn apply_genesis(&mut self, genesis: Genesis) -> Result<(), SpectreError> {
+        let mut chain = match &self.fork {
+            Some(options) => Chain::fork(options, genesis.config).await?,
+            None => {
+                ensure_mpt(&genesis.config, 0, genesis.timestamp)?;
+                Chain::new(genesis.config)
+            }
+        };
+        chain.state_root = chain
+            .with_state(|chain| chain.override_accounts(&genesis.alloc))
+            .await?;
+
+        if chain.blocks.is_empty() {
+            let mut header = Header {
+                timestamp: genesis.timestamp,
+                gas_limit: genesis.gas_limit,
+                difficulty: genesis.difficulty,
+                extra_data: genesis.extra_data,
+                state_root: chain.state_root,
+                base_fee_per_gas: has_base_fee(&chain.config, 0).then(|| {
+                    genesis
+                        .base_fee_per_gas
+                        .map_or(INITIAL_BASE_FEE, |fee| fee as u64)
+                }),
+                ..Default::default()
+            };
+            fork_fields(&chain.config, &mut header);
+            chain.blocks.push(Block {
+                hash: header.hash_slow(),
+                header,
+                transactions: vec![],
+                witness: None,
+            });
+        }
+        self.chain = Some(chain);
+        self.queued.clear();
+        Ok(())
+    }
+
+    async fn nonce(&self, address: Address) -> Result<u64, SpectreError> {
+        let chain = self.chain()?;
+        match chain.account(address) {
+            Ok(account) => Ok(account.map_or(0, |account| account.nonce)),
+            // not revealed, so left as it was in the forked block
+            Err(_) => {
+                let upstream = chain
+                    .upstream
+                    .as_ref()
+                    .expect("a state from the genesis is whole");
+                Ok(upstream
+                    .provider
+                    .get_transaction_count(address)
+                    .number(upstream.number)
+                    .await?)
+            }
+        }
+    }
+
+    async fn submit(&mut self, tx: TxEnvelope, label: TxLabel) -> Result<TxHash, SpectreError> {
+        let chain = self.chain.as_mut().ok_or(SpectreError::GenesisNotApplied)?;
+        let reject = |reason| {
+            Err(SpectreError::TxRejected {
+                tx: label.clone(),
+                reason,
+            })
+        };
+        let Some(sender) = recover_sender(&tx) else {
+            return reject(RejectReason::Other("invalid signature".to_string()));
+        };
+        #[cfg(not(feature = "scroll"))]
+        if tx.is_eip4844() {
+            return reject(RejectReason::Other(
+                "blob transactions are not supported".to_string(),
+            ));
+        }
+
+        let account = chain
+            .with_state(|chain| Ok(chain.account(sender)?))
+            .await?
+            .unwrap_or_else(empty_account);
+        let queued = self
+            .queued
+            .iter()
+            .filter(|(_, _, from)| *from == sender)
+            .count() as u64;
+        let nonce = account.nonce + queued;
+        if tx.nonce() < nonce {
+            return reject(RejectReason::NonceTooLow);
+        }
+        if tx.nonce() > nonce {
+            return reject(RejectReason::Other(format!(
+                "nonce too high, the next one is {nonce}"
+            )));
+        }
+        let head = chain.head();
+        if tx.gas_limit() > head.gas_limit {
+            return reject(RejectReason::Other("exceeds block gas limit".to_string()));
+        }
+        let base_fee = next_base_fee(&chain.config, head).unwrap_or_default();
+        if tx.max_fee_per_gas() < base_fee as u128 {
+            return reject(RejectReason::Underpriced);
+        }
+        let cost = U256::from(tx.gas_limit()) * U256::from(tx.max_fee_per_gas()) + tx.value();
+        if account.balance < cost {
+            return reject(RejectReason::InsufficientFunds);
+        }
+
+        let hash = tx.trie_hash();
+        self.queued.push((label, tx, sender));
+        Ok(hash)
+    }
+
+    async fn seal(&mut self) -> Result<Vec<TxOutcome>, SpectreError> {
+        let chain = self.chain.as_mut().ok_or(SpectreError::GenesisNotApplied)?;
+        let mut queued = std::mem::take(&mut self.queued).into_iter().peekable();
+        let mut outcomes = vec![];
+        while queued.peek().is_some() {
+            let header = chain.next_header();
+            let mut gas_left = header.gas_limit;
+            let mut transactions = vec![];
+            // a transaction fits an empty block, its gas limit was checked on submission
+            while let Some(next) = queued.next_if(|(_, tx, _)| tx.gas_limit() <= gas_left) {
+                gas_left -= next.1.gas_limit();
+                transactions.push(next);
+            }
+            // executed again from the start once a fetch reveals what it missed
+            let block = chain
+                .with_state(|chain| chain.execute(header.clone(), &transactions))
+                .await?;
+            debug!(number = block.header.number, hash = %block.hash, "block sealed");
+            outcomes.extend(block.transactions.iter().map(|tx| tx.outcome.clone()));
+            chain.blocks.push(block);
+        }
+        Ok(outcomes)
+    }
+
+    async fn head(&self) -> Result<BlockInfo, SpectreError> {
+        Ok(self.chain()?.blocks.last().unwrap().info())
+    }
+
+    async fn block(&self, number: u64) -> Result<BlockInfo, SpectreError> {
+        self.chain()?
+            .block(number)
+            .map(Block::info)
+            .ok_or(SpectreError::MissingBlock(number))
+    }
+
+    async fn dump_witness(&self, number: u64) -> Result<BlockWitness, SpectreError> {
+        self.chain()?
+            .block(number)
+            .and_then(|block| block.witness.clone())
+            .ok_or(SpectreError::MissingWitness(number))
+    }
+
+    async fn debug_trace(
+        &self,
+        _hash: TxHash,
+        _kind: TraceKind,
+    ) -> Result<serde_json::Value, SpectreError> {
+        Err(SpectreError::Unsupported("debug traces"))
+    }
+
+    async fn last_step(&self, hash: TxHash) -> Result<Option<ExecutionStep>, SpectreError> {
+        Ok(self
+            .chain()?
+            .blocks
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .find(|executed| executed.outcome.hash == hash)
+            .and_then(|executed| executed.last_step.clone()))
+    }
+}
+
+/// Assembles the witness of a sealed block from the RPC view of it, the way it is built from
+/// what a node serves.
+pub(super) fn block_witness(
+    chain_id: u64,
+    header: &Header,
+    pre_state_root: B256,
+    transactions: &[ExecutedTx],
+    states: Vec<Bytes>,
+    codes: Vec<Bytes>,
+) -> BlockWitness {
+    let rpc_header = alloy_rpc_types_eth::Header::new(header.clone());
+    let transaction = transactions
+        .iter()
+        .enumerate()
+        .map(|(idx, executed)| {
+            let tx = alloy_rpc_types_eth::Transaction {
+                inner: executed.tx.clone(),
+                block_hash: Some(rpc_header.hash),
+                block_number: Some(header.number),
+                transaction_index: Some(idx as u64),
+                effective_gas_price: Some(executed.outcome.effective_gas_price),
+                from: executed.sender,
+            };
+            #[cfg(feature = "scroll")]
+            let tx = scroll_alloy_rpc_types::Transaction { inner: tx };
+            sbv_primitives::types::Transaction::from_alloy(tx)
+        })
+        .collect();
+    BlockWitness {
+        chain_id,
+        header: rpc_header.into(),
+        pre_state_root,
+        transaction,
+        withdrawals: header.withdrawals_root.map(|_| vec![]),
+        states,
+        codes,
+    }
+}
+
+/// What a block changed, on top of the state before it.
+#[derive(Debug, Default)]
+struct Overlay {
+    /// `None` once deleted.
+    accounts: BTreeMap<Address, Option<AccountInfo>>,
+    /// Zero once cleared.
+    storage: BTreeMap<Address, BTreeMap<U256, U256>>,
+    /// Accounts whose storage a deployment or a self-destruct wiped.
+    wiped: BTreeSet<Address>,
+    codes: HashMap<B256, Bytes>,
+    touched: Touched,
+}
+
+/// The state the EVM runs on, the block's changes over the chain's latest state, recording every
+/// account and slot it reads or writes.
+struct Recorder<'a> {
+    chain: &'a Chain,
+    overlay: Overlay,
+}
+
+impl Database for Recorder<'_> {
+    type Error = Missing;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Missing> {
+        self.overlay.touched.accounts.insert(address);
+        if let Some(changed) = self.overlay.accounts.get(&address) {
+            return Ok(changed.clone());
+        }
+        let Some(account) = self.chain.account(address)? else {
+            return Ok(None);
+        };
+        Ok(Some(AccountInfo {
+            balance: account.balance,
+            nonce: account.nonce,
+            code_hash: account.code_hash,
+            code: self
+                .chain
+                .code(address, account.code_hash)?
+                .map(Bytecode::new_raw),
+        }))
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Missing> {
+        Ok(self
+            .overlay
+            .codes
+            .get(&code_hash)
+            .or_else(|| self.chain.codes.get(&code_hash))
+            .map(|code| Bytecode::new_raw(code.clone()))
+            .unwrap_or_default())
+    }
+
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Missing> {
+        self.overlay.touched.accounts.insert(address);
+        self.overlay
+            .touched
+            .storage
+            .entry(address)
+            .or_default()
+            .insert(index);
+        if let Some(value) = self
+            .overlay
+            .storage
+            .get(&address)
+            .and_then(|slots| slots.get(&index))
+        {
+            return Ok(*value);
+        }
+        if self.overlay.wiped.contains(&address) {
+            return Ok(U256::ZERO);
+        }
+        self.chain.storage(address, index)
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<B256, Missing> {
+        self.chain.block_hash(number)
+    }
+}
+
+impl DatabaseCommit for Recorder<'_> {
+    fn commit(&mut self, changes: ChangedAccounts<Address, ChangedAccount>) {
+        let overlay = &mut self.overlay;
+        for (address, changed) in changes {
+            if !changed.is_touched() {
+                continue;
+            }
+            overlay.touched.accounts.insert(address);
+            // self-destructed, or left empty by the transaction and cleared (EIP-161)
+            if changed.is_selfdestructed() || changed.is_empty() {
+                overlay.accounts.insert(address, None);
+                overlay.storage.remove(&address);
+                overlay.wiped.insert(address);
+                continue;
+            }
+            if changed.is_created() {
+                overlay.storage.remove(&address);
+                overlay.wiped.insert(address);
+            }
+            if let Some(code) = changed.info.code.as_ref().filter(|code| !code.is_empty()) {
+                overlay
+                    .codes
+                    .insert(changed.info.code_hash, code.original_bytes());
+            }
+            for (slot, value) in changed.storage {
+                if !value.is_changed() {
+                    continue;
+                }
+                overlay
+                    .touched
+                    .storage
+                    .entry(address)
+                    .or_default()
+                    .insert(slot);
+                overlay
+                    .storage
+                    .entry(address)
+                    .or_default()
+                    .insert(slot, value.present_value());
+            }
+            overlay.accounts.insert(address, Some(changed.info));
+        }
+    }
+}
+
+/// Program counter, opcode and call depth of the step a transaction executed last.
+#[derive(Debug, Default)]
+struct LastStep(Option<(usize, u8, u64)>);
+
+impl<DB: Database> Inspector<DB> for LastStep {
+    fn step(&mut self, interp: &mut Interpreter, context: &mut EvmContext<DB>) {
+        self.0 = Some((
+            interp.program_counter(),
+            interp.current_opcode(),
+            context.journaled_state.depth(),
+        ));
+    }
+}
+
+fn tx_env(tx: &TxEnvelope, sender: Address) -> TxEnv {
+    TxEnv {
+        caller: sender,
+        gas_limit: tx.gas_limit(),
+        gas_price: U256::from(tx.max_fee_per_gas()),
+        transact_to: tx.kind(),
+        value: tx.value(),
+        data: tx.input().clone(),
+        nonce: Some(tx.nonce()),
+        chain_id: tx.chain_id(),
+        access_list: tx.access_list().cloned().unwrap_or_default(),
+        gas_priority_fee: tx.max_priority_fee_per_gas().map(U256::from),
+        authorization_list: tx
+            .authorization_list()
+            .map(|list| AuthorizationList::Signed(list.to_vec())),
+        // the L1 data fee is charged on the encoded transaction
+        #[cfg(feature = "scroll")]
+        scroll: revm::primitives::ScrollFields {
+            is_l1_msg: false,
+            rlp_bytes: Some(tx.encoded_2718().into()),
+        },
+        ..Default::default()
+    }
+}
+
+fn block_env(header: &Header, coinbase: Address, spec_id: SpecId) -> BlockEnv {
+    BlockEnv {
+        number: U256::from(header.number),
+        coinbase,
+        timestamp: U256::from(header.timestamp),
+        gas_limit: U256::from(header.gas_limit),
+        basefee: U256::from(header.base_fee_per_gas.unwrap_or_default()),
+        difficulty: header.difficulty,
+        prevrandao: Some(header.mix_hash),
+        blob_excess_gas_and_price: header
+            .excess_blob_gas
+            .map(|excess| BlobExcessGasAndPrice::new(excess, spec_id >= SpecId::PRAGUE)),
+    }
+}
+
+fn receipt_envelope(tx: &TxEnvelope, receipt: ReceiptWithBloom) -> ReceiptEnvelope {
+    match tx.ty() {
+        0 => ReceiptEnvelope::Legacy(receipt),
+        1 => ReceiptEnvelope::Eip2930(receipt),
+        2 => ReceiptEnvelope::Eip1559(receipt),
+        3 => ReceiptEnvelope::Eip4844(receipt),
+        _ => ReceiptEnvelope::Eip7702(receipt),
+    }
+}
+
+#[cfg(not(feature = "scroll"))]
+pub(super) fn recover_sender(tx: &TxEnvelope) -> Option<Address> {
+    tx.recover_signer().ok()
+}
+
+#[cfg(feature = "scroll")]
+pub(super) fn recover_sender(tx: &TxEnvelope) -> Option<Address> {
+    use scroll_alloy_consensus::ScrollTxEnvelope;
+    match tx {
+        ScrollTxEnvelope::L1Message(tx) => Some(tx.sender),
+        tx => tx
+            .signature()?
+            .recover_address_from_prehash(&tx.signature_hash())
+            .ok(),
+    }
+}
+
+fn empty_account() -> TrieAccount {
+    TrieAccount {
+        nonce: 0,
+        balance: U256::ZERO,
+        storage_root: EMPTY_ROOT_HASH,
+        code_hash: KECCAK_EMPTY,
+    }
+}
+
+/// Fails on Scroll before Euclid, whose state trie is a zkTrie until then: proofs are only built
+/// for the MPT.
+#[cfg_attr(not(feature = "scroll"), allow(unused_variables))]
+fn ensure_mpt(config: &ChainConfig, number: u64, timestamp: u64) -> Result<(), SpectreError> {
+    #[cfg(feature = "scroll")]
+    if Hardfork::active_at(config, number, timestamp) < Some(Hardfork::Euclid) {
+        return Err(SpectreError::Unsupported("a zkTrie state before Euclid"));
+    }
+    Ok(())
+}
+
+/// Where fees go: the fee vault on Scroll, the beneficiary otherwise.
+fn coinbase(config: &ChainConfig, header: &Header) -> Address {
+    config
+        .extra_fields
+        .get("scroll")
+        .and_then(|scroll| scroll.get("feeVaultAddress")?.as_str()?.parse().ok())
+        .unwrap_or(header.beneficiary)
+}
+
+/// Whether blocks carry a base fee: from London on, from Curie on Scroll.
+fn has_base_fee(config: &ChainConfig, number: u64) -> bool {
+    #[cfg(not(feature = "scroll"))]
+    let activation = config.london_block;
+    #[cfg(feature = "scroll")]
+    let activation = match Hardfork::Curie.activation(config) {
+        Some(ForkCondition::Block(block)) => Some(block),
+        _ => None,
+    };
+    activation.is_some_and(|block| number >= block)
+}
+
+fn next_base_fee(config: &ChainConfig, parent: &Header) -> Option<u64> {
+    if !has_base_fee(config, parent.number + 1) {
+        return None;
+    }
+    #[cfg(not(feature = "scroll"))]
+    let base_fee = parent.next_block_base_fee(BaseFeeParams::ethereum());
+    // the sequencer derives it from the L1 fee, it is kept as it is here
+    #[cfg(feature = "scroll")]
+    let base_fee = parent.base_fee_per_gas;
+    Some(base_fee.unwrap_or(INITIAL_BASE_FEE))
+}
+
+/// Header fields later forks add, for blocks without withdrawals, blobs or requests.
+fn fork_fields(config: &ChainConfig, header: &mut Header) {
+    // Scroll blocks carry none of them
+    if cfg!(feature = "scroll") {
+        return;
+    }
+    if config.is_shanghai_active_at_timestamp(header.timestamp) {
+        header.withdrawals_root = Some(EMPTY_WITHDRAWALS);
+    }
+    if config.is_cancun_active_at_timestamp(header.timestamp) {
+        header.blob_gas_used = Some(0);
+        header.excess_blob_gas = Some(0);
+        header.parent_beacon_block_root = Some(B256::ZERO);
+    }
+    if config.is_prague_active_at_timestamp(header.timestamp) {
+        header.requests_hash = Some(EMPTY_REQUESTS_HASH);
+    }
+}
+
+/// Accounts the system calls of Cancun and Prague read.
+fn system_accounts(config: &ChainConfig, timestamp: u64) -> Vec<Address> {
+    let mut accounts = vec![];
+    if cfg!(feature = "scroll") {
+        return accounts;
+    }
+    if config.is_cancun_active_at_timestamp(timestamp) {
+        accounts.extend([SYSTEM_ADDRESS, BEACON_ROOTS_ADDRESS]);
+    }
+    if config.is_prague_active_at_timestamp(timestamp) {
+        accounts.extend([
+            HISTORY_STORAGE_ADDRESS,
+            WITHDRAWAL_REQUEST_PREDEPLOY_ADDRESS,
+            CONSOLIDATION_REQUEST_PREDEPLOY_ADDRESS,
+        ]);
+    }
+    accounts
+}
+
+#[cfg(not(feature = "scroll"))]
+fn spec_id(config: &ChainConfig, number: u64, timestamp: u64) -> SpecId {
+    let block = |activation: Option<u64>| activation.is_some_and(|block| number >= block);
+    let time = |activation: Option<u64>| activation.is_some_and(|time| timestamp >= time);
+    [
+        (SpecId::OSAKA, time(config.osaka_time)),
+        (SpecId::PRAGUE, time(config.prague_time)),
+        (SpecId::CANCUN, time(config.cancun_time)),
+        (SpecId::SHANGHAI, time(config.shanghai_time)),
+        (SpecId::LONDON, block(config.london_block)),
+        (SpecId::BERLIN, block(config.berlin_block)),
+        (SpecId::ISTANBUL, block(config.istanbul_block)),
+        (SpecId::PETERSBURG, block(config.petersburg_block)),
+        (SpecId::CONSTANTINOPLE, block(config.constantinople_block)),
+        (SpecId::BYZANTIUM, block(config.byzantium_block)),
+        (SpecId::SPURIOUS_DRAGON, block(config.eip158_block)),
+        (SpecId::TANGERINE, block(config.eip150_block)),
+        (SpecId::HOMESTEAD, block(config.homestead_block)),
+    ]
+    .into_iter()
+    .find(|(_, active)| *active)
+    .map_or(SpecId::FRONTIER, |(spec_id, _)| spec_id)
+}
+
+#[cfg(feature = "scroll")]
+fn spec_id(config: &ChainConfig, number: u64, timestamp: u64) -> SpecId {
+    match Hardfork::active_at(config, number, timestamp) {
+        Some(Hardfork::Euclid | Hardfork::EuclidV2) => SpecId::EUCLID,
+        Some(Hardfork::Darwin | Hardfork::DarwinV2) => SpecId::DARWIN,
+        Some(Hardfork::Curie) => SpecId::CURIE,
+        None => SpecId::BERNOULLI,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+
+    #[tokio::test]
+    async fn test_revm_backend() {
+        let counter = address!("0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef");
+        let transaction = r#"
+            [[transactions]]
+            from = "alice"
+            to = "counter"
+            gas-price = "1 Gwei"
+            gas-limit = 100000
+            "#;
+        let builder: crate::SpectreBuilder = toml::from_str(&format!(
+            r#"
+            [[wallet]]
+            name = "alice"
+            [[alloc]]
+            address = "alice"
+            balance = "1 ether"
+            [[alloc]]
+            address = "{counter}"
+            name = "counter"
+            code = "PUSH0\nSLOAD\nPUSH1 0x01\nADD\nPUSH0\nSSTORE"
+            {transaction}
+            {transaction}
+            "#
+        ))
+        .unwrap();
+        let mut backend = RevmBackend::default();
+        let report = builder
+            .build()
+            .unwrap()
+            .trace_with(&mut backend)
+            .await
+            .unwrap();
+
+        assert!(report.transactions.iter().all(|tx| tx.outcome.success));
+        assert_eq!(backend.storage(counter, U256::ZERO), U256::from(2));
+        let step = backend
+            .last_step(report.transactions[0].outcome.hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((step.op.as_str(), step.depth), ("SSTORE", 1));
+        for verification in report.verify() {
+            assert!(verification.is_ok(), "{verification:?}");
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use alloy_primitives::{B256, Bytes, U256, keccak256};
+use alloy_rlp::{EMPTY_STRING_CODE, Encodable, Header};
+use alloy_trie::EMPTY_ROOT_HASH;
+use std::collections::{BTreeSet, HashMap, hash_map::Entry};
+
+/// A node a trie references by hash without it being known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Blinded(pub(super) B256);
+
+/// Nodes of Merkle Patricia tries by the hash of their encoding, shared by the state trie and the
+/// storage tries and kept for every root they had.
+///
+/// The tries are sparse: a forked state only holds the nodes revealed by the proofs fetched so
+/// far, and walking through another one fails with [`Blinded`].
+#[derive(Debug, Default)]
+pub(super) struct Nodes {
+    nodes: HashMap<B256, Bytes>,
+    /// Nodes updates read since the last [`Nodes::take_reads`].
+    reads: BTreeSet<B256>,
+    /// Nodes updates added since then, which the tries they started from do not have.
+    added: BTreeSet<B256>,
+}
+
+/// A decoded node. Children are references: the hash of their encoding as an RLP string, or the
+/// encoding itself when shorter than a hash.
+enum Node {
+    Empty,
+    Leaf(Vec<u8>, Vec<u8>),
+    Extension(Vec<u8>, Vec<u8>),
+    Branch(Box<[Vec<u8>; 16]>),
+}
+
+impl Nodes {
+    /// Adds the nodes of a proof, of whichever trie.
+    pub(super) fn reveal(&mut self, nodes: impl IntoIterator<Item = Bytes>) {
+        for node in nodes {
+            self.nodes.entry(keccak256(&node)).or_insert(node);
+        }
+    }
+
+    pub(super) fn get(&self, root: B256, key: B256) -> Result<Option<Vec<u8>>, Blinded> {
+        self.walk(root, key, |_, _| {})
+    }
+
+    /// The nodes on the path to `key`, proving its value or its absence.
+    pub(super) fn proof(&self, root: B256, key: B256) -> Result<Vec<Bytes>, Blinded> {
+        let mut proof = vec![];
+        self.walk(root, key, |_, node| proof.push(node.clone()))?;
+        Ok(proof)
+    }
+
+    /// Root of the trie with `key` set to `value`.
+    pub(super) fn insert(
+        &mut self,
+        root: B256,
+        key: B256,
+        value: Vec<u8>,
+    ) -> Result<B256, Blinded> {
+        let node = self.load_root(root)?;
+        let node = self.insert_at(node, &nibbles(key), value)?;
+        Ok(self.store_root(node))
+    }
+
+    /// Root of the trie without `key`.
+    ///
+    /// Removing a key can collapse its branch into the one child left, which is read to merge it
+    /// and fails with [`Blinded`] when it is not known.
+    pub(super) fn remove(&mut self, root: B256, key: B256) -> Result<B256, Blinded> {
+        let node = self.load_root(root)?;
+        let node = self.remove_at(node, &nibbles(key))?;
+        Ok(self.store_root(node))
+    }
+
+    /// The nodes updates read since the last call, out of the tries they started from.
+    pub(super) fn take_reads(&mut self) -> Vec<Bytes> {
+        self.added.clear();
+        std::mem::take(&mut self.reads)
+            .into_iter()
+            .map(|hash| self.nodes[&hash].clone())
+            .collect()
+    }
+
+    fn walk(
+        &self,
+        root: B256,
+        key: B256,
+        mut visit: impl FnMut(B256, &Bytes),
+    ) -> Result<Option<Vec<u8>>, Blinded> {
+        if root == EMPTY_ROOT_HASH {
+            return Ok(None);
+        }
+        let path = nibbles(key);
+        let mut path = path.as_slice();
+        let mut node = self.resolve(&word(root), &mut visit)?;
+        loop {
+            let child = match node {
+                Node::Empty => return Ok(None),
+                Node::Leaf(key, value) => return Ok((key == path).then_some(value)),
+                Node::Extension(key, child) => match path.strip_prefix(key.as_slice()) {
+                    Some(rest) => {
+                        path = rest;
+                        child
+                    }
+                    None => return Ok(None),
+                },
+                Node::Branch(mut children) => match path.split_first() {
+                    Some((&nibble, rest)) => {
+                        path = rest;
+                        std::mem::take(&mut children[nibble as usize])
+                    }
+                    None => return Ok(None),
+                },
+            };
+            node = self.resolve(&child, &mut visit)?;
+        }
+    }
+
+    fn resolve(
+        &self,
+        reference: &[u8],
+        visit: &mut impl FnMut(B256, &Bytes),
+    ) -> Result<Node, Blinded> {
+        if reference == [EMPTY_STRING_CODE] {
+            return Ok(Node::Empty);
+        }
+        let encoded = match hash_of(reference) {
+            Some(hash) => {
+                let node = self.nodes.get(&hash).ok_or(Blinded(hash))?;
+                visit(hash, node);
+                node
+            }
+            None => reference,
+        };
+        Ok(decode(encoded).expect("nodes are hashed as they are revealed"))
+    }
+
+    /// Resolves a node for an update, recording the read.
+    fn load(&mut self, reference: &[u8]) -> Result<Node, Blinded> {
+        let mut read = None;
+        let node = self.resolve(reference, &mut |hash, _| read = Some(hash))?;
+        if let Some(hash) = read.filter(|hash| !self.added.contains(hash)) {
+            self.reads.insert(hash);
+        }
+        Ok(node)
+    }
+
+    fn load_root(&mut self, root: B256) -> Result<Node, Blinded> {
+        match root {
+            EMPTY_ROOT_HASH => Ok(Node::Empty),
+            root => self.load(&word(root)),
+        }
+    }
+
+    /// Adds a node, returning the reference to it.
+    fn store(&mut self, node: Node) -> Vec<u8> {
+        let encoded = encode(&node);
+        if encoded.len() < 32 {
+            return encoded;
+        }
+        let hash = keccak256(&encoded);
+        self.add(hash, encoded);
+        word(hash)
+    }
+
+    /// Adds a root node, which is hashed whatever its length.
+    fn store_root(&mut self, node: Node) -> B256 {
+        if let Node::Empty = node {
+            return EMPTY_ROOT_HASH;
+        }
+        let encoded = encode(&node);
+        let hash = keccak256(&encoded);
+        self.add(hash, encoded);
+        hash
+    }
+
+    fn add(&mut self, hash: B256, encoded: Vec<u8>) {
+        if let Entry::Vacant(entry) = self.nodes.entry(hash) {
+            entry.insert(encoded.into());
+            self.added.insert(hash);
+        }
+    }
+
+    fn insert_at(&mut self, node: Node, path: &[u8], value: Vec<u8>) -> Result<Node, Blinded> {
+        Ok(match node {
+            Node::Empty => Node::Leaf(path.to_vec(), value),
+            Node::Leaf(key, _) if key == path => Node::Leaf(key, value),
+            // keys have the same length, neither is a prefix of the other
+            Node::Leaf(key, old) => {
+                let common = common_prefix(&key, path);
+                let mut children = empty_children();
+                children[key[common] as usize] =
+                    self.store(Node::Leaf(key[common + 1..].to_vec(), old));
+                children[path[common] as usize] =
+                    self.store(Node::Leaf(path[common + 1..].to_vec(), value));
+                self.extend(&path[..common], Node::Branch(children))
+            }
+            Node::Extension(key, child) if path.starts_with(&key) => {
+                let node = self.load(&child)?;
+                let node = self.insert_at(node, &path[key.len()..], value)?;
+                self.extend(&key, node)
+            }
+            Node::Extension(key, child) => {
+                let common = common_prefix(&key, path);
+                let mut children = empty_children();
+                children[key[common] as usize] = match &key[common + 1..] {
+                    [] => child,
+                    rest => self.store(Node::Extension(rest.to_vec(), child)),
+                };
+                children[path[common] as usize] =
+                    self.store(Node::Leaf(path[common + 1..].to_vec(), value));
+                self.extend(&path[..common], Node::Branch(children))
+            }
+            Node::Branch(mut children) => {
+                let nibble = path[0] as usize;
+                let child = self.load(&children[nibble])?;
+                let child = self.insert_at(child, &path[1..], value)?;
+                children[nibble] = self.store(child);
+                Node::Branch(children)
+            }
+        })
+    }
+
+    fn remove_at(&mut self, node: Node, path: &[u8]) -> Result<Node, Blinded> {
+        Ok(match node {
+            Node::Empty => Node::Empty,
+            Node::Leaf(key, _) if key == path => Node::Empty,
+            Node::Extension(key, child) if path.starts_with(&key) => {
+                let node = self.load(&child)?;
+                let node = self.remove_at(node, &path[key.len()..])?;
+                self.extend(&key, node)
+            }
+            Node::Branch(mut children) => {
+                let nibble = path[0] as usize;
+                let child = self.load(&children[nibble])?;
+                let child = self.remove_at(child, &path[1..])?;
+                children[nibble] = self.store(child);
+                let mut left = (0..16).filter(|&idx| children[idx] != [EMPTY_STRING_CODE]);
+                match (left.next(), left.next()) {
+                    (Some(only), None) => {
+                        let child = self.load(&children[only])?;
+                        self.extend(&[only as u8], child)
+                    }
+                    _ => Node::Branch(children),
+                }
+            }
+            node => node,
+        })
+    }
+
+    /// `node` moved under `prefix`, merged into it unless it is a branch.
+    fn extend(&mut self, prefix: &[u8], node: Node) -> Node {
+        if prefix.is_empty() {
+            return node;
+        }
+        match node {
+            Node::Empty => Node::Empty,
+            Node::Leaf(key, value) => Node::Leaf([prefix, &key].concat(), value),
+            Node::Extension(key, child) => Node::Extension([prefix, &key].concat(), child),
+            branch => Node::Extension(prefix.to_vec(), self.store(branch)),
+        }
+    }
+}
+
+pub(super) fn slot_key(slot: U256) -> B256 {
+    keccak256(slot.to_be_bytes::<32>())
+}
+
+fn nibbles(key: B256) -> Vec<u8> {
+    key.iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn empty_children() -> Box<[Vec<u8>; 16]> {
+    Box::new(std::array::from_fn(|_| vec![EMPTY_STRING_CODE]))
+}
+
+/// Reference to a node by its hash.
+fn word(hash: B256) -> Vec<u8> {
+    let mut reference = Vec::with_capacity(33);
+    hash.as_slice().encode(&mut reference);
+    reference
+}
+
+fn hash_of(reference: &[u8]) -> Option<B256> {
+    match reference {
+        [0xa0, hash @ ..] if hash.len() == 32 => Some(B256::from_slice(hash)),
+        _ => None,
+    }
+}
+
+fn encode(node: &Node) -> Vec<u8> {
+    let mut payload = vec![];
+    match node {
+        Node::Empty => return vec![EMPTY_STRING_CODE],
+        Node::Leaf(key, value) => {
+            compact(key, true).as_slice().encode(&mut payload);
+            value.as_slice().encode(&mut payload);
+        }
+        Node::Extension(key, child) => {
+            compact(key, false).as_slice().encode(&mut payload);
+            payload.extend_from_slice(child);
+        }
+        Node::Branch(children) => {
+            children
+                .iter()
+                .for_each(|child| payload.extend_from_slice(child));
+            // the value slot, unused with keys of a fixed length
+            payload.push(EMPTY_STRING_CODE);
+        }
+    }
+    let mut encoded = vec![];
+    Header {
+        list: true,
+        payload_length: payload.len(),
+    }
+    .encode(&mut encoded);
+    encoded.extend(payload);
+    encoded
+}
+
+fn decode(mut encoded: &[u8]) -> Option<Node> {
+    let mut payload = Header::decode_bytes(&mut encoded, true).ok()?;
+    let mut items = vec![];
+    while !payload.is_empty() {
+        let item = payload;
+        let header = Header::decode(&mut payload).ok()?;
+        let length = item.len() - payload.len() + header.payload_length;
+        items.push(&item[..length]);
+        payload = &item[length..];
+    }
+    let string = |mut item: &[u8]| Header::decode_bytes(&mut item, false).ok();
+    match items.as_slice() {
+        [key, child] => {
+            let (key, leaf) = uncompact(string(key)?)?;
+            Some(match leaf {
+                true => Node::Leaf(key, string(child)?.to_vec()),
+                false => Node::Extension(key, child.to_vec()),
+            })
+        }
+        [children @ .., _] if children.len() == 16 => {
+            Some(Node::Branch(Box::new(std::array::from_fn(|idx| {
+                children[idx].to_vec()
+            }))))
+        }
+        _ => None,
+    }
+}
+
+/// Hex-prefix encoding of a path, flagging leaves and odd lengths.
+fn compact(path: &[u8], leaf: bool) -> Vec<u8> {
+    let odd = path.len() % 2 == 1;
+    let flag = ((leaf as u8) << 1) | odd as u8;
+    let mut nibbles = vec![flag];
+    if !odd {
+        nibbles.push(0);
+    }
+    nibbles.extend_from_slice(path);
+    nibbles
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect()
+}
+
+fn uncompact(compact: &[u8]) -> Option<(Vec<u8>, bool)> {
+    let (&first, rest) = compact.split_first()?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return None;
+    }
+    let mut path = vec![];
+    if flag & 1 == 1 {
+        path.push(first & 0x0f);
+    }
+    path.extend(rest.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]));
+    Some((path, flag & 2 == 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_trie::{HashBuilder, Nibbles, proof::verify_proof};
+    use std::collections::BTreeMap;
+
+    fn leaves(range: std::ops::Range<u8>) -> BTreeMap<B256, Vec<u8>> {
+        // values long and short, for hashed and inlined nodes
+        range
+            .map(|byte| (keccak256([byte]), vec![byte + 1; 1 + byte as usize % 40]))
+            .collect()
+    }
+
+    fn expected_root(leaves: &BTreeMap<B256, Vec<u8>>) -> B256 {
+        let mut builder = HashBuilder::default();
+        for (key, value) in leaves {
+            builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        builder.root()
+    }
+
+    fn build(nodes: &mut Nodes, leaves: &BTreeMap<B256, Vec<u8>>) -> B256 {
+        leaves.iter().fold(EMPTY_ROOT_HASH, |root, (key, value)| {
+            nodes.insert(root, *key, value.clone()).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_root() {
+        let mut nodes = Nodes::default();
+        let all = leaves(0..60);
+        let mut root = build(&mut nodes, &all);
+        assert_eq!(root, expected_root(&all));
+        assert_eq!(
+            nodes.get(root, keccak256([7u8])).unwrap(),
+            Some(all[&keccak256([7u8])].clone())
+        );
+        assert_eq!(nodes.get(root, keccak256([70u8])).unwrap(), None);
+
+        for byte in 20..60u8 {
+            root = nodes.remove(root, keccak256([byte])).unwrap();
+        }
+        assert_eq!(root, expected_root(&leaves(0..20)));
+        for byte in 0..20u8 {
+            root = nodes.remove(root, keccak256([byte])).unwrap();
+        }
+        assert_eq!(root, EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut full = Nodes::default();
+        let all = leaves(0..60);
+        let root = build(&mut full, &all);
+        let key = keccak256([7u8]);
+        let proof = full.proof(root, key).unwrap();
+        verify_proof(root, Nibbles::unpack(key), Some(all[&key].clone()), &proof).unwrap();
+
+        let mut sparse = Nodes::default();
+        sparse.reveal(proof.clone());
+        assert_eq!(sparse.get(root, key).unwrap(), Some(all[&key].clone()));
+        assert!(sparse.get(root, keccak256([8u8])).is_err());
+
+        // an update on the revealed path reads its nodes only
+        sparse.take_reads();
+        let updated = sparse.insert(root, key, vec![0xff]).unwrap();
+        assert_eq!(updated, full.insert(root, key, vec![0xff]).unwrap());
+        let reads = sparse.take_reads();
+        assert!(!reads.is_empty() && reads.iter().all(|node| proof.contains(node)));
+    }
+
+    #[test]
+    fn test_remove_collapse() {
+        let mut full = Nodes::default();
+        let two = leaves(0..2);
+        let root = build(&mut full, &two);
+        let [first, second] = [keccak256([0u8]), keccak256([1u8])];
+
+        // the remaining leaf moves up into the root, it has to be known
+        let mut sparse = Nodes::default();
+        sparse.reveal(full.proof(root, first).unwrap());
+        let removed = sparse.remove(root, first);
+        if let Err(Blinded(hash)) = removed {
+            sparse.reveal(full.proof(root, second).unwrap());
+            assert!(
+                full.proof(root, second)
+                    .unwrap()
+                    .iter()
+                    .any(|node| keccak256(node) == hash)
+            );
+        }
+        let removed = sparse.remove(root, first).unwrap();
+        assert_eq!(removed, expected_root(&leaves(1..2)));
+    }
+}
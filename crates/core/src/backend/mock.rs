@@ -0,0 +1,175 @@
+use super::{
+    BlockInfo, ExecutionStep, TraceKind, TxEnvelope, TxOutcome, WitnessBackend,
+    memory::{block_witness, recover_sender},
+};
+use crate::core::{SpectreError, TxLabel};
+use alloy_consensus::{Header, Transaction};
+use alloy_eips::eip2718::Encodable2718;
+use alloy_genesis::Genesis;
+use alloy_primitives::{Address, B256, TxHash};
+use sbv_primitives::types::BlockWitness;
+use std::{collections::HashSet, sync::Mutex};
+
+/// Seals transactions in memory without executing them, for testing what
+/// [`Spectre::trace_with`](crate::Spectre::trace_with) does around a chain.
+///
+/// Every seal packs the queued transactions into one block, highest tip first like geth's miner.
+/// Calls to a reverting address fail, every transaction uses all of its gas. Debug traces echo
+/// the transaction, except for the prestate tracer the mock does not have. Struct logs also hold
+/// a `STOP` at the top frame and one in a call below it.
+#[derive(Debug, Default)]
+pub(crate) struct MockBackend {
+    pub(crate) reverting: HashSet<Address>,
+    chain_id: u64,
+    gas_limit: u64,
+    timestamp: u64,
+    queued: Vec<(TxLabel, TxEnvelope)>,
+    /// Sealed blocks after genesis, with the labels of their transactions.
+    pub(crate) blocks: Vec<(BlockInfo, Vec<TxLabel>)>,
+    /// Transactions whose failure was traced.
+    pub(crate) traced_failures: Mutex<Vec<TxHash>>,
+    /// Senders of the submitted transactions, in submission order.
+    pub(crate) senders: Vec<Address>,
+}
+
+impl MockBackend {
+    fn genesis_block(&self) -> BlockInfo {
+        BlockInfo {
+            number: 0,
+            hash: B256::ZERO,
+            state_root: B256::ZERO,
+            receipts_root: B256::ZERO,
+            timestamp: self.timestamp,
+            gas_limit: self.gas_limit,
+            gas_used: 0,
+            tx_count: 0,
+            base_fee: None,
+        }
+    }
+}
+
+impl WitnessBackend for MockBackend {
+    async fn apply_genesis(&mut self, genesis: Genesis) -> Result<(), SpectreError> {
+        self.chain_id = genesis.config.chain_id;
+        self.gas_limit = genesis.gas_limit;
+        self.timestamp = genesis.timestamp;
+        Ok(())
+    }
+
+    async fn nonce(&self, _address: Address) -> Result<u64, SpectreError> {
+        Ok(0)
+    }
+
+    async fn submit(&mut self, tx: TxEnvelope, label: TxLabel) -> Result<TxHash, SpectreError> {
+        let hash = tx.trie_hash();
+        self.senders.extend(recover_sender(&tx));
+        self.queued.push((label, tx));
+        Ok(hash)
+    }
+
+    async fn seal(&mut self) -> Result<Vec<TxOutcome>, SpectreError> {
+        let head = self.head().await?;
+        let number = head.number + 1;
+        let mut queued = self.queued.drain(..).enumerate().collect::<Vec<_>>();
+        // stable, so equal tips keep their arrival order
+        queued.sort_by_key(|(_, (_, tx))| std::cmp::Reverse(tx.effective_tip_per_gas(0)));
+
+        let mut outcomes = vec![];
+        let mut labels = vec![];
+        for (position, (submitted, (label, tx))) in queued.into_iter().enumerate() {
+            let success = !tx.to().is_some_and(|to| self.reverting.contains(&to));
+            let outcome = TxOutcome {
+                hash: tx.trie_hash(),
+                block_number: number,
+                position: position as u64,
+                success,
+                gas_used: tx.gas_limit(),
+                effective_gas_price: tx.max_fee_per_gas(),
+                contract_address: None,
+                logs: vec![],
+            };
+            outcomes.push((submitted, outcome));
+            labels.push(label);
+        }
+        let info = BlockInfo {
+            number,
+            hash: B256::with_last_byte(number as u8),
+            timestamp: head.timestamp + 1,
+            gas_used: outcomes.iter().map(|(_, outcome)| outcome.gas_used).sum(),
+            tx_count: outcomes.len(),
+            ..head
+        };
+        self.blocks.push((info, labels));
+
+        outcomes.sort_by_key(|(submitted, _)| *submitted);
+        Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
+    }
+
+    async fn head(&self) -> Result<BlockInfo, SpectreError> {
+        Ok(self
+            .blocks
+            .last()
+            .map(|(info, _)| *info)
+            .unwrap_or_else(|| self.genesis_block()))
+    }
+
+    async fn block(&self, number: u64) -> Result<BlockInfo, SpectreError> {
+        match number {
+            0 => Ok(self.genesis_block()),
+            _ => self
+                .blocks
+                .get(number as usize - 1)
+                .map(|(info, _)| *info)
+                .ok_or(SpectreError::MissingBlock(number)),
+        }
+    }
+
+    async fn dump_witness(&self, number: u64) -> Result<BlockWitness, SpectreError> {
+        let info = self.block(number).await?;
+        let header = Header {
+            number,
+            timestamp: info.timestamp,
+            gas_limit: info.gas_limit,
+            gas_used: info.gas_used,
+            ..Default::default()
+        };
+        Ok(block_witness(
+            self.chain_id,
+            &header,
+            B256::ZERO,
+            &[],
+            vec![],
+            vec![],
+        ))
+    }
+
+    async fn debug_trace(
+        &self,
+        hash: TxHash,
+        kind: TraceKind,
+    ) -> Result<serde_json::Value, SpectreError> {
+        if kind == TraceKind::PrestateTracer {
+            return Err(SpectreError::Unsupported("prestate traces"));
+        }
+        if kind == TraceKind::StructLogs {
+            return Ok(serde_json::json!({
+                "hash": hash,
+                "kind": kind,
+                "structLogs": [
+                    { "pc": 0, "op": "STOP", "depth": 1 },
+                    { "pc": 0, "op": "STOP", "depth": 2 },
+                ],
+            }));
+        }
+        Ok(serde_json::json!({ "hash": hash, "kind": kind }))
+    }
+
+    async fn last_step(&self, hash: TxHash) -> Result<Option<ExecutionStep>, SpectreError> {
+        self.traced_failures.lock().unwrap().push(hash);
+        Ok(Some(ExecutionStep {
+            pc: 0,
+            op: "REVERT".to_string(),
+            depth: 1,
+        }))
+    }
+}
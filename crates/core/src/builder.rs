@@ -1,15 +1,29 @@
-use crate::{Spectre, utils::*};
+use crate::{
+    Spectre,
+    artifact::{Artifact, ArtifactError, encode_call, encode_deployment},
+    asm::{CompileError, SourceMap, compile_mnemonic_file, compile_mnemonic_mapped},
+    backend::{AttachOptions, Backend, ForkOptions, MinerCredentials, TraceKind},
+    core::{
+        AllocFiles, FILLER_BALANCE, ForkTransition, ScheduledTransaction, TxOrder, filler_signer,
+    },
+    generate::GenerateBuilder,
+    hardfork::{ForkCondition, ForkTarget, Hardfork},
+    scenario::{Scenario, UseBuilder},
+    utils::*,
+    validate::{CodeOrigin, CodeWarning, EvmRules, check_built_code},
+};
 use alloy_consensus::{TxEip1559, TxEip2930, TxLegacy, TxType};
 use alloy_genesis::{ChainConfig, Genesis, GenesisAccount};
+use alloy_json_abi::JsonAbi;
 use alloy_primitives::{Address, B256, Bytes, TxKind, U256};
 use alloy_rpc_types_eth::AccessList;
 use alloy_serde::OtherFields;
 use alloy_signer_local::PrivateKeySigner;
 use rand::{SeedableRng, rngs::StdRng};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashMap},
-    path::PathBuf,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
     str::FromStr,
     time,
 };
@@ -21,6 +35,16 @@ use scroll_alloy_consensus::ScrollTypedTransaction as TypedTransaction;
 
 #[derive(Debug, thiserror::Error)]
 pub enum BuilderError {
+    // system errors
+    #[error("{field} is set without rpc-url")]
+    AttachWithoutRpcUrl { field: &'static str },
+    #[error("Invalid funding secret")]
+    InvalidFundingSecret,
+    #[error("fork-block is set without fork-url")]
+    ForkBlockWithoutUrl,
+    #[error("fork-url needs the revm backend")]
+    ForkWithoutRevm,
+
     // wallet errors
     #[error("Invalid secret of wallet#{idx}")]
     InvalidSecret { idx: usize },
@@ -32,12 +56,26 @@ pub enum BuilderError {
     AllocWalletNotFound { idx: usize, name: String },
     #[error("alloc#{idx}: Neither balance or defaults is set")]
     BalanceNotSet { idx: usize },
+    #[error("alloc#{idx}: name already taken: {name}")]
+    AllocNameTaken { idx: usize, name: String },
+    #[error("alloc#{idx}: address already allocated: {address}")]
+    AllocAddressTaken { idx: usize, address: Address },
     #[error("cannot compile code of alloc#{idx}({address:?}): {error:?}")]
     CompileError {
         idx: usize,
         address: Address,
         error: CompileError,
     },
+    #[error("alloc#{idx}: {field} and {field}-file cannot both be set")]
+    AllocFileConflict { idx: usize, field: &'static str },
+    #[error("alloc#{idx}: artifact cannot be combined with {field}")]
+    AllocArtifactConflict { idx: usize, field: &'static str },
+    #[error("alloc#{idx}: variables are set without an artifact")]
+    VariablesWithoutArtifact { idx: usize },
+    #[error("alloc#{idx}: a running node only takes the balance of allocs")]
+    AttachedAlloc { idx: usize },
+    #[error("alloc#{idx}: {error}")]
+    AllocArtifactError { idx: usize, error: ArtifactError },
 
     #[error("at least one transaction is required")]
     AtLeastOneTransaction,
@@ -45,6 +83,45 @@ pub enum BuilderError {
     UnexpectedTxType { idx: usize, tx_type: u8 },
     #[error("transaction#{idx}: Account not found: {name}")]
     TxAccountNotFound { idx: usize, name: String },
+    #[error("transaction#{idx}: deploy-code cannot be combined with {field}")]
+    DeployCodeConflict { idx: usize, field: &'static str },
+    #[error("transaction#{idx}: input and input-file cannot both be set")]
+    InputFileConflict { idx: usize },
+    #[error("transaction#{idx}: call cannot be combined with {field}")]
+    CallConflict { idx: usize, field: &'static str },
+    #[error("transaction#{idx}: call needs a `to` account")]
+    CallWithoutTo { idx: usize },
+    #[error("transaction#{idx}: call needs an artifact on the transaction or the called alloc")]
+    CallWithoutAbi { idx: usize },
+    #[error("transaction#{idx}: deploying an artifact cannot be combined with {field}")]
+    TxArtifactConflict { idx: usize, field: &'static str },
+    #[error("transaction#{idx}: args are set without call or an artifact to deploy")]
+    ArgsWithoutCall { idx: usize },
+    #[error("transaction#{idx}: {error}")]
+    TxArtifactError { idx: usize, error: ArtifactError },
+    #[error("transaction#{idx}: init-storage is set without deploy-code")]
+    InitStorageWithoutCode { idx: usize },
+    #[error("cannot compile deploy-code of transaction#{idx}: {error:?}")]
+    DeployCodeCompileError { idx: usize, error: CompileError },
+    #[error("transaction#{idx}: {error}")]
+    DeployCodeTooLarge { idx: usize, error: CodeTooLarge },
+    #[error(
+        "code has {} warnings: {}",
+        warnings.len(),
+        warnings.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    )]
+    CodeWarnings { warnings: Vec<CodeWarning> },
+
+    // file errors
+    #[error("cannot read {path:?}: {error}")]
+    ReadFile {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("invalid file {path:?}: {error}")]
+    InvalidFile { path: PathBuf, error: String },
+    #[error("cannot compile {path:?}: {error:?}")]
+    FileCompileError { path: PathBuf, error: CompileError },
 
     #[error("transaction#{idx}: Both gas price and default are not set")]
     GasPriceNotSet { idx: usize },
@@ -54,9 +131,43 @@ pub enum BuilderError {
     MaxPriorityFeePerGasNotSet { idx: usize },
     #[error("transaction#{idx}: Both gas limit and default are not set")]
     GasLimitNotSet { idx: usize },
+    #[error("transaction#{idx}: targeted fork {fork} is disabled")]
+    ForkTargetDisabled { idx: usize, fork: Hardfork },
+    #[error("transaction#{idx}: targeted fork {fork} is already active at genesis")]
+    ForkTargetAtGenesis { idx: usize, fork: Hardfork },
+
+    // scenario errors
+    #[error("use#{idx}: scenario {scenario} needs at least one holder")]
+    UseNoHolders { idx: usize, scenario: Scenario },
+    #[error("use#{idx}: holder not found: {name}")]
+    UseHolderNotFound { idx: usize, name: String },
+
+    // generate errors
+    #[error("generate: unsupported tx type {tx_type}")]
+    GenerateTxType { tx_type: u8 },
+    #[error("generate: sender wallet not found: {name}")]
+    GenerateSenderNotFound { name: String },
+    #[error("generate: all transaction kinds have zero weight")]
+    GenerateNoWeights,
+    #[error("generate: senders ran out of funds after {generated} transactions")]
+    GenerateNoFunds { generated: usize },
+
+    // chain config errors
+    #[error("chain: {fork} is enabled but {parent} is disabled")]
+    ForkParentDisabled {
+        fork: &'static str,
+        parent: &'static str,
+    },
+    #[error("chain: {fork} activates at {at}, before {parent} at {parent_at}")]
+    ForkOrder {
+        fork: &'static str,
+        at: u64,
+        parent: &'static str,
+        parent_at: u64,
+    },
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct SpectreBuilder {
     #[serde(default)]
@@ -73,18 +184,101 @@ pub struct SpectreBuilder {
     pub wallet: Vec<WalletBuilder>,
     #[serde(default)]
     pub transactions: Vec<TransactionBuilder>,
+    #[serde(default, rename = "use")]
+    pub scenarios: Vec<UseBuilder>,
+    #[serde(default)]
+    pub generate: Option<GenerateBuilder>,
+    /// Directory `%include`s in alloc code and blob files are relative to, the working directory if
+    /// unset.
+    #[serde(skip)]
+    pub base_dir: Option<PathBuf>,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct SystemBuilder {
     #[serde(default)]
     pub random_seed: Option<u64>,
+    /// Chain the transactions are traced on.
+    #[serde(default)]
+    pub backend: Backend,
     #[serde(default)]
     pub geth_path: Option<PathBuf>,
+    /// Running node to trace on instead of spawning geth, `ipc://` path or `http(s)://` URL.
+    #[serde(default)]
+    pub rpc_url: Option<String>,
+    /// Unlocks the miner of the running node, which otherwise has to seal blocks on its own.
+    #[serde(default)]
+    pub miner: Option<MinerCredentials>,
+    /// Key of a funded account on the running node, paying for the genesis balances.
+    #[serde(default)]
+    pub funding_secret: Option<B256>,
+    /// Node whose state the revm backend forks, `ipc://` path or `http(s)://` URL. Allocs are
+    /// written over its accounts.
+    #[serde(default)]
+    pub fork_url: Option<String>,
+    /// Block the fork executes on, the latest one if unset.
+    #[serde(default)]
+    pub fork_block: Option<u64>,
+    /// Seconds sealing waits for its transactions while the node keeps sealing blocks without
+    /// them. A node that stops sealing, or drops one of them, fails it sooner.
+    #[serde(default)]
+    pub tx_timeout: Option<u64>,
+    /// Whether transactions keep their declared order within a block, or the miner's.
+    #[serde(default)]
+    pub order: TxOrder,
+    /// Debug traces collected for every sent transaction, written next to the witnesses.
+    #[serde(default)]
+    pub traces: Vec<TraceKind>,
+    /// Fails the build on code warnings instead of printing them: opcodes or precompiles the
+    /// chain does not offer, and stack errors in mnemonic code.
+    #[serde(default)]
+    pub strict: bool,
 }
 
-#[derive(Debug, Default, Deserialize)]
+impl SystemBuilder {
+    fn attach_options(&self) -> Result<Option<AttachOptions>, BuilderError> {
+        let Some(rpc_url) = self.rpc_url.clone() else {
+            if self.miner.is_some() {
+                return Err(BuilderError::AttachWithoutRpcUrl { field: "miner" });
+            }
+            if self.funding_secret.is_some() {
+                return Err(BuilderError::AttachWithoutRpcUrl {
+                    field: "funding-secret",
+                });
+            }
+            return Ok(None);
+        };
+        let funding_key = self
+            .funding_secret
+            .map(|secret| PrivateKeySigner::from_bytes(&secret))
+            .transpose()
+            .map_err(|_| BuilderError::InvalidFundingSecret)?;
+        Ok(Some(AttachOptions {
+            rpc_url,
+            miner: self.miner.clone(),
+            funding_key,
+        }))
+    }
+
+    fn fork_options(&self) -> Result<Option<ForkOptions>, BuilderError> {
+        let Some(rpc_url) = self.fork_url.clone() else {
+            if self.fork_block.is_some() {
+                return Err(BuilderError::ForkBlockWithoutUrl);
+            }
+            return Ok(None);
+        };
+        if self.backend != Backend::Revm {
+            return Err(BuilderError::ForkWithoutRevm);
+        }
+        Ok(Some(ForkOptions {
+            rpc_url,
+            block: self.fork_block,
+        }))
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct DefaultsBuilder {
     #[serde(default)]
@@ -99,7 +293,7 @@ pub struct DefaultsBuilder {
     pub tx_gas_limit: Option<u64>,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct GenesisBuilder {
     #[serde(default = "default_zero")]
@@ -116,89 +310,106 @@ pub struct GenesisBuilder {
     pub coinbase: Address,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ChainConfigBuilder {
-    #[serde(default = "default_chain_id")]
-    pub chain_id: u64,
-
-    #[serde(default = "default_enabled")]
-    pub homestead_block: BoolOr<u64>,
-    #[serde(default)]
-    pub dao_fork_block: BoolOr<u64>,
-    #[serde(default = "default_true")]
-    pub dao_fork_support: bool,
-    #[serde(default = "default_enabled")]
-    pub eip150_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub eip155_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub eip158_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub byzantium_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub constantinople_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub petersburg_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub istanbul_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub muir_glacier_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub berlin_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub london_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub arrow_glacier_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub gray_glacier_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub merge_netsplit_block: BoolOr<u64>,
-    #[serde(default = "default_enabled")]
-    pub shanghai_time: BoolOr<u64>,
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Enables every fork up to this one, explicit fork keys take precedence.
+    #[serde(default)]
+    pub hardfork: Option<Hardfork>,
+
+    #[serde(default)]
+    pub homestead_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub dao_fork_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub dao_fork_support: Option<bool>,
+    #[serde(default)]
+    pub eip150_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub eip155_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub eip158_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub byzantium_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub constantinople_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub petersburg_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub istanbul_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub muir_glacier_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub berlin_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub london_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub arrow_glacier_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub gray_glacier_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub merge_netsplit_block: Option<BoolOr<u64>>,
+    #[serde(default)]
+    pub shanghai_time: Option<BoolOr<ForkTime>>,
 
     #[cfg(feature = "scroll")]
-    #[serde(default = "default_enabled")]
-    pub curie_block: BoolOr<u64>,
+    #[serde(default)]
+    pub curie_block: Option<BoolOr<u64>>,
     #[cfg(feature = "scroll")]
-    #[serde(default = "default_enabled")]
-    pub darwin_time: BoolOr<u64>,
+    #[serde(default)]
+    pub darwin_time: Option<BoolOr<ForkTime>>,
     #[cfg(feature = "scroll")]
-    #[serde(default = "default_enabled")]
-    pub darwinv2_time: BoolOr<u64>,
+    #[serde(default)]
+    pub darwinv2_time: Option<BoolOr<ForkTime>>,
     #[cfg(feature = "scroll")]
-    #[serde(default = "default_enabled")]
-    pub euclid_time: BoolOr<u64>,
+    #[serde(default)]
+    pub euclid_time: Option<BoolOr<ForkTime>>,
     #[cfg(feature = "scroll")]
-    #[serde(default = "default_enabled")]
-    pub euclidv2_time: BoolOr<u64>,
+    #[serde(default)]
+    pub euclidv2_time: Option<BoolOr<ForkTime>>,
 
-    #[cfg_attr(feature = "scroll", serde(default))]
-    #[cfg_attr(not(feature = "scroll"), serde(default = "default_enabled"))]
-    pub cancun_time: BoolOr<u64>,
-    #[cfg_attr(feature = "scroll", serde(default))]
-    #[cfg_attr(not(feature = "scroll"), serde(default = "default_enabled"))]
-    pub prague_time: BoolOr<u64>,
-    #[cfg_attr(feature = "scroll", serde(default))]
-    #[cfg_attr(not(feature = "scroll"), serde(default = "default_enabled"))]
-    pub osaka_time: BoolOr<u64>,
+    #[serde(default)]
+    pub cancun_time: Option<BoolOr<ForkTime>>,
+    #[serde(default)]
+    pub prague_time: Option<BoolOr<ForkTime>>,
+    #[serde(default)]
+    pub osaka_time: Option<BoolOr<ForkTime>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct AllocBuilder {
     pub address: String,
+    /// Lets transactions refer to this account by name, like a wallet.
+    #[serde(default)]
+    pub name: Option<String>,
     #[serde(default)]
     pub nonce: Option<u64>,
     #[serde(default)]
     pub balance: Option<Ether>,
     #[serde(default)]
     pub code: Option<String>,
+    /// Code read from a `.bin`, `.hex` or `.asm` file instead.
+    #[serde(default)]
+    pub code_file: Option<PathBuf>,
     #[serde(default)]
     pub storage: BTreeMap<U256, U256>,
+    /// Storage read from a `.json` map of slots to values, or consecutive words from slot 0 in
+    /// a `.bin`, `.hex` or `.asm` file.
+    #[serde(default)]
+    pub storage_file: Option<PathBuf>,
+    /// Foundry or Hardhat artifact to take the runtime code and ABI from.
+    #[serde(default)]
+    pub artifact: Option<PathBuf>,
+    /// Storage set by variable name, `balanceOf[alice]` for mappings, using the storage layout
+    /// of the artifact.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct WalletBuilder {
     pub name: String,
@@ -206,9 +417,12 @@ pub struct WalletBuilder {
     pub secret: Option<B256>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct TransactionBuilder {
+    /// Alias naming the transaction in errors.
+    #[serde(default)]
+    pub name: Option<String>,
     #[serde(rename = "type")]
     #[serde(default)]
     pub transaction_type: u8,
@@ -227,12 +441,42 @@ pub struct TransactionBuilder {
     pub value: Option<Ether>,
     #[serde(default)]
     pub input: Option<Bytes>,
+    /// Input read from a `.bin`, `.hex` or `.asm` file instead.
+    #[serde(default)]
+    pub input_file: Option<PathBuf>,
+    /// Runtime code to deploy, hex or mnemonic like alloc code. Spectre wraps it in init code
+    /// and sends it as the input of a CREATE transaction.
+    #[serde(default)]
+    pub deploy_code: Option<String>,
+    /// Storage the init code of `deploy-code` writes before returning the runtime code.
+    #[serde(default)]
+    pub init_storage: BTreeMap<U256, U256>,
+    /// Foundry or Hardhat artifact, deployed when `to` is unset. Its ABI encodes `call` and
+    /// decodes the logs of the contract, with `to` it needs a `call`.
+    #[serde(default)]
+    pub artifact: Option<PathBuf>,
+    /// Function to call, by name or full signature, encoded with the ABI of the artifact or of
+    /// the called alloc.
+    #[serde(default)]
+    pub call: Option<String>,
+    /// Arguments of `call`, or of the constructor when deploying an artifact.
+    #[serde(default)]
+    pub args: Vec<String>,
     #[serde(default)]
     pub access_list: Option<AccessList>,
+    /// Lands the transaction in the last block before or the first block after a fork.
+    #[serde(default)]
+    pub at: Option<ForkTarget>,
 }
 
 impl SpectreBuilder {
-    pub fn build(self) -> Result<Spectre, BuilderError> {
+    /// Resolves everything drawn from the random seed: wallet secrets are filled in, `[[use]]`
+    /// scenarios are expanded into allocs and transactions, then the `[generate]` section is
+    /// expanded into transactions.
+    ///
+    /// The returned builder builds the same [`Spectre`] without relying on the seed, so it can
+    /// be written out for reproduction.
+    pub fn expand(mut self) -> Result<SpectreBuilder, BuilderError> {
         // for deterministic tests
         let mut rng = if let Some(random_seed) = self.system.random_seed {
             StdRng::seed_from_u64(random_seed)
@@ -242,7 +486,7 @@ impl SpectreBuilder {
 
         let wallets_by_name = self
             .wallet
-            .into_iter()
+            .iter_mut()
             .enumerate()
             .map(|(idx, wallet)| {
                 if let Some(secret) = wallet.secret {
@@ -251,45 +495,189 @@ impl SpectreBuilder {
                 } else {
                     Ok(PrivateKeySigner::random_with(&mut rng))
                 }
-                .map(|signer| (wallet.name, signer))
+                .map(|signer| {
+                    wallet.secret = Some(signer.to_bytes());
+                    (wallet.name.clone(), signer)
+                })
+            })
+            .collect::<Result<HashMap<String, PrivateKeySigner>, _>>()?;
+
+        let names = wallets_by_name
+            .iter()
+            .map(|(name, wallet)| (name.clone(), wallet.address()))
+            .collect::<HashMap<String, Address>>();
+        for (idx, scenario) in std::mem::take(&mut self.scenarios).into_iter().enumerate() {
+            let (alloc, transactions) = scenario.expand(idx, &names)?;
+            self.alloc.extend(alloc);
+            self.transactions.extend(transactions);
+        }
+
+        if let Some(generate) = self.generate.take() {
+            let transactions = generate.generate(&mut rng, &self, &wallets_by_name)?;
+            self.transactions.extend(transactions);
+        }
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<Spectre, BuilderError> {
+        let this = self.expand()?;
+
+        let wallets_by_name = this
+            .wallet
+            .into_iter()
+            .enumerate()
+            .map(|(idx, wallet)| {
+                wallet
+                    .secret
+                    .and_then(|secret| PrivateKeySigner::from_bytes(&secret).ok())
+                    .ok_or(BuilderError::InvalidSecret { idx })
+                    .map(|signer| (wallet.name, signer))
             })
             .collect::<Result<HashMap<String, PrivateKeySigner>, _>>()?;
         let wallets = wallets_by_name
             .iter()
             .map(|(_, wallet)| (wallet.address(), wallet.clone()))
             .collect::<HashMap<Address, PrivateKeySigner>>();
+        let mut names = wallets_by_name
+            .iter()
+            .map(|(name, wallet)| (name.clone(), wallet.address()))
+            .collect::<HashMap<String, Address>>();
+        for (idx, alloc) in this.alloc.iter().enumerate() {
+            let Some(name) = &alloc.name else {
+                continue;
+            };
+            let address = resolve_address(&alloc.address, &names).ok_or(
+                BuilderError::AllocWalletNotFound {
+                    idx,
+                    name: alloc.address.clone(),
+                },
+            )?;
+            if names.insert(name.clone(), address).is_some() {
+                return Err(BuilderError::AllocNameTaken {
+                    idx,
+                    name: name.clone(),
+                });
+            }
+        }
 
-        let alloc = self
+        let attach = this.system.attach_options()?;
+        let fork = this.system.fork_options()?;
+        let chain_config = this.chain.build(this.genesis.timestamp)?;
+        let rules = EvmRules::at(&chain_config, 0, this.genesis.timestamp);
+        let base_dir = this.base_dir.as_deref().unwrap_or(Path::new(""));
+        let mut checked = CheckedCode::default();
+        let mut alloc_files = BTreeMap::new();
+        // e.g. two `[[use]]` of the CREATE2 factory, which lives at a fixed address
+        let mut allocated = HashSet::new();
+        let alloc = this
             .alloc
             .into_iter()
             .enumerate()
-            .map(|(idx, alloc)| alloc.build_with(idx, &wallets_by_name, &self.defaults))
-            .collect::<Result<BTreeMap<Address, GenesisAccount>, _>>()?;
-
-        let chain_config = self.chain.build();
-        let genesis = self.genesis.build_with(chain_config, alloc);
+            .map(|(idx, alloc)| {
+                let (address, account, files) = alloc.build_with(
+                    idx,
+                    &names,
+                    &this.defaults,
+                    base_dir,
+                    &rules,
+                    &mut checked,
+                )?;
+                if !allocated.insert(address) {
+                    return Err(BuilderError::AllocAddressTaken { idx, address });
+                }
+                if files.code.is_some() || files.storage.is_some() {
+                    alloc_files.insert(address, files);
+                }
+                let written = account.code.as_ref().is_some_and(|code| !code.is_empty())
+                    || account
+                        .storage
+                        .as_ref()
+                        .is_some_and(|storage| !storage.is_empty())
+                    || account.nonce.is_some_and(|nonce| nonce != 0);
+                if attach.is_some() && written {
+                    return Err(BuilderError::AttachedAlloc { idx });
+                }
+                Ok((address, account))
+            })
+            .collect::<Result<BTreeMap<Address, GenesisAccount>, BuilderError>>()?;
+        let mut genesis = this.genesis.build_with(chain_config, alloc);
 
-        if self.transactions.is_empty() {
+        if this.transactions.is_empty() {
             return Err(BuilderError::AtLeastOneTransaction);
         }
-        let transactions = self
+        let transactions = this
             .transactions
             .into_iter()
             .enumerate()
             .map(|(idx, transaction)| {
-                transaction.build_with(idx, &genesis, &wallets_by_name, &self.defaults)
+                transaction.build_with(
+                    idx,
+                    &genesis,
+                    &names,
+                    &this.defaults,
+                    base_dir,
+                    &mut checked,
+                )
             })
             .collect::<Result<Vec<_>, _>>()?;
+        // filler blocks lead up to a fork activating by block, paid by an account of their own
+        let fills = transactions.iter().any(|scheduled| {
+            scheduled
+                .fork_transition
+                .is_some_and(|transition| matches!(transition.activation, ForkCondition::Block(_)))
+        });
+        if fills {
+            let account = GenesisAccount {
+                balance: FILLER_BALANCE,
+                ..Default::default()
+            };
+            genesis.alloc.insert(filler_signer().address(), account);
+        }
+
+        let CheckedCode {
+            warnings,
+            source_maps,
+            abis,
+        } = checked;
+        if this.system.strict && !warnings.is_empty() {
+            return Err(BuilderError::CodeWarnings { warnings });
+        }
+        for warning in &warnings {
+            warn!(%warning);
+        }
 
         Ok(Spectre {
-            geth_path: self.system.geth_path,
+            backend: this.system.backend,
+            geth_path: this.system.geth_path,
+            attach,
+            fork,
+            tx_timeout: this.system.tx_timeout.map(time::Duration::from_secs),
+            order: this.system.order,
+            traces: this.system.traces,
             genesis,
             wallets,
             transactions,
+            alloc_files,
+            warnings,
+            source_maps,
+            abis,
         })
     }
 }
 
+impl Default for GenesisBuilder {
+    fn default() -> Self {
+        GenesisBuilder {
+            nonce: default_zero(),
+            timestamp: default_now(),
+            gas_limit: default_block_gas_limit(),
+            difficulty: default_difficulty(),
+            mix_hash: B256::ZERO,
+            coinbase: Address::ZERO,
+        }
+    }
+}
+
 impl GenesisBuilder {
     fn build_with(
         self,
@@ -313,24 +701,106 @@ impl GenesisBuilder {
 }
 
 impl ChainConfigBuilder {
-    fn build(self) -> ChainConfig {
+    fn build(self, genesis_timestamp: u64) -> Result<ChainConfig, BuilderError> {
+        let preset = self.hardfork.unwrap_or_default();
+        // forks not covered by the presets follow the network defaults
+        #[cfg(not(feature = "scroll"))]
+        let (dao_fork, post_london, cancun, prague, osaka) = (
+            true,
+            preset >= Hardfork::Shanghai,
+            preset >= Hardfork::Cancun,
+            preset >= Hardfork::Prague,
+            preset >= Hardfork::Osaka,
+        );
+        #[cfg(feature = "scroll")]
+        let (dao_fork, post_london, cancun, prague, osaka) = (false, true, false, false, false);
+
+        let fork_time = |explicit: Option<BoolOr<ForkTime>>, enabled: bool| {
+            fork_activation(explicit, enabled).map(|time| time.resolve(genesis_timestamp))
+        };
+
+        let homestead_block = fork_activation(self.homestead_block, true);
+        let dao_fork_block = fork_activation(self.dao_fork_block, dao_fork);
+        let eip150_block = fork_activation(self.eip150_block, true);
+        let eip155_block = fork_activation(self.eip155_block, true);
+        let eip158_block = fork_activation(self.eip158_block, true);
+        let byzantium_block = fork_activation(self.byzantium_block, true);
+        let constantinople_block = fork_activation(self.constantinople_block, true);
+        let petersburg_block = fork_activation(self.petersburg_block, true);
+        let istanbul_block = fork_activation(self.istanbul_block, true);
+        let muir_glacier_block = fork_activation(self.muir_glacier_block, true);
+        let berlin_block = fork_activation(self.berlin_block, true);
+        let london_block = fork_activation(self.london_block, true);
+        let arrow_glacier_block = fork_activation(self.arrow_glacier_block, post_london);
+        let gray_glacier_block = fork_activation(self.gray_glacier_block, post_london);
+        let merge_netsplit_block = fork_activation(self.merge_netsplit_block, post_london);
+        let shanghai_time = fork_time(self.shanghai_time, post_london);
+        let cancun_time = fork_time(self.cancun_time, cancun);
+        let prague_time = fork_time(self.prague_time, prague);
+        let osaka_time = fork_time(self.osaka_time, osaka);
+
+        #[cfg(feature = "scroll")]
+        let curie_block = fork_activation(self.curie_block, preset >= Hardfork::Curie);
+        #[cfg(feature = "scroll")]
+        let darwin_time = fork_time(self.darwin_time, preset >= Hardfork::Darwin);
+        #[cfg(feature = "scroll")]
+        let darwinv2_time = fork_time(self.darwinv2_time, preset >= Hardfork::DarwinV2);
+        #[cfg(feature = "scroll")]
+        let euclid_time = fork_time(self.euclid_time, preset >= Hardfork::Euclid);
+        #[cfg(feature = "scroll")]
+        let euclidv2_time = fork_time(self.euclidv2_time, preset >= Hardfork::EuclidV2);
+
+        check_fork_order(&[
+            ("homestead-block", homestead_block, false),
+            ("dao-fork-block", dao_fork_block, true),
+            ("eip150-block", eip150_block, false),
+            ("eip155-block", eip155_block, false),
+            ("eip158-block", eip158_block, false),
+            ("byzantium-block", byzantium_block, false),
+            ("constantinople-block", constantinople_block, false),
+            ("petersburg-block", petersburg_block, false),
+            ("istanbul-block", istanbul_block, false),
+            ("muir-glacier-block", muir_glacier_block, true),
+            ("berlin-block", berlin_block, false),
+            ("london-block", london_block, false),
+            ("arrow-glacier-block", arrow_glacier_block, true),
+            ("gray-glacier-block", gray_glacier_block, true),
+            ("merge-netsplit-block", merge_netsplit_block, true),
+            #[cfg(feature = "scroll")]
+            ("curie-block", curie_block, false),
+        ])?;
+        check_fork_order(&[
+            ("shanghai-time", shanghai_time, false),
+            #[cfg(feature = "scroll")]
+            ("darwin-time", darwin_time, false),
+            #[cfg(feature = "scroll")]
+            ("darwinv2-time", darwinv2_time, false),
+            #[cfg(feature = "scroll")]
+            ("euclid-time", euclid_time, false),
+            #[cfg(feature = "scroll")]
+            ("euclidv2-time", euclidv2_time, false),
+            ("cancun-time", cancun_time, false),
+            ("prague-time", prague_time, false),
+            ("osaka-time", osaka_time, false),
+        ])?;
+
         #[allow(unused_mut)]
         let mut extra_fields = OtherFields::default();
         #[cfg(feature = "scroll")]
         {
-            if let Some(curie_block) = self.curie_block.into_option() {
+            if let Some(curie_block) = curie_block {
                 extra_fields.insert("curieBlock".to_string(), curie_block.into());
             }
-            if let Some(darwin_time) = self.darwin_time.into_option() {
+            if let Some(darwin_time) = darwin_time {
                 extra_fields.insert("darwinTime".to_string(), darwin_time.into());
             }
-            if let Some(darwinv2_time) = self.darwinv2_time.into_option() {
+            if let Some(darwinv2_time) = darwinv2_time {
                 extra_fields.insert("darwinv2Time".to_string(), darwinv2_time.into());
             }
-            if let Some(euclid_time) = self.euclid_time.into_option() {
+            if let Some(euclid_time) = euclid_time {
                 extra_fields.insert("euclidTime".to_string(), euclid_time.into());
             }
-            if let Some(euclidv2_time) = self.euclidv2_time.into_option() {
+            if let Some(euclidv2_time) = euclidv2_time {
                 extra_fields.insert("euclidv2Time".to_string(), euclidv2_time.into());
             }
             extra_fields.insert(
@@ -342,28 +812,28 @@ impl ChainConfigBuilder {
             )
         };
 
-        ChainConfig {
-            chain_id: self.chain_id,
-            homestead_block: self.homestead_block.into_option(),
-            dao_fork_block: self.dao_fork_block.into_option(),
-            dao_fork_support: self.dao_fork_support,
-            eip150_block: self.eip150_block.into_option(),
-            eip155_block: self.eip155_block.into_option(),
-            eip158_block: self.eip158_block.into_option(),
-            byzantium_block: self.byzantium_block.into_option(),
-            constantinople_block: self.constantinople_block.into_option(),
-            petersburg_block: self.petersburg_block.into_option(),
-            istanbul_block: self.istanbul_block.into_option(),
-            muir_glacier_block: self.muir_glacier_block.into_option(),
-            berlin_block: self.berlin_block.into_option(),
-            london_block: self.london_block.into_option(),
-            arrow_glacier_block: self.arrow_glacier_block.into_option(),
-            gray_glacier_block: self.gray_glacier_block.into_option(),
-            merge_netsplit_block: self.merge_netsplit_block.into_option(),
-            shanghai_time: self.shanghai_time.into_option(),
-            cancun_time: self.cancun_time.into_option(),
-            prague_time: self.prague_time.into_option(),
-            osaka_time: self.osaka_time.into_option(),
+        Ok(ChainConfig {
+            chain_id: self.chain_id.unwrap_or_else(default_chain_id),
+            homestead_block,
+            dao_fork_block,
+            dao_fork_support: self.dao_fork_support.unwrap_or(true),
+            eip150_block,
+            eip155_block,
+            eip158_block,
+            byzantium_block,
+            constantinople_block,
+            petersburg_block,
+            istanbul_block,
+            muir_glacier_block,
+            berlin_block,
+            london_block,
+            arrow_glacier_block,
+            gray_glacier_block,
+            merge_netsplit_block,
+            shanghai_time,
+            cancun_time,
+            prague_time,
+            osaka_time,
             terminal_total_difficulty: None,
             terminal_total_difficulty_passed: false,
             ethash: None,
@@ -372,7 +842,7 @@ impl ChainConfigBuilder {
             extra_fields,
             deposit_contract_address: None,
             blob_schedule: Default::default(),
-        }
+        })
     }
 }
 
@@ -380,27 +850,86 @@ impl AllocBuilder {
     fn build_with(
         self,
         idx: usize,
-        wallets: &HashMap<String, PrivateKeySigner>,
+        names: &HashMap<String, Address>,
         defaults: &DefaultsBuilder,
-    ) -> Result<(Address, GenesisAccount), BuilderError> {
+        base_dir: &Path,
+        rules: &EvmRules,
+        checked: &mut CheckedCode,
+    ) -> Result<(Address, GenesisAccount, AllocFiles), BuilderError> {
         let address =
-            resolve_address(&self.address, wallets).ok_or(BuilderError::AllocWalletNotFound {
+            resolve_address(&self.address, names).ok_or(BuilderError::AllocWalletNotFound {
                 idx,
                 name: self.address.clone(),
             })?;
 
-        let code = match self.code {
-            Some(s) => Some(
-                Bytes::from_str(&s)
-                    .or_else(|_| compile_mnemonic(&s))
-                    .map_err(|e| BuilderError::CompileError {
+        let code_file = self.code_file.map(|path| base_dir.join(path));
+        let artifact_file = self.artifact.map(|path| base_dir.join(path));
+        let artifact = match &artifact_file {
+            Some(path) => Some(
+                Artifact::load(path)
+                    .map_err(|error| BuilderError::AllocArtifactError { idx, error })?,
+            ),
+            None => None,
+        };
+        let code = match (self.code, &code_file, &artifact) {
+            (Some(_), Some(_), _) => {
+                return Err(BuilderError::AllocFileConflict { idx, field: "code" });
+            }
+            (Some(_), _, Some(_)) => {
+                return Err(BuilderError::AllocArtifactConflict { idx, field: "code" });
+            }
+            (_, Some(_), Some(_)) => {
+                return Err(BuilderError::AllocArtifactConflict {
+                    idx,
+                    field: "code-file",
+                });
+            }
+            (Some(s), None, None) => {
+                Some(
+                    compile_code(&s, base_dir).map_err(|e| BuilderError::CompileError {
                         idx,
                         address,
                         error: e,
                     })?,
-            ),
-            None => None,
+                )
+            }
+            (None, Some(path), None) => Some(read_blob(path)?),
+            (None, None, Some(artifact)) => Some((artifact.deployed_bytecode.clone(), None)),
+            (None, None, None) => None,
+        };
+        let code = code.map(|(code, source_map)| {
+            checked.check(&code, source_map, rules, CodeOrigin::Alloc(address));
+            code
+        });
+        // an artifact is listed as the file the code came from
+        let files = AllocFiles {
+            code: code_file.or(artifact_file),
+            storage: self.storage_file.map(|path| base_dir.join(path)),
+        };
+        let mut storage = match &files.storage {
+            Some(_) if !self.storage.is_empty() => {
+                return Err(BuilderError::AllocFileConflict {
+                    idx,
+                    field: "storage",
+                });
+            }
+            Some(path) => read_storage(path)?,
+            None => self.storage,
         };
+        match artifact {
+            Some(artifact) => {
+                artifact
+                    .write_variables(&self.variables, names, &mut storage)
+                    .map_err(|error| BuilderError::AllocArtifactError { idx, error })?;
+                checked
+                    .abis
+                    .insert(CodeOrigin::Alloc(address), artifact.abi);
+            }
+            None if !self.variables.is_empty() => {
+                return Err(BuilderError::VariablesWithoutArtifact { idx });
+            }
+            None => {}
+        }
 
         let account = GenesisAccount {
             nonce: self.nonce,
@@ -411,7 +940,7 @@ impl AllocBuilder {
                 .0,
             code,
             storage: Some(
-                self.storage
+                storage
                     .into_iter()
                     .map(|(k, v)| (B256::from(k.to_be_bytes()), B256::from(v.to_be_bytes())))
                     .collect(),
@@ -419,7 +948,7 @@ impl AllocBuilder {
             private_key: None,
         };
 
-        Ok((address, account))
+        Ok((address, account, files))
     }
 }
 
@@ -428,24 +957,31 @@ impl TransactionBuilder {
         self,
         idx: usize,
         genesis: &Genesis,
-        wallets: &HashMap<String, PrivateKeySigner>,
+        names: &HashMap<String, Address>,
         defaults: &DefaultsBuilder,
-    ) -> Result<(Address, TypedTransaction), BuilderError> {
-        let tx_type = TxType::try_from(self.transaction_type).map_err(|_| {
-            BuilderError::UnexpectedTxType {
-                idx,
-                tx_type: self.transaction_type,
+        base_dir: &Path,
+        checked: &mut CheckedCode,
+    ) -> Result<ScheduledTransaction, BuilderError> {
+        let name = self.name.clone();
+        // blob and set-code transactions need fields the builder does not take
+        let tx_type = match TxType::try_from(self.transaction_type) {
+            Ok(tx_type @ (TxType::Legacy | TxType::Eip2930 | TxType::Eip1559)) => tx_type,
+            _ => {
+                return Err(BuilderError::UnexpectedTxType {
+                    idx,
+                    tx_type: self.transaction_type,
+                });
             }
-        })?;
+        };
 
         let chain_id = genesis.config.chain_id;
 
-        let from = resolve_address(&self.from, wallets).ok_or(BuilderError::TxAccountNotFound {
+        let from = resolve_address(&self.from, names).ok_or(BuilderError::TxAccountNotFound {
             idx,
             name: self.from.clone(),
         })?;
         let to = match self.to {
-            Some(ref to) => Some(resolve_address(to, wallets).ok_or(
+            Some(ref to) => Some(resolve_address(to, names).ok_or(
                 BuilderError::TxAccountNotFound {
                     idx,
                     name: to.clone(),
@@ -453,6 +989,120 @@ impl TransactionBuilder {
             )?),
             None => None,
         };
+        let fork_transition = match self.at {
+            Some(target) => Some(fork_transition(idx, target, genesis)?),
+            None => None,
+        };
+
+        // deployments land after genesis, in the block targeted by `at` if any
+        let deployment_rules = || {
+            let (number, timestamp) = match fork_transition {
+                Some(ForkTransition {
+                    target: ForkTarget::After(_),
+                    activation,
+                }) => match activation {
+                    ForkCondition::Block(number) => (number, genesis.timestamp),
+                    ForkCondition::Timestamp(timestamp) => (1, timestamp),
+                },
+                _ => (1, genesis.timestamp),
+            };
+            EvmRules::at(&genesis.config, number, timestamp)
+        };
+
+        let input_file = self.input_file.map(|path| base_dir.join(path));
+        let input_field = match input_file {
+            Some(_) => "input-file",
+            None => "input",
+        };
+        let input = match (self.input, &input_file) {
+            (Some(_), Some(_)) => return Err(BuilderError::InputFileConflict { idx }),
+            (Some(input), None) => Some(input),
+            (None, Some(path)) => Some(read_blob(path)?.0),
+            (None, None) => None,
+        };
+        let artifact = match &self.artifact {
+            Some(path) => Some(
+                Artifact::load(&base_dir.join(path))
+                    .map_err(|error| BuilderError::TxArtifactError { idx, error })?,
+            ),
+            None => None,
+        };
+        let input = match (&self.call, &artifact) {
+            (Some(call), _) => {
+                let Some(to) = to else {
+                    return Err(BuilderError::CallWithoutTo { idx });
+                };
+                if input.is_some() {
+                    let field = input_field;
+                    return Err(BuilderError::CallConflict { idx, field });
+                }
+                let abi = artifact
+                    .as_ref()
+                    .map(|artifact| &artifact.abi)
+                    .or_else(|| checked.abis.get(&CodeOrigin::Alloc(to)))
+                    .ok_or(BuilderError::CallWithoutAbi { idx })?;
+                let input = encode_call(abi, call, &self.args, names)
+                    .map_err(|error| BuilderError::TxArtifactError { idx, error })?;
+                Some(input)
+            }
+            (None, Some(artifact)) if to.is_none() => {
+                if input.is_some() {
+                    let field = input_field;
+                    return Err(BuilderError::TxArtifactConflict { idx, field });
+                }
+                if self.deploy_code.is_some() {
+                    let field = "deploy-code";
+                    return Err(BuilderError::TxArtifactConflict { idx, field });
+                }
+                let origin = CodeOrigin::Deployment(idx);
+                checked.check(
+                    &artifact.deployed_bytecode,
+                    None,
+                    &deployment_rules(),
+                    origin,
+                );
+                let input = encode_deployment(artifact, &self.args, names)
+                    .map_err(|error| BuilderError::TxArtifactError { idx, error })?;
+                Some(input)
+            }
+            (None, _) if !self.args.is_empty() => {
+                return Err(BuilderError::ArgsWithoutCall { idx });
+            }
+            // the called alloc keeps its code, the artifact would only be ignored
+            (None, Some(_)) => {
+                return Err(BuilderError::TxArtifactConflict { idx, field: "to" });
+            }
+            (None, None) => input,
+        };
+        if let Some(artifact) = artifact {
+            let origin = match to {
+                Some(to) => CodeOrigin::Alloc(to),
+                None => CodeOrigin::Deployment(idx),
+            };
+            checked.abis.entry(origin).or_insert(artifact.abi);
+        }
+
+        let input = match self.deploy_code {
+            Some(ref code) => {
+                if to.is_some() {
+                    return Err(BuilderError::DeployCodeConflict { idx, field: "to" });
+                }
+                if input.is_some() {
+                    let field = input_field;
+                    return Err(BuilderError::DeployCodeConflict { idx, field });
+                }
+                let (runtime, source_map) = compile_code(code, base_dir)
+                    .map_err(|error| BuilderError::DeployCodeCompileError { idx, error })?;
+                let rules = deployment_rules();
+                checked.check(&runtime, source_map, &rules, CodeOrigin::Deployment(idx));
+                init_code(&runtime, &self.init_storage)
+                    .map_err(|error| BuilderError::DeployCodeTooLarge { idx, error })?
+            }
+            None if !self.init_storage.is_empty() => {
+                return Err(BuilderError::InitStorageWithoutCode { idx });
+            }
+            None => input.unwrap_or_default(),
+        };
 
         let tx = match tx_type {
             TxType::Legacy => {
@@ -463,7 +1113,7 @@ impl TransactionBuilder {
                     gas_limit: gas_limit(idx, self.gas_limit, defaults)?,
                     to: tx_kind(to),
                     value: self.value.unwrap_or_default().0,
-                    input: self.input.unwrap_or_default(),
+                    input,
                 };
                 TypedTransaction::Legacy(tx)
             }
@@ -475,7 +1125,7 @@ impl TransactionBuilder {
                     gas_limit: gas_limit(idx, self.gas_limit, defaults)?,
                     to: tx_kind(to),
                     value: self.value.unwrap_or_default().0,
-                    input: self.input.unwrap_or_default(),
+                    input,
                     access_list: self.access_list.unwrap_or_default(),
                 };
                 TypedTransaction::Eip2930(tx)
@@ -495,77 +1145,20 @@ impl TransactionBuilder {
                     to: tx_kind(to),
                     value: self.value.unwrap_or_default().0,
                     access_list: self.access_list.unwrap_or_default(),
-                    input: self.input.unwrap_or_default(),
+                    input,
                 };
                 TypedTransaction::Eip1559(tx)
             }
-            _ => unimplemented!(),
+            _ => unreachable!("other types are rejected above"),
         };
 
-        Ok((from, tx))
-    }
-}
-
-#[cfg(feature = "scroll")]
-impl Default for ChainConfigBuilder {
-    fn default() -> Self {
-        ChainConfigBuilder {
-            chain_id: default_chain_id(),
-            homestead_block: BoolOr::Value(0),
-            dao_fork_block: BoolOr::Bool(false),
-            dao_fork_support: true,
-            eip150_block: BoolOr::Value(0),
-            eip155_block: BoolOr::Value(0),
-            eip158_block: BoolOr::Value(0),
-            byzantium_block: BoolOr::Value(0),
-            constantinople_block: BoolOr::Value(0),
-            petersburg_block: BoolOr::Value(0),
-            istanbul_block: BoolOr::Value(0),
-            muir_glacier_block: BoolOr::Value(0),
-            berlin_block: BoolOr::Value(0),
-            london_block: BoolOr::Value(0),
-            arrow_glacier_block: BoolOr::Value(0),
-            gray_glacier_block: BoolOr::Value(0),
-            merge_netsplit_block: BoolOr::Value(0),
-            shanghai_time: BoolOr::Value(0),
-            curie_block: BoolOr::Value(0),
-            darwin_time: BoolOr::Value(0),
-            darwinv2_time: BoolOr::Value(0),
-            euclid_time: BoolOr::Value(0),
-            euclidv2_time: BoolOr::Value(0),
-            cancun_time: BoolOr::Bool(false),
-            prague_time: BoolOr::Bool(false),
-            osaka_time: BoolOr::Bool(false),
-        }
-    }
-}
-
-#[cfg(not(feature = "scroll"))]
-impl Default for ChainConfigBuilder {
-    fn default() -> Self {
-        ChainConfigBuilder {
-            chain_id: default_chain_id(),
-            homestead_block: BoolOr::Value(0),
-            dao_fork_block: BoolOr::Value(0),
-            dao_fork_support: true,
-            eip150_block: BoolOr::Value(0),
-            eip155_block: BoolOr::Value(0),
-            eip158_block: BoolOr::Value(0),
-            byzantium_block: BoolOr::Value(0),
-            constantinople_block: BoolOr::Value(0),
-            petersburg_block: BoolOr::Value(0),
-            istanbul_block: BoolOr::Value(0),
-            muir_glacier_block: BoolOr::Value(0),
-            berlin_block: BoolOr::Value(0),
-            london_block: BoolOr::Value(0),
-            arrow_glacier_block: BoolOr::Value(0),
-            gray_glacier_block: BoolOr::Value(0),
-            merge_netsplit_block: BoolOr::Value(0),
-            shanghai_time: BoolOr::Value(0),
-            cancun_time: BoolOr::Value(0),
-            prague_time: BoolOr::Value(0),
-            osaka_time: BoolOr::Value(0),
-        }
+        Ok(ScheduledTransaction {
+            name,
+            from,
+            fork_transition,
+            input_file,
+            tx,
+        })
     }
 }
 
@@ -593,11 +1186,139 @@ fn default_chain_id() -> u64 {
     22222
 }
 
-fn resolve_address(address: &str, wallets: &HashMap<String, PrivateKeySigner>) -> Option<Address> {
+/// Resolves a fork activation, an explicit key takes precedence over the preset.
+#[inline]
+fn fork_activation<T: Default>(explicit: Option<BoolOr<T>>, enabled: bool) -> Option<T> {
+    explicit.unwrap_or(BoolOr::Bool(enabled)).into_option()
+}
+
+/// Checks that forks listed in activation order are enabled in order.
+///
+/// Optional forks may be disabled without affecting later forks, same as geth.
+fn check_fork_order(forks: &[(&'static str, Option<u64>, bool)]) -> Result<(), BuilderError> {
+    let mut last: Option<(&'static str, Option<u64>)> = None;
+    for &(fork, activation, optional) in forks {
+        if let Some((parent, parent_activation)) = last {
+            match (parent_activation, activation) {
+                (None, Some(_)) => return Err(BuilderError::ForkParentDisabled { fork, parent }),
+                (Some(parent_at), Some(at)) if at < parent_at => {
+                    return Err(BuilderError::ForkOrder {
+                        fork,
+                        at,
+                        parent,
+                        parent_at,
+                    });
+                }
+                _ => {}
+            }
+        }
+        if !optional || activation.is_some() {
+            last = Some((fork, activation));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn resolve_address(address: &str, names: &HashMap<String, Address>) -> Option<Address> {
     if address.starts_with("0x") {
         Address::from_str(address).ok()
     } else {
-        wallets.get(address).map(|wallet| wallet.address())
+        names.get(address).copied()
+    }
+}
+
+/// Code given as hex, or as mnemonic if it is not, with the source map of assembled code.
+fn compile_code(code: &str, base_dir: &Path) -> Result<(Bytes, Option<SourceMap>), CompileError> {
+    match Bytes::from_str(code) {
+        Ok(code) => Ok((code, None)),
+        Err(_) => compile_mnemonic_mapped(code, base_dir)
+            .map(|(code, source_map)| (code, Some(source_map))),
+    }
+}
+
+/// Reads a blob by the extension of its file: raw bytes from `.bin`, hex text from `.hex`, or
+/// mnemonic from `.asm`, assembled with its source map.
+fn read_blob(path: &Path) -> Result<(Bytes, Option<SourceMap>), BuilderError> {
+    let invalid = |error: String| BuilderError::InvalidFile {
+        path: path.to_path_buf(),
+        error,
+    };
+    let read_error = |error| BuilderError::ReadFile {
+        path: path.to_path_buf(),
+        error,
+    };
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("bin") => Ok((std::fs::read(path).map_err(read_error)?.into(), None)),
+        Some("hex") => {
+            let text = std::fs::read_to_string(path).map_err(read_error)?;
+            let hex = text.split_whitespace().collect::<String>();
+            let blob = Bytes::from_str(&hex).map_err(|e| invalid(e.to_string()))?;
+            Ok((blob, None))
+        }
+        Some("asm") => {
+            let text = std::fs::read_to_string(path).map_err(read_error)?;
+            let (code, source_map) = compile_mnemonic_file(&text, path).map_err(|error| {
+                BuilderError::FileCompileError {
+                    path: path.to_path_buf(),
+                    error,
+                }
+            })?;
+            Ok((code, Some(source_map)))
+        }
+        _ => Err(invalid("expected a .bin, .hex or .asm file".to_string())),
+    }
+}
+
+/// Reads storage from a `.json` map of slots to values, or from a blob holding consecutive words
+/// from slot 0.
+fn read_storage(path: &Path) -> Result<BTreeMap<U256, U256>, BuilderError> {
+    let invalid = |error: String| BuilderError::InvalidFile {
+        path: path.to_path_buf(),
+        error,
+    };
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let text = std::fs::read_to_string(path).map_err(|error| BuilderError::ReadFile {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        return serde_json::from_str(&text).map_err(|e| invalid(e.to_string()));
+    }
+    let (blob, _) = read_blob(path)?;
+    if blob.len() % 32 != 0 {
+        return Err(invalid(format!(
+            "{} bytes is not a whole number of 32 byte words",
+            blob.len()
+        )));
+    }
+    Ok(blob
+        .chunks(32)
+        .enumerate()
+        .map(|(slot, word)| (U256::from(slot), U256::from_be_slice(word)))
+        .collect())
+}
+
+/// What building learns about the code of allocs and deployments.
+#[derive(Default)]
+struct CheckedCode {
+    warnings: Vec<CodeWarning>,
+    source_maps: HashMap<CodeOrigin, SourceMap>,
+    /// From artifacts, to encode calls and decode logs.
+    abis: HashMap<CodeOrigin, JsonAbi>,
+}
+
+impl CheckedCode {
+    fn check(
+        &mut self,
+        code: &[u8],
+        source_map: Option<SourceMap>,
+        rules: &EvmRules,
+        origin: CodeOrigin,
+    ) {
+        self.warnings
+            .extend(check_built_code(code, source_map.as_ref(), rules, origin));
+        if let Some(source_map) = source_map {
+            self.source_maps.insert(origin, source_map);
+        }
     }
 }
 
@@ -648,6 +1369,27 @@ fn gas_limit(
         .ok_or_else(|| BuilderError::GasLimitNotSet { idx })
 }
 
+fn fork_transition(
+    idx: usize,
+    target: ForkTarget,
+    genesis: &Genesis,
+) -> Result<ForkTransition, BuilderError> {
+    let fork = target.hardfork();
+    let activation = fork
+        .activation(&genesis.config)
+        .ok_or(BuilderError::ForkTargetDisabled { idx, fork })?;
+    // the targeted block has to come after genesis
+    let at_genesis = match (target, activation) {
+        (ForkTarget::Before(_), ForkCondition::Block(number)) => number <= 1,
+        (ForkTarget::After(_), ForkCondition::Block(number)) => number == 0,
+        (_, ForkCondition::Timestamp(timestamp)) => timestamp <= genesis.timestamp,
+    };
+    if at_genesis {
+        return Err(BuilderError::ForkTargetAtGenesis { idx, fork });
+    }
+    Ok(ForkTransition { target, activation })
+}
+
 #[inline]
 fn tx_kind(to_address: Option<Address>) -> TxKind {
     match to_address {
@@ -657,13 +1399,616 @@ fn tx_kind(to_address: Option<Address>) -> TxKind {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use alloy_consensus::Transaction;
+    use alloy_primitives::{address, keccak256};
+
+    /// Where the account a non-empty `alloc` of [`build_with`] describes lives.
+    const CONTRACT: &str = "0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef";
+
+    /// Builds wallet `alice` sending one transaction with the keys of `tx`. A non-empty `alloc`
+    /// holds the keys of an account named `contract`, `extra` any other table.
+    pub(crate) fn build_with(alloc: &str, tx: &str, extra: &str) -> Result<Spectre, BuilderError> {
+        config(alloc, tx, extra).build()
+    }
+
+    /// Same as [`build_with`], before it is built.
+    fn config(alloc: &str, tx: &str, extra: &str) -> SpectreBuilder {
+        let alloc = match alloc {
+            "" => String::new(),
+            keys => format!("[[alloc]]\naddress = \"{CONTRACT}\"\nname = \"contract\"\n{keys}"),
+        };
+        toml::from_str(&format!(
+            r#"
+            {extra}
+            {alloc}
+            [[wallet]]
+            name = "alice"
+            [[transactions]]
+            from = "alice"
+            gas-price = "1 Gwei"
+            gas-limit = 100000
+            {tx}
+            "#
+        ))
+        .unwrap()
+    }
 
     #[tokio::test]
     async fn test_de_and_trace() {
-        let config: SpectreBuilder =
+        let mut config: SpectreBuilder =
             toml::from_str(include_str!("../../../examples/full.toml")).unwrap();
-        config.build().unwrap().trace().await.unwrap();
+        config.system.backend = Backend::Revm;
+        let spectre = config.build().unwrap();
+        let count = spectre.transactions.len();
+        let report = spectre.trace().await.unwrap();
+        assert_eq!(report.transactions.len(), count);
+        for (idx, tx) in report.transactions.iter().enumerate() {
+            assert_eq!(tx.idx, idx);
+            let block = report
+                .blocks
+                .iter()
+                .find(|block| block.number == tx.outcome.block_number)
+                .unwrap();
+            assert!(tx.outcome.position < block.tx_count as u64);
+        }
+    }
+
+    #[test]
+    fn test_attach_options() {
+        let system: SystemBuilder = toml::from_str(
+            r#"
+            funding-secret = "0x0000000000000000000000000000000000000000000000000000000000000001"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            system.attach_options(),
+            Err(BuilderError::AttachWithoutRpcUrl {
+                field: "funding-secret"
+            })
+        ));
+
+        let system: SystemBuilder = toml::from_str(
+            r#"
+            rpc-url = "ipc:///tmp/geth.ipc"
+            funding-secret = "0x0000000000000000000000000000000000000000000000000000000000000001"
+            miner = { address = "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf", password = "dev" }
+            "#,
+        )
+        .unwrap();
+        let attach = system.attach_options().unwrap().unwrap();
+        assert_eq!(attach.rpc_url, "ipc:///tmp/geth.ipc");
+        assert_eq!(attach.miner.unwrap().password, "dev");
+        assert_eq!(
+            attach.funding_key.unwrap().address(),
+            address!("7E5F4552091A69125d5DfCb7b8C2659029395Bdf")
+        );
+    }
+
+    #[test]
+    fn test_fork_options() {
+        let system: SystemBuilder = toml::from_str(r#"fork-block = 100"#).unwrap();
+        assert!(matches!(
+            system.fork_options(),
+            Err(BuilderError::ForkBlockWithoutUrl)
+        ));
+
+        let system: SystemBuilder =
+            toml::from_str(r#"fork-url = "http://localhost:8545""#).unwrap();
+        assert!(matches!(
+            system.fork_options(),
+            Err(BuilderError::ForkWithoutRevm)
+        ));
+
+        let system: SystemBuilder = toml::from_str(
+            r#"
+            backend = "revm"
+            fork-url = "http://localhost:8545"
+            fork-block = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            system.fork_options().unwrap(),
+            Some(ForkOptions {
+                rpc_url: "http://localhost:8545".to_string(),
+                block: Some(100),
+            })
+        );
+    }
+
+    #[test]
+    fn test_traces() {
+        let system: SystemBuilder = toml::from_str("").unwrap();
+        assert!(system.traces.is_empty());
+
+        let system: SystemBuilder =
+            toml::from_str(r#"traces = ["struct-logs", "call-tracer", "prestate-tracer"]"#)
+                .unwrap();
+        assert_eq!(
+            system.traces,
+            [
+                TraceKind::StructLogs,
+                TraceKind::CallTracer,
+                TraceKind::PrestateTracer
+            ]
+        );
+
+        let result = toml::from_str::<SystemBuilder>(r#"traces = ["4byte-tracer"]"#);
+        assert!(result.is_err());
+    }
+
+    #[cfg(not(feature = "scroll"))]
+    #[test]
+    fn test_hardfork_preset() {
+        let chain: ChainConfigBuilder = toml::from_str(r#"hardfork = "shanghai""#).unwrap();
+        let config = chain.build(0).unwrap();
+        assert_eq!(config.merge_netsplit_block, Some(0));
+        assert_eq!(config.shanghai_time, Some(0));
+        assert_eq!(config.cancun_time, None);
+        assert_eq!(config.osaka_time, None);
+
+        let chain: ChainConfigBuilder = toml::from_str(
+            r#"
+            hardfork = "shanghai"
+            cancun-time = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(chain.build(0).unwrap().cancun_time, Some(100));
+
+        let chain: ChainConfigBuilder = toml::from_str(
+            r#"
+            hardfork = "london"
+            prague-time = 100
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            chain.build(0),
+            Err(BuilderError::ForkParentDisabled {
+                fork: "prague-time",
+                parent: "cancun-time"
+            })
+        ));
+
+        let chain: ChainConfigBuilder = toml::from_str(
+            r#"
+            cancun-time = 100
+            prague-time = 50
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            chain.build(0),
+            Err(BuilderError::ForkOrder {
+                fork: "prague-time",
+                at: 50,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_fork_target() {
+        let fork = *Hardfork::ALL.last().unwrap();
+        let builder = |chain: &str, at: &str| {
+            let extra = format!("[genesis]\ntimestamp = 1000\n[chain]\n{chain}");
+            build_with("", &format!("at = \"{at}\""), &extra)
+        };
+
+        let time_key = format!("{fork}-time");
+        let spectre = builder(&format!("{time_key} = \"+10\""), &format!("after {fork}")).unwrap();
+        assert_eq!(
+            spectre.transactions[0].fork_transition,
+            Some(ForkTransition {
+                target: ForkTarget::After(fork),
+                activation: ForkCondition::Timestamp(1010)
+            })
+        );
+        assert!(matches!(
+            builder("", &format!("before {fork}")),
+            Err(BuilderError::ForkTargetAtGenesis { idx: 0, .. })
+        ));
+        assert!(matches!(
+            builder(&format!("{time_key} = false"), &format!("after {fork}")),
+            Err(BuilderError::ForkTargetDisabled { idx: 0, .. })
+        ));
+
+        // the first preset activates by block number
+        let block_fork = Hardfork::ALL[0];
+        let block_key = format!("{block_fork}-block");
+        let spectre =
+            builder(&format!("{block_key} = 5"), &format!("before {block_fork}")).unwrap();
+        assert_eq!(
+            spectre.transactions[0].fork_transition.unwrap().activation,
+            ForkCondition::Block(5)
+        );
+        // only a fork activating by block needs filler blocks, and the account paying for them
+        let filler = filler_signer().address();
+        assert_eq!(spectre.genesis.alloc[&filler].balance, FILLER_BALANCE);
+        let spectre = builder(&format!("{time_key} = \"+10\""), &format!("after {fork}")).unwrap();
+        assert!(!spectre.genesis.alloc.contains_key(&filler));
+        assert!(matches!(
+            builder(&format!("{block_key} = 1"), &format!("before {block_fork}")),
+            Err(BuilderError::ForkTargetAtGenesis { idx: 0, .. })
+        ));
+        assert!(builder(&format!("{block_key} = 1"), &format!("after {block_fork}")).is_ok());
+    }
+
+    #[test]
+    fn test_tx_type() {
+        assert!(build_with("", "type = 1", "").is_ok());
+        for tx_type in [3, 4, 5] {
+            assert!(matches!(
+                build_with("", &format!("type = {tx_type}"), ""),
+                Err(BuilderError::UnexpectedTxType { idx: 0, tx_type: rejected }) if rejected == tx_type
+            ));
+        }
+    }
+
+    #[test]
+    fn test_deploy_code() {
+        let builder = |tx: &str| build_with("", tx, "");
+
+        let spectre = builder(
+            r#"
+            deploy-code = "PUSH1 0x2a\nPUSH0\nSSTORE"
+            [transactions.init-storage]
+            "0x01" = "0x1234"
+            "#,
+        )
+        .unwrap();
+        let tx = &spectre.transactions[0].tx;
+        assert_eq!(tx.kind(), TxKind::Create);
+        assert_eq!(
+            tx.input(),
+            &init_code(
+                &[0x60, 0x2a, 0x5f, 0x55],
+                &BTreeMap::from([(U256::from(1), U256::from(0x1234))])
+            )
+            .unwrap()
+        );
+
+        assert!(matches!(
+            builder(
+                r#"
+                to = "alice"
+                deploy-code = "0x00"
+                "#
+            ),
+            Err(BuilderError::DeployCodeConflict {
+                idx: 0,
+                field: "to"
+            })
+        ));
+        assert!(matches!(
+            builder(
+                r#"
+                [transactions.init-storage]
+                "0x01" = "0x01"
+                "#
+            ),
+            Err(BuilderError::InitStorageWithoutCode { idx: 0 })
+        ));
+        assert!(matches!(
+            builder(r#"deploy-code = "PUSHX""#),
+            Err(BuilderError::DeployCodeCompileError { idx: 0, .. })
+        ));
+        assert!(matches!(
+            builder(&format!(r#"deploy-code = "0x{}""#, "00".repeat(0x10000))),
+            Err(BuilderError::DeployCodeTooLarge { idx: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_blob_files() {
+        let dir = std::env::temp_dir().join(format!("spectre-blobs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("code.asm"), "PUSH1 0x01 // one\nPOP\nSTOP\n").unwrap();
+        std::fs::write(
+            dir.join("storage.hex"),
+            format!("0x{}\n{}", "00".repeat(31), "2a".repeat(32)),
+        )
+        .unwrap();
+        std::fs::write(dir.join("storage.json"), r#"{"0x05": "0x07"}"#).unwrap();
+        std::fs::write(dir.join("input.bin"), [0xde, 0xad]).unwrap();
+        std::fs::write(dir.join("input.txt"), "").unwrap();
+
+        let builder = |alloc: &str, tx: &str| {
+            let mut config = config(alloc, &format!("to = \"alice\"\n{tx}"), "");
+            config.base_dir = Some(dir.clone());
+            config.build()
+        };
+
+        let spectre = builder(
+            r#"
+            code-file = "code.asm"
+            storage-file = "storage.hex"
+            "#,
+            r#"input-file = "input.bin""#,
+        )
+        .unwrap();
+        let address = Address::from_str("0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef").unwrap();
+        let account = &spectre.genesis.alloc[&address];
+        assert_eq!(account.code.as_ref().unwrap()[..], [0x60, 0x01, 0x50, 0x00]);
+        let storage = account.storage.as_ref().unwrap();
+        assert_eq!(storage[&B256::with_last_byte(0)], B256::ZERO);
+        assert_eq!(storage[&B256::with_last_byte(1)], B256::repeat_byte(0x2a));
+        assert_eq!(spectre.transactions[0].tx.input()[..], [0xde, 0xad]);
+        assert_eq!(
+            spectre.alloc_files[&address].code,
+            Some(dir.join("code.asm"))
+        );
+        assert_eq!(
+            spectre.transactions[0].input_file,
+            Some(dir.join("input.bin"))
+        );
+        let source_map = spectre.source_map(CodeOrigin::Alloc(address)).unwrap();
+        assert_eq!(
+            source_map.annotate(0),
+            Some(format!(
+                "line 1 of {}: PUSH1 0x01 // one",
+                dir.join("code.asm").display()
+            ))
+        );
+
+        let spectre = builder(r#"storage-file = "storage.json""#, "").unwrap();
+        let storage = spectre.genesis.alloc[&address].storage.as_ref().unwrap();
+        assert_eq!(storage[&B256::with_last_byte(5)], B256::with_last_byte(7));
+
+        assert!(matches!(
+            builder(
+                r#"
+                code = "STOP"
+                code-file = "code.asm"
+                "#,
+                ""
+            ),
+            Err(BuilderError::AllocFileConflict {
+                idx: 0,
+                field: "code"
+            })
+        ));
+        assert!(matches!(
+            builder(
+                "",
+                r#"
+                input = "0x00"
+                input-file = "input.bin"
+                "#
+            ),
+            Err(BuilderError::InputFileConflict { idx: 0 })
+        ));
+        assert!(matches!(
+            builder(r#"storage-file = "input.bin""#, ""),
+            Err(BuilderError::InvalidFile { .. })
+        ));
+        assert!(matches!(
+            builder("", r#"input-file = "input.txt""#),
+            Err(BuilderError::InvalidFile { .. })
+        ));
+        assert!(matches!(
+            builder(r#"code-file = "missing.asm""#, ""),
+            Err(BuilderError::ReadFile { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_artifacts() {
+        let dir = std::env::temp_dir().join(format!("spectre-artifacts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Counter.json"),
+            r#"{
+                "abi": [
+                    {"type": "constructor", "inputs": [{"name": "start", "type": "uint256"}], "stateMutability": "nonpayable"},
+                    {"type": "function", "name": "add", "inputs": [{"name": "amount", "type": "uint256"}], "outputs": [], "stateMutability": "nonpayable"}
+                ],
+                "bytecode": {"object": "0x6080"},
+                "deployedBytecode": {"object": "0x5f5400"},
+                "storageLayout": {
+                    "storage": [{"label": "count", "offset": 0, "slot": "3", "type": "t_uint256"}],
+                    "types": {"t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"}}
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let builder = |alloc: &str, tx: &str| {
+            let mut config = config(alloc, tx, "");
+            config.base_dir = Some(dir.clone());
+            config.build()
+        };
+
+        let spectre = builder(
+            r#"
+            artifact = "Counter.json"
+            variables = { count = "7" }
+            "#,
+            r#"
+            to = "contract"
+            call = "add"
+            args = ["2"]
+            "#,
+        )
+        .unwrap();
+        let address = Address::from_str("0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef").unwrap();
+        let account = &spectre.genesis.alloc[&address];
+        assert_eq!(account.code.as_ref().unwrap()[..], [0x5f, 0x54, 0x00]);
+        assert_eq!(
+            account.storage.as_ref().unwrap()[&B256::with_last_byte(3)],
+            B256::with_last_byte(7)
+        );
+        assert_eq!(
+            spectre.transactions[0].tx.input()[..],
+            [
+                &keccak256("add(uint256)")[..4],
+                &B256::with_last_byte(2)[..]
+            ]
+            .concat()
+        );
+        assert!(spectre.abi(CodeOrigin::Alloc(address)).is_some());
+
+        let spectre = builder(
+            "",
+            r#"
+            artifact = "Counter.json"
+            args = ["1"]
+            "#,
+        )
+        .unwrap();
+        let tx = &spectre.transactions[0].tx;
+        assert_eq!(tx.kind(), TxKind::Create);
+        assert_eq!(
+            tx.input()[..],
+            [&[0x60, 0x80][..], &B256::with_last_byte(1)[..]].concat()
+        );
+        assert!(spectre.abi(CodeOrigin::Deployment(0)).is_some());
+
+        assert!(matches!(
+            builder(
+                "balance = \"0 wei\"",
+                "to = \"contract\"\ncall = \"add\"\nargs = [\"2\"]"
+            ),
+            Err(BuilderError::CallWithoutAbi { idx: 0 })
+        ));
+        assert!(matches!(
+            builder("variables = { count = \"7\" }", ""),
+            Err(BuilderError::VariablesWithoutArtifact { idx: 0 })
+        ));
+        assert!(matches!(
+            builder("artifact = \"Counter.json\"\ncode = \"STOP\"", ""),
+            Err(BuilderError::AllocArtifactConflict {
+                idx: 0,
+                field: "code"
+            })
+        ));
+        assert!(matches!(
+            builder("", "args = [\"1\"]"),
+            Err(BuilderError::ArgsWithoutCall { idx: 0 })
+        ));
+        assert!(matches!(
+            builder(
+                "balance = \"0 wei\"",
+                "to = \"contract\"\nartifact = \"Counter.json\""
+            ),
+            Err(BuilderError::TxArtifactConflict {
+                idx: 0,
+                field: "to"
+            })
+        ));
+        assert!(matches!(
+            builder(
+                "balance = \"0 wei\"",
+                "to = \"contract\"\nartifact = \"Counter.json\"\nargs = [\"1\"]"
+            ),
+            Err(BuilderError::ArgsWithoutCall { idx: 0 })
+        ));
+        assert!(matches!(
+            builder(
+                "artifact = \"Counter.json\"",
+                "to = \"contract\"\ncall = \"sub\""
+            ),
+            Err(BuilderError::TxArtifactError {
+                idx: 0,
+                error: ArtifactError::UnknownFunction { .. }
+            })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(not(feature = "scroll"))]
+    #[test]
+    fn test_code_warnings() {
+        let builder = |strict: bool| {
+            build_with(
+                r#"code = "PUSH0\nTLOAD\nADD""#,
+                r#"deploy-code = "PUSH0\nPUSH0\nPUSH0\nPUSH0\nPUSH1 0x0a\nGAS\nSTATICCALL""#,
+                &format!("[system]\nstrict = {strict}\n[chain]\nhardfork = \"shanghai\""),
+            )
+        };
+
+        let spectre = builder(false).unwrap();
+        let warnings = spectre
+            .warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            [
+                "alloc 0xDeaDbeefdEAdbeefdEadbEEFdeadbeEFdEaDbeeF: TLOAD at pc 0x0001 needs cancun (line 2)",
+                "alloc 0xDeaDbeefdEAdbeefdEadbEEFdeadbeEFdEaDbeeF: stack underflow, ADD needs 2 items with 1 on the stack at pc 0x0002 (line 3)",
+                "transaction#0: precompile point evaluation at pc 0x0007 needs cancun (line 7)",
+            ]
+        );
+        let source_map = spectre.source_map(CodeOrigin::Deployment(0)).unwrap();
+        assert_eq!(source_map.annotate(6).as_deref(), Some("line 6: GAS"));
+        assert!(matches!(
+            builder(true),
+            Err(BuilderError::CodeWarnings { warnings }) if warnings.len() == 3
+        ));
+    }
+
+    #[cfg(feature = "scroll")]
+    #[test]
+    fn test_hardfork_preset() {
+        let chain: ChainConfigBuilder = toml::from_str(r#"hardfork = "euclid""#).unwrap();
+        let config = chain.build(0).unwrap();
+        assert_eq!(config.shanghai_time, Some(0));
+        assert_eq!(config.cancun_time, None);
+        assert!(config.extra_fields.contains_key("euclidTime"));
+        assert!(!config.extra_fields.contains_key("euclidv2Time"));
+
+        let chain: ChainConfigBuilder = toml::from_str(
+            r#"
+            hardfork = "euclid"
+            euclidv2-time = 100
+            "#,
+        )
+        .unwrap();
+        let config = chain.build(0).unwrap();
+        assert_eq!(
+            config
+                .extra_fields
+                .get_deserialized::<u64>("euclidv2Time")
+                .and_then(Result::ok),
+            Some(100)
+        );
+
+        let chain: ChainConfigBuilder = toml::from_str(
+            r#"
+            hardfork = "curie"
+            euclid-time = 100
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            chain.build(0),
+            Err(BuilderError::ForkParentDisabled {
+                fork: "euclid-time",
+                parent: "darwinv2-time"
+            })
+        ));
+
+        let chain: ChainConfigBuilder = toml::from_str(
+            r#"
+            euclid-time = 100
+            euclidv2-time = 50
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            chain.build(0),
+            Err(BuilderError::ForkOrder {
+                fork: "euclidv2-time",
+                at: 50,
+                ..
+            })
+        ));
     }
 }
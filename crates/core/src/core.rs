@@ -1,23 +1,48 @@
-use alloy_consensus::SignableTransaction;
+use crate::{
+    artifact::decode_log,
+    asm::SourceMap,
+    backend::{
+        AttachOptions, Backend, BlockInfo, ExecutionStep, ForkOptions, GethBackend, RevmBackend,
+        TraceKind, TxOutcome, WitnessBackend,
+    },
+    hardfork::{ForkCondition, ForkTarget, Hardfork},
+    validate::{CodeOrigin, CodeWarning},
+};
+use alloy_consensus::{SignableTransaction, Transaction, TxLegacy};
 #[cfg(not(feature = "scroll"))]
 use alloy_consensus::{TxEip4844Variant, TxEnvelope, TypedTransaction};
-use alloy_genesis::Genesis;
-use alloy_network::{ReceiptResponse, TxSignerSync};
-use alloy_primitives::Address;
-use alloy_provider::Provider;
+use alloy_eips::eip1559::{BaseFeeParams, calc_next_block_base_fee};
+use alloy_genesis::{ChainConfig, Genesis};
+use alloy_json_abi::JsonAbi;
+use alloy_network::TxSignerSync;
+use alloy_primitives::{Address, B256, Bytes, TxHash, TxKind, U256, keccak256};
 use alloy_signer_local::PrivateKeySigner;
 use sbv_primitives::types::BlockWitness;
-use sbv_utils::rpc::ProviderExt;
 #[cfg(feature = "scroll")]
 use scroll_alloy_consensus::{
     ScrollTxEnvelope as TxEnvelope, ScrollTypedTransaction as TypedTransaction,
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeSet, HashMap},
-    fmt::Debug,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{Debug, Display},
     path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+/// Seconds a block needs to be sealed, a stage has to start this long before a fork time to land
+/// before it.
+const SEAL_MARGIN: u64 = 2;
+const FILLER_GAS_LIMIT: u64 = 21_000;
+/// Balance of the filler account, enough for far more filler blocks than a fork block needs.
+pub(crate) const FILLER_BALANCE: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+/// The account paying for filler blocks, allocated in genesis when a stage targets a fork that
+/// activates by block, so the senders of the transactions keep their nonces and balances.
+pub(crate) fn filler_signer() -> PrivateKeySigner {
+    PrivateKeySigner::from_bytes(&keccak256("spectre filler")).unwrap()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SpectreError {
     #[error("Error while build testnet: {0}")]
@@ -28,114 +53,823 @@ pub enum SpectreError {
     Rpc(#[from] alloy_json_rpc::RpcError<alloy_transport::TransportErrorKind>),
     #[error("Error while waiting for receipt: {0}")]
     PendingTransaction(#[from] alloy_provider::PendingTransactionError),
+    #[error("no witness for block #{0}")]
+    MissingWitness(u64),
+    #[error("block #{0} not found")]
+    MissingBlock(u64),
+    #[error("invalid block range {0}, expected a number or `start-end`")]
+    InvalidBlockRange(String),
+    #[error("node runs chain {actual}, the genesis is for chain {expected}")]
+    ChainIdMismatch { expected: u64, actual: u64 },
+    #[error("the node activates {fork} {}, the genesis {}", activation_text(.actual), activation_text(.expected))]
+    ForkScheduleMismatch {
+        fork: Hardfork,
+        expected: Option<ForkCondition>,
+        actual: Option<ForkCondition>,
+    },
+    #[error("cannot read the fork schedule of the node, admin_nodeInfo is needed: {0}")]
+    ForkScheduleUnavailable(String),
+    #[error(
+        "the node cannot seal on demand ({0}), miner control needs geth v1.13 or older serving the personal and miner APIs"
+    )]
+    MinerControlUnavailable(String),
+    #[error("cannot fund {address} on the node without a funding-secret")]
+    NoFundingKey { address: Address },
+    #[error("funding transaction {hash} failed")]
+    FundingFailed { hash: TxHash },
+    #[error("the backend has no chain yet, its genesis is not applied")]
+    GenesisNotApplied,
+    #[error("the backend does not support {0}")]
+    Unsupported(&'static str),
+    #[error("cannot fetch the forked state: {0}")]
+    ForkedState(String),
+    #[error(
+        "transaction#{idx}: missed {target} (activates at {activation}), landed in block #{block} at {timestamp}"
+    )]
+    ForkTargetMissed {
+        idx: usize,
+        target: ForkTarget,
+        activation: ForkCondition,
+        block: u64,
+        timestamp: u64,
+    },
+    #[error("transaction#{idx}: cannot land {target}, {reason}")]
+    ForkTargetUnreachable {
+        idx: usize,
+        target: ForkTarget,
+        reason: &'static str,
+    },
+    #[error("{tx}: landed before transaction#{previous}, against the declared order")]
+    OrderDeviation { tx: TxLabel, previous: usize },
+    #[error("{tx}: rejected by the pool, {reason}")]
+    TxRejected { tx: TxLabel, reason: RejectReason },
+    #[error("{tx}: {hash} left the pool without being mined")]
+    TxDropped { tx: TxLabel, hash: TxHash },
+    #[error("{tx}: {hash} still {section} in the pool after {waited:?}")]
+    TxStuck {
+        tx: TxLabel,
+        hash: TxHash,
+        /// `pending`, or `queued` behind a missing nonce.
+        section: &'static str,
+        waited: Duration,
+    },
+    #[error("{tx}: {source}")]
+    TxRequest {
+        tx: TxLabel,
+        source: alloy_json_rpc::RpcError<alloy_transport::TransportErrorKind>,
+    },
+    #[error(
+        "cannot tell pending transactions from dropped ones, txpool_status and txpool_content are needed: {0}"
+    )]
+    TxPoolUnavailable(String),
+}
+
+/// How the transactions of a block are ordered, set by `[system] order`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TxOrder {
+    /// As declared: transactions the miner would move ahead are sealed in a later block, and a
+    /// deviation fails the trace.
+    #[default]
+    Declared,
+    /// As the miner picks them, by tip. Deviations from the declared order are only warned
+    /// about.
+    Pool,
+}
+
+/// Names a submitted transaction in errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxLabel {
+    /// A builder transaction, by index and name.
+    Scheduled { idx: usize, name: Option<String> },
+    /// A transfer funding a genesis account on a running node.
+    Funding(Address),
+    /// A self-transfer sealing an empty block, moving the chain towards a block-activated fork.
+    Filler { before: usize },
+}
+
+impl Display for TxLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TxLabel::Scheduled { idx, name: None } => write!(f, "transaction#{idx}"),
+            TxLabel::Scheduled {
+                idx,
+                name: Some(name),
+            } => write!(f, "transaction#{idx} ({name})"),
+            TxLabel::Funding(address) => write!(f, "funding of {address}"),
+            TxLabel::Filler { before } => write!(f, "filler block before transaction#{before}"),
+        }
+    }
+}
+
+/// Why the pool refused a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RejectReason {
+    #[error("fee too low (underpriced)")]
+    Underpriced,
+    #[error("nonce too low")]
+    NonceTooLow,
+    #[error("insufficient funds for gas * price + value")]
+    InsufficientFunds,
+    #[error("gas limit below the intrinsic gas")]
+    IntrinsicGas,
+    #[error("{0}")]
+    Other(String),
+}
+
+impl RejectReason {
+    /// Classifies the error message of a node.
+    pub fn from_message(message: &str) -> Self {
+        let lower = message.to_lowercase();
+        if lower.contains("underpriced") || lower.contains("less than block base fee") {
+            RejectReason::Underpriced
+        } else if lower.contains("nonce too low") {
+            RejectReason::NonceTooLow
+        } else if lower.contains("insufficient funds") {
+            RejectReason::InsufficientFunds
+        } else if lower.contains("intrinsic gas too low") {
+            RejectReason::IntrinsicGas
+        } else {
+            RejectReason::Other(message.to_string())
+        }
+    }
+}
+
+/// A fork target resolved against the chain config.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct ForkTransition {
+    pub(crate) target: ForkTarget,
+    pub(crate) activation: ForkCondition,
+}
+
+pub(crate) struct ScheduledTransaction {
+    pub(crate) name: Option<String>,
+    pub(crate) from: Address,
+    pub(crate) fork_transition: Option<ForkTransition>,
+    /// File the input was read from, if any.
+    pub(crate) input_file: Option<PathBuf>,
+    pub(crate) tx: TypedTransaction,
+}
+
+/// Files the code and storage of an alloc were read from.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct AllocFiles {
+    pub(crate) code: Option<PathBuf>,
+    pub(crate) storage: Option<PathBuf>,
+}
+
+/// A traced block, labelled with the fork rules it was executed under.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TracedBlock {
+    pub number: u64,
+    pub hash: B256,
+    pub state_root: B256,
+    pub receipts_root: B256,
+    pub timestamp: u64,
+    pub gas_used: u64,
+    pub tx_count: usize,
+    pub hardfork: Option<Hardfork>,
+    /// Written on its own, not as part of the report.
+    #[serde(skip)]
+    pub witness: BlockWitness,
+}
+
+impl TracedBlock {
+    pub(crate) fn new(info: BlockInfo, hardfork: Option<Hardfork>, witness: BlockWitness) -> Self {
+        TracedBlock {
+            number: info.number,
+            hash: info.hash,
+            state_root: info.state_root,
+            receipts_root: info.receipts_root,
+            timestamp: info.timestamp,
+            gas_used: info.gas_used,
+            tx_count: info.tx_count,
+            hardfork,
+            witness,
+        }
+    }
+}
+
+/// A traced transaction, at the index it has in the builder.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TracedTransaction {
+    pub idx: usize,
+    #[serde(flatten)]
+    pub outcome: TxOutcome,
+    /// Written on their own, not as part of the report.
+    #[serde(skip)]
+    pub traces: BTreeMap<TraceKind, serde_json::Value>,
+}
+
+/// What tracing produced: the blocks with their witnesses, and the receipt of every transaction.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TraceReport {
+    pub blocks: Vec<TracedBlock>,
+    pub transactions: Vec<TracedTransaction>,
+    /// Transactions sealed in a later block than the ones declared before them, so the miner
+    /// could not put them first, by index.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<usize>,
+    /// Fork rules the blocks are re-executed under when verified.
+    #[serde(skip)]
+    pub(crate) chain_config: ChainConfig,
 }
 
 // #[derive(Debug)]
 pub struct Spectre {
+    pub(crate) backend: Backend,
     pub(crate) geth_path: Option<PathBuf>,
+    pub(crate) attach: Option<AttachOptions>,
+    pub(crate) fork: Option<ForkOptions>,
+    pub(crate) tx_timeout: Option<Duration>,
+    pub(crate) order: TxOrder,
+    pub(crate) traces: Vec<TraceKind>,
     pub(crate) genesis: Genesis,
     pub(crate) wallets: HashMap<Address, PrivateKeySigner>,
-    pub(crate) transactions: Vec<(Address, TypedTransaction)>,
+    pub(crate) transactions: Vec<ScheduledTransaction>,
+    pub(crate) alloc_files: BTreeMap<Address, AllocFiles>,
+    pub(crate) warnings: Vec<CodeWarning>,
+    pub(crate) source_maps: HashMap<CodeOrigin, SourceMap>,
+    pub(crate) abis: HashMap<CodeOrigin, JsonAbi>,
 }
 
 impl Spectre {
-    pub async fn trace(self) -> Result<Vec<BlockWitness>, SpectreError> {
-        let mut provider_builder = testnet::TestNetBuilder::default();
-        if let Some(geth_path) = self.geth_path {
-            provider_builder = provider_builder.geth_path(geth_path);
+    /// Code using opcodes or precompiles the chain does not offer when it runs.
+    pub fn warnings(&self) -> &[CodeWarning] {
+        &self.warnings
+    }
+
+    /// Maps the code of an alloc or deployment written as mnemonic back to its lines.
+    pub fn source_map(&self, origin: CodeOrigin) -> Option<&SourceMap> {
+        self.source_maps.get(&origin)
+    }
+
+    /// ABI of an alloc or deployment taken from an artifact.
+    pub fn abi(&self, origin: CodeOrigin) -> Option<&JsonAbi> {
+        self.abis.get(&origin)
+    }
+
+    /// Traces the transactions on the backend chosen by `[system] backend`.
+    pub async fn trace(self) -> Result<TraceReport, SpectreError> {
+        match self.backend {
+            Backend::Geth => {
+                let mut backend = match self.attach.clone() {
+                    Some(options) => GethBackend::attach(options),
+                    None => GethBackend::new(self.geth_path.clone()),
+                };
+                if let Some(timeout) = self.tx_timeout {
+                    backend = backend.tx_timeout(timeout);
+                }
+                self.trace_with(&mut backend).await
+            }
+            Backend::Revm => {
+                let mut backend = match self.fork.clone() {
+                    Some(options) => RevmBackend::fork(options),
+                    None => RevmBackend::default(),
+                };
+                self.trace_with(&mut backend).await
+            }
         }
-        let provider = provider_builder.genesis(self.genesis).build().await?;
-        provider.stop_miner().await?;
+    }
+
+    /// Traces the transactions on `backend`, one block witness per block they land in.
+    ///
+    /// The backend is left running, its chain can be inspected afterwards.
+    pub async fn trace_with<B: WitnessBackend>(
+        self,
+        backend: &mut B,
+    ) -> Result<TraceReport, SpectreError> {
+        let chain_config = self.genesis.config.clone();
+        backend.apply_genesis(self.genesis).await?;
 
         let mut nonce_map = HashMap::new();
+        let mut blocks = BTreeSet::new();
+        let mut report = TraceReport {
+            blocks: vec![],
+            transactions: vec![],
+            splits: vec![],
+            chain_config: chain_config.clone(),
+        };
+        // contracts created by deployments, to map their code back to its source
+        let mut deployed = HashMap::new();
+        let mut head = backend.head().await?;
+
+        // transactions sharing a fork target are mined together, one stage at a time
+        let mut offset = 0;
+        for stage in self
+            .transactions
+            .chunk_by(|a, b| a.fork_transition == b.fork_transition)
+        {
+            let stage_idx = offset;
+            offset += stage.len();
+            let transition = stage[0].fork_transition;
+            let unreachable =
+                |transition: ForkTransition, reason| SpectreError::ForkTargetUnreachable {
+                    idx: stage_idx,
+                    target: transition.target,
+                    reason,
+                };
 
-        let mut txs = vec![];
-        for (from, tx) in self.transactions.into_iter() {
-            if !nonce_map.contains_key(&from) {
-                let nonce = provider.get_transaction_count(from).await?;
-                nonce_map.insert(from, nonce);
-            }
-            let nonce = {
-                let nonce = nonce_map.get_mut(&from).unwrap();
-                let current = *nonce;
-                *nonce += 1;
-                current
+            let batches = match self.order {
+                TxOrder::Declared => split_for_order(stage, next_base_fee(&head)),
+                TxOrder::Pool => vec![stage],
             };
+            if let Some(transition) = transition {
+                // the targeted block is a single one, the whole stage has to fit in it
+                if batches.len() > 1 {
+                    return Err(unreachable(
+                        transition,
+                        "the declared order splits its stage over several blocks",
+                    ));
+                }
+                let gas = stage.iter().map(|scheduled| scheduled.tx.gas_limit());
+                if gas.sum::<u64>() > head.gas_limit {
+                    return Err(unreachable(
+                        transition,
+                        "its stage needs more gas than a block holds",
+                    ));
+                }
+                if let Some(number) = prepare_transition(stage_idx, transition, &head).await? {
+                    let signer = filler_signer();
+                    while head.number + 1 < number {
+                        head = seal_filler(
+                            backend,
+                            &mut nonce_map,
+                            &signer,
+                            chain_config.chain_id,
+                            stage_idx,
+                            &head,
+                        )
+                        .await?;
+                    }
+                }
+            }
 
-            let signer = self.wallets.get(&from).expect("missing wallet");
-            let tx_envelope = match tx {
-                TypedTransaction::Legacy(mut tx) => {
-                    tx.nonce = nonce;
-                    let sig = signer.sign_transaction_sync(&mut tx).unwrap();
-                    TxEnvelope::Legacy(tx.into_signed(sig))
+            let mut batch_idx = stage_idx;
+            for batch in batches {
+                let first_idx = batch_idx;
+                batch_idx += batch.len();
+                if first_idx != stage_idx {
+                    info!(
+                        idx = first_idx,
+                        "sealed after the transactions before it, to keep the declared order"
+                    );
+                    report.splits.push(first_idx);
                 }
-                TypedTransaction::Eip2930(mut tx) => {
-                    tx.nonce = nonce;
-                    let sig = signer.sign_transaction_sync(&mut tx).unwrap();
-                    TxEnvelope::Eip2930(tx.into_signed(sig))
+
+                let mut txs = vec![];
+                for (idx, ScheduledTransaction { name, from, tx, .. }) in (first_idx..).zip(batch) {
+                    let nonce = next_nonce(backend, &mut nonce_map, *from).await?;
+                    let signer = self.wallets.get(from).expect("missing wallet");
+                    let tx_envelope = sign_transaction(signer, tx.clone(), nonce);
+
+                    trace!(tx_envelope = ?tx_envelope);
+                    let label = TxLabel::Scheduled {
+                        idx,
+                        name: name.clone(),
+                    };
+                    backend.submit(tx_envelope, label).await?;
+                    txs.push((idx, tx.to()));
                 }
-                TypedTransaction::Eip1559(mut tx) => {
-                    tx.nonce = nonce;
-                    let sig = signer.sign_transaction_sync(&mut tx).unwrap();
-                    TxEnvelope::Eip1559(tx.into_signed(sig))
-                }
-                #[cfg(not(feature = "scroll"))]
-                TypedTransaction::Eip4844(mut tx) => {
-                    match &mut tx {
-                        TxEip4844Variant::TxEip4844(tx) => {
-                            tx.nonce = nonce;
-                        }
-                        TxEip4844Variant::TxEip4844WithSidecar(tx) => {
-                            tx.tx.nonce = nonce;
-                        }
+
+                // the block the stage is sealed in, when it targets a fork
+                let expected = head.number + 1;
+                let outcomes = backend.seal().await?;
+                if let Some(transition) = transition {
+                    let number = outcomes[0].block_number;
+                    if outcomes
+                        .iter()
+                        .any(|outcome| outcome.block_number != number)
+                    {
+                        return Err(unreachable(transition, "its stage spans several blocks"));
+                    }
+                }
+                // the code each transaction calls, deployments run init code without a source map
+                let mut origins = vec![];
+                for ((idx, to), outcome) in txs.into_iter().zip(&outcomes) {
+                    if let Some(address) = outcome.contract_address {
+                        deployed.insert(address, CodeOrigin::Deployment(idx));
+                    }
+                    let origin =
+                        to.map(|to| deployed.get(&to).copied().unwrap_or(CodeOrigin::Alloc(to)));
+                    origins.push(origin);
+                    if !outcome.success {
+                        report_failure(backend, &self.source_maps, idx, origin, outcome.hash).await;
+                    }
+                    let block_number = outcome.block_number;
+                    if blocks.contains(&block_number) {
+                        continue;
                     }
 
-                    let sig = signer.sign_transaction_sync(&mut tx).unwrap();
-                    TxEnvelope::Eip4844(tx.into_signed(sig))
+                    let info = backend.block(block_number).await?;
+                    if let Some(transition) = transition {
+                        check_transition(stage_idx, transition, expected, &info)?;
+                    }
+                    if info.number > head.number {
+                        head = info;
+                    }
+
+                    let witness = backend.dump_witness(block_number).await?;
+                    blocks.insert(block_number);
+                    let hardfork = Hardfork::active_at(&chain_config, block_number, info.timestamp);
+                    report
+                        .blocks
+                        .push(TracedBlock::new(info, hardfork, witness));
                 }
-                TypedTransaction::Eip7702(mut tx) => {
-                    tx.nonce = nonce;
-                    let sig = signer.sign_transaction_sync(&mut tx).unwrap();
-                    TxEnvelope::Eip7702(tx.into_signed(sig))
+
+                // once every contract the batch deploys is known
+                report_events(&self.abis, &deployed, first_idx, &outcomes);
+                for ((idx, outcome), origin) in (first_idx..).zip(outcomes).zip(origins) {
+                    let source_map = origin.and_then(|origin| self.source_maps.get(&origin));
+                    let mut traces = BTreeMap::new();
+                    for &kind in &self.traces {
+                        // the witnesses do not depend on them, a missing trace is only reported
+                        match backend.debug_trace(outcome.hash, kind).await {
+                            Ok(mut trace) => {
+                                if let Some(source_map) = source_map {
+                                    annotate_struct_logs(&mut trace, source_map);
+                                }
+                                traces.insert(kind, trace);
+                            }
+                            Err(e) => warn!(idx, %kind, "cannot collect the debug trace: {e}"),
+                        }
+                    }
+                    report.transactions.push(TracedTransaction {
+                        idx,
+                        outcome,
+                        traces,
+                    });
                 }
-                _ => unimplemented!(),
-            };
+            }
 
-            trace!(tx_envelope = ?tx_envelope);
-            let mut pending_tx = provider.send_tx_envelope(tx_envelope).await?;
-            pending_tx.set_required_confirmations(0);
-            txs.push(pending_tx);
+            if let Some(ForkTransition {
+                target: ForkTarget::Before(_),
+                activation: ForkCondition::Timestamp(timestamp),
+            }) = transition
+            {
+                // later blocks belong to the fork, leaving the stage in the last block before it
+                sleep_until(timestamp).await;
+            }
         }
+        check_order(&report.transactions, &self.transactions, self.order)?;
+        trace!(witnesses = %report.blocks.len());
 
-        provider.start_miner().await?;
+        Ok(report)
+    }
+}
 
-        let mut blocks = BTreeSet::new();
-        let mut witnesses = vec![];
-        for tx in txs {
-            let receipt = tx.get_receipt().await?;
-            let block_number = receipt.block_number().unwrap();
-            if blocks.contains(&block_number) {
+/// The base fee the block after `head` is expected to have, `None` before London.
+fn next_base_fee(head: &BlockInfo) -> Option<u64> {
+    let base_fee = head.base_fee?;
+    // the sequencer derives it from the L1 fee, the head's is the closest guess
+    if cfg!(feature = "scroll") {
+        return Some(base_fee);
+    }
+    Some(calc_next_block_base_fee(
+        head.gas_used,
+        head.gas_limit,
+        base_fee,
+        BaseFeeParams::ethereum(),
+    ))
+}
+
+/// Splits a stage where the miner would reorder it: across senders it picks the highest tip
+/// first, keeping arrival order among equal tips and nonce order within a sender.
+///
+/// Tips of dynamic fee transactions are compared at `base_fee`. Under Scroll that is only a
+/// guess, a block sealed at another base fee is caught by checking the order afterwards.
+fn split_for_order(
+    stage: &[ScheduledTransaction],
+    base_fee: Option<u64>,
+) -> Vec<&[ScheduledTransaction]> {
+    let tip = |scheduled: &ScheduledTransaction| {
+        scheduled
+            .tx
+            .effective_tip_per_gas(base_fee.unwrap_or_default())
+            .unwrap_or_default()
+    };
+    let overtakes = |earlier: &ScheduledTransaction, later: &ScheduledTransaction| {
+        earlier.from != later.from && tip(earlier) < tip(later)
+    };
+    let mut batches = vec![];
+    let mut start = 0;
+    for (idx, scheduled) in stage.iter().enumerate().skip(1) {
+        let overtakes = stage[start..idx]
+            .iter()
+            .any(|earlier| overtakes(earlier, scheduled));
+        if overtakes {
+            batches.push(&stage[start..idx]);
+            start = idx;
+        }
+    }
+    batches.push(&stage[start..]);
+    batches
+}
+
+/// Checks the transactions landed in the order they were declared.
+fn check_order(
+    transactions: &[TracedTransaction],
+    scheduled: &[ScheduledTransaction],
+    order: TxOrder,
+) -> Result<(), SpectreError> {
+    let landed = |tx: &TracedTransaction| (tx.outcome.block_number, tx.outcome.position);
+    for pair in transactions.windows(2) {
+        let [previous, tx] = pair else { unreachable!() };
+        if landed(tx) > landed(previous) {
+            continue;
+        }
+        let label = TxLabel::Scheduled {
+            idx: tx.idx,
+            name: scheduled[tx.idx].name.clone(),
+        };
+        match order {
+            TxOrder::Declared => {
+                return Err(SpectreError::OrderDeviation {
+                    tx: label,
+                    previous: previous.idx,
+                });
+            }
+            TxOrder::Pool => warn!("{label}: landed before transaction#{}", previous.idx),
+        }
+    }
+    Ok(())
+}
+
+/// Logs the events emitted by a stage in contracts with an ABI, decoded.
+fn report_events(
+    abis: &HashMap<CodeOrigin, JsonAbi>,
+    deployed: &HashMap<Address, CodeOrigin>,
+    stage_idx: usize,
+    outcomes: &[TxOutcome],
+) {
+    if abis.is_empty() {
+        return;
+    }
+    for (idx, outcome) in (stage_idx..).zip(outcomes) {
+        for log in &outcome.logs {
+            let address = log.address;
+            let abi = deployed
+                .get(&address)
+                .and_then(|origin| abis.get(origin))
+                .or_else(|| abis.get(&CodeOrigin::Alloc(address)));
+            let Some(event) = abi.and_then(|abi| decode_log(abi, &log.data)) else {
                 continue;
+            };
+            info!(idx, %address, "{event}");
+        }
+    }
+}
+
+/// Logs where a failed transaction stopped, with the mnemonic line when the called code has
+/// a source map.
+async fn report_failure<B: WitnessBackend>(
+    backend: &B,
+    source_maps: &HashMap<CodeOrigin, SourceMap>,
+    idx: usize,
+    origin: Option<CodeOrigin>,
+    hash: TxHash,
+) {
+    let step = match backend.last_step(hash).await {
+        Ok(step) => step,
+        Err(e) => {
+            warn!(idx, %hash, "transaction failed, cannot trace it: {e}");
+            return;
+        }
+    };
+    let Some(ExecutionStep { pc, op, depth }) = step else {
+        warn!(idx, %hash, "transaction failed before executing code");
+        return;
+    };
+    // deeper frames run other code, and the top frame of a deployment runs its init code
+    let location = origin
+        .filter(|_| depth == 1)
+        .and_then(|origin| source_maps.get(&origin)?.annotate(pc));
+    match location {
+        Some(location) => {
+            warn!(idx, %hash, "transaction failed at {op} pc {pc:#06x}, {location}")
+        }
+        None => warn!(idx, %hash, "transaction failed at {op} pc {pc:#06x}"),
+    }
+}
+
+/// Adds the mnemonic line to the struct logs steps of the top frame, under `source`. Deeper
+/// frames run other code.
+fn annotate_struct_logs(trace: &mut serde_json::Value, source_map: &SourceMap) {
+    let Some(steps) = trace
+        .get_mut("structLogs")
+        .and_then(|steps| steps.as_array_mut())
+    else {
+        return;
+    };
+    for step in steps.iter_mut().filter_map(|step| step.as_object_mut()) {
+        if step.get("depth").and_then(|depth| depth.as_u64()) != Some(1) {
+            continue;
+        }
+        let location = step
+            .get("pc")
+            .and_then(|pc| pc.as_u64())
+            .and_then(|pc| source_map.annotate(pc as usize));
+        if let Some(location) = location {
+            step.insert("source".to_string(), location.into());
+        }
+    }
+}
+
+fn sign_transaction(signer: &PrivateKeySigner, tx: TypedTransaction, nonce: u64) -> TxEnvelope {
+    match tx {
+        TypedTransaction::Legacy(mut tx) => {
+            tx.nonce = nonce;
+            let sig = signer.sign_transaction_sync(&mut tx).unwrap();
+            TxEnvelope::Legacy(tx.into_signed(sig))
+        }
+        TypedTransaction::Eip2930(mut tx) => {
+            tx.nonce = nonce;
+            let sig = signer.sign_transaction_sync(&mut tx).unwrap();
+            TxEnvelope::Eip2930(tx.into_signed(sig))
+        }
+        TypedTransaction::Eip1559(mut tx) => {
+            tx.nonce = nonce;
+            let sig = signer.sign_transaction_sync(&mut tx).unwrap();
+            TxEnvelope::Eip1559(tx.into_signed(sig))
+        }
+        #[cfg(not(feature = "scroll"))]
+        TypedTransaction::Eip4844(mut tx) => {
+            match &mut tx {
+                TxEip4844Variant::TxEip4844(tx) => {
+                    tx.nonce = nonce;
+                }
+                TxEip4844Variant::TxEip4844WithSidecar(tx) => {
+                    tx.tx.nonce = nonce;
+                }
             }
 
-            let witness = provider
-                .dump_block_witness(block_number.into())
-                .await?
-                .unwrap();
-            blocks.insert(block_number);
-            witnesses.push(witness);
+            let sig = signer.sign_transaction_sync(&mut tx).unwrap();
+            TxEnvelope::Eip4844(tx.into_signed(sig))
         }
-        trace!(witnesses = %witnesses.len());
+        TypedTransaction::Eip7702(mut tx) => {
+            tx.nonce = nonce;
+            let sig = signer.sign_transaction_sync(&mut tx).unwrap();
+            TxEnvelope::Eip7702(tx.into_signed(sig))
+        }
+        _ => unreachable!("the builder rejects other transaction types"),
+    }
+}
 
-        Ok(witnesses)
+/// Nonce of the next transaction from `from`, counting the ones already sent.
+async fn next_nonce<B: WitnessBackend>(
+    backend: &B,
+    nonce_map: &mut HashMap<Address, u64>,
+    from: Address,
+) -> Result<u64, SpectreError> {
+    let nonce = match nonce_map.get(&from) {
+        Some(&nonce) => nonce,
+        None => backend.nonce(from).await?,
+    };
+    nonce_map.insert(from, nonce + 1);
+    Ok(nonce)
+}
+
+/// Seals a block after `head` holding only a self-transfer of the filler account, at the lowest
+/// gas price the node takes.
+async fn seal_filler<B: WitnessBackend>(
+    backend: &mut B,
+    nonce_map: &mut HashMap<Address, u64>,
+    signer: &PrivateKeySigner,
+    chain_id: u64,
+    idx: usize,
+    head: &BlockInfo,
+) -> Result<BlockInfo, SpectreError> {
+    let from = signer.address();
+    let nonce = next_nonce(backend, nonce_map, from).await?;
+    let base_fee = next_base_fee(head).unwrap_or_default() as u128;
+    let tx = TxLegacy {
+        chain_id: Some(chain_id),
+        nonce,
+        gas_price: base_fee.max(testnet::MIN_GAS_PRICE),
+        gas_limit: FILLER_GAS_LIMIT,
+        to: TxKind::Call(from),
+        value: U256::ZERO,
+        input: Bytes::new(),
+    };
+    let tx_envelope = sign_transaction(signer, TypedTransaction::Legacy(tx), nonce);
+    backend
+        .submit(tx_envelope, TxLabel::Filler { before: idx })
+        .await?;
+    let outcomes = backend.seal().await?;
+    debug!(
+        before = idx,
+        number = outcomes[0].block_number,
+        "filler block sealed"
+    );
+    backend.block(outcomes[0].block_number).await
+}
+
+fn activation_text(activation: &Option<ForkCondition>) -> String {
+    match activation {
+        Some(activation) => format!("at {activation}"),
+        None => "never".to_string(),
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn sleep_until(timestamp: u64) {
+    let now = now();
+    if now < timestamp {
+        debug!(wait = timestamp - now, "waiting for the clock");
+        tokio::time::sleep(Duration::from_secs(timestamp - now)).await;
+    }
+}
+
+/// Checks a fork-targeted stage can still land in its block, and waits for the clock when the
+/// block has to be sealed after a fork time. Returns the block number the stage has to land in
+/// when the fork activates by block, filler blocks have to be sealed up to it.
+///
+/// Clique seals at `max(parent.time, now)`, so block timestamps follow the wall clock.
+async fn prepare_transition(
+    idx: usize,
+    transition: ForkTransition,
+    head: &BlockInfo,
+) -> Result<Option<u64>, SpectreError> {
+    let unreachable = |reason| SpectreError::ForkTargetUnreachable {
+        idx,
+        target: transition.target,
+        reason,
+    };
+    let sealed = "the block it targets is already sealed";
+    match (transition.target, transition.activation) {
+        (ForkTarget::Before(_), ForkCondition::Block(number)) => {
+            if head.number + 1 >= number {
+                return Err(unreachable(sealed));
+            }
+            Ok(Some(number - 1))
+        }
+        (ForkTarget::After(_), ForkCondition::Block(number)) => {
+            if head.number >= number {
+                return Err(unreachable(sealed));
+            }
+            Ok(Some(number))
+        }
+        (ForkTarget::Before(_), ForkCondition::Timestamp(timestamp)) => {
+            if head.timestamp >= timestamp {
+                return Err(unreachable(sealed));
+            }
+            if now() + SEAL_MARGIN >= timestamp {
+                return Err(unreachable(
+                    "too close to the fork time to be sealed before it",
+                ));
+            }
+            Ok(None)
+        }
+        (ForkTarget::After(_), ForkCondition::Timestamp(timestamp)) => {
+            if head.timestamp >= timestamp {
+                return Err(unreachable(sealed));
+            }
+            sleep_until(timestamp).await;
+            Ok(None)
+        }
+    }
+}
+
+/// Checks a stage landed in the block its fork target asks for, `expected` being the one after
+/// the head the stage was prepared on.
+fn check_transition(
+    idx: usize,
+    transition: ForkTransition,
+    expected: u64,
+    info: &BlockInfo,
+) -> Result<(), SpectreError> {
+    let landed = match (transition.target, transition.activation) {
+        (ForkTarget::Before(_), ForkCondition::Block(number)) => info.number + 1 == number,
+        (ForkTarget::After(_), ForkCondition::Block(number)) => info.number == number,
+        (ForkTarget::Before(_), ForkCondition::Timestamp(timestamp)) => info.timestamp < timestamp,
+        (ForkTarget::After(_), ForkCondition::Timestamp(timestamp)) => info.timestamp >= timestamp,
+    };
+    if landed && info.number == expected {
+        Ok(())
+    } else {
+        Err(SpectreError::ForkTargetMissed {
+            idx,
+            target: transition.target,
+            activation: transition.activation,
+            block: info.number,
+            timestamp: info.timestamp,
+        })
     }
 }
 
 #[cfg(feature = "cli")]
 mod display {
     use super::*;
-    use crate::utils::*;
-    use alloy_consensus::Transaction;
+    use crate::{asm::disassemble, utils::*};
     use alloy_genesis::GenesisAccount;
     use console::{Emoji, style};
     use std::fmt::Display;
@@ -149,6 +883,7 @@ mod display {
         typed_tx: &'a TypedTransaction,
     }
 
+    /// The alternate form `{:#}` also prints the disassembled code of allocs.
     impl Display for Spectre {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             writeln!(
@@ -169,10 +904,36 @@ mod display {
                 } else {
                     write!(f, "{} ", Emoji("- 🔐", "- [ wallet]"),)?;
                 }
-                writeln!(f, "{}", DisplayAccount {
+                write!(f, "{}", DisplayAccount {
                     addr: address,
                     acc: account
                 })?;
+                if let Some(files) = self.alloc_files.get(address) {
+                    let files = [("code", &files.code), ("storage", &files.storage)]
+                        .into_iter()
+                        .filter_map(|(blob, path)| {
+                            Some(format!("{blob}: {}", path.as_ref()?.display()))
+                        })
+                        .collect::<Vec<_>>();
+                    write!(f, " | {} {}", Emoji("📄", "files"), files.join(", "))?;
+                }
+                writeln!(f)?;
+                if let Some(code) = account.code.as_ref().filter(|_| f.alternate()) {
+                    // mnemonic code is listed as written, with the offset of each line
+                    match self.source_map(CodeOrigin::Alloc(*address)) {
+                        Some(source_map) => {
+                            for span in source_map.spans().iter().filter(|span| span.len > 0) {
+                                let text = source_map.text(&span.line).unwrap_or_default();
+                                writeln!(f, "    {:04x}: {}", span.offset, style(text).dim())?;
+                            }
+                        }
+                        None => {
+                            for line in disassemble(code).lines() {
+                                writeln!(f, "    {}", style(line).dim())?;
+                            }
+                        }
+                    }
+                }
             }
             writeln!(
                 f,
@@ -180,8 +941,83 @@ mod display {
                 Emoji("💸", ""),
                 self.transactions.len()
             )?;
-            for (from, tx) in &self.transactions {
-                writeln!(f, "- {}", DisplayTransaction { from, typed_tx: tx })?;
+            for tx in &self.transactions {
+                write!(f, "- {}", DisplayTransaction {
+                    from: &tx.from,
+                    typed_tx: &tx.tx
+                })?;
+                if let Some(transition) = tx.fork_transition {
+                    write!(f, " | {} {}", Emoji("⏱️", "at"), transition.target)?;
+                }
+                if let Some(path) = &tx.input_file {
+                    write!(f, " | {} input: {}", Emoji("📄", "files"), path.display())?;
+                }
+                writeln!(f)?;
+            }
+            if !self.warnings.is_empty() {
+                writeln!(
+                    f,
+                    "\n{} {} warnings:",
+                    Emoji("⚠️", ""),
+                    self.warnings.len()
+                )?;
+                for warning in &self.warnings {
+                    writeln!(f, "- {}", style(warning).yellow())?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Blocks in order, each with the transactions it includes.
+    impl Display for TraceReport {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            writeln!(f, "{} {} blocks:", Emoji("📦", ""), self.blocks.len())?;
+            for block in &self.blocks {
+                write!(f, "- #{}", block.number)?;
+                if let Some(hardfork) = block.hardfork {
+                    write!(f, " ({hardfork})")?;
+                }
+                writeln!(
+                    f,
+                    " {} | {} {} | {} {} gas | {} txs",
+                    block.hash,
+                    Emoji("⏱️", "at"),
+                    block.timestamp,
+                    Emoji("⛽", "used"),
+                    block.gas_used,
+                    block.tx_count
+                )?;
+                let transactions = self
+                    .transactions
+                    .iter()
+                    .filter(|tx| tx.outcome.block_number == block.number);
+                for TracedTransaction { idx, outcome, .. } in transactions {
+                    let status = if outcome.success {
+                        style(Emoji("✅", "ok")).green()
+                    } else {
+                        style(Emoji("❌", "failed")).red()
+                    };
+                    write!(
+                        f,
+                        "  - tx#{idx} {} {status} | position {} | {} {} gas at {:?}",
+                        outcome.hash,
+                        outcome.position,
+                        Emoji("⛽", "used"),
+                        outcome.gas_used,
+                        Ether(U256::from(outcome.effective_gas_price)),
+                    )?;
+                    if !outcome.logs.is_empty() {
+                        write!(f, " | {} logs", outcome.logs.len())?;
+                    }
+                    if let Some(address) = outcome.contract_address {
+                        write!(f, " | {} {address}", Emoji("🆕", "created"))?;
+                    }
+                    if self.splits.contains(idx) {
+                        write!(f, " | split off to keep the declared order")?;
+                    }
+                    writeln!(f)?;
+                }
             }
             Ok(())
         }
@@ -239,3 +1075,394 @@ mod display {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use alloy_consensus::TxEip1559;
+    use alloy_primitives::address;
+
+    #[test]
+    fn test_reject_reason() {
+        let reasons = [
+            ("transaction underpriced", RejectReason::Underpriced),
+            (
+                "replacement transaction underpriced",
+                RejectReason::Underpriced,
+            ),
+            (
+                "nonce too low: next nonce 3, tx nonce 1",
+                RejectReason::NonceTooLow,
+            ),
+            (
+                "insufficient funds for gas * price + value: balance 0",
+                RejectReason::InsufficientFunds,
+            ),
+            (
+                "intrinsic gas too low: gas 0, minimum needed 21000",
+                RejectReason::IntrinsicGas,
+            ),
+            (
+                "already known",
+                RejectReason::Other("already known".to_string()),
+            ),
+        ];
+        for (message, reason) in reasons {
+            assert_eq!(RejectReason::from_message(message), reason);
+        }
+
+        let tx = TxLabel::Scheduled {
+            idx: 3,
+            name: Some("approve".to_string()),
+        };
+        assert_eq!(tx.to_string(), "transaction#3 (approve)");
+    }
+
+    fn scheduled(from: Address, gas_price: u128) -> ScheduledTransaction {
+        ScheduledTransaction {
+            name: None,
+            from,
+            fork_transition: None,
+            input_file: None,
+            tx: TypedTransaction::Legacy(TxLegacy {
+                gas_price,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_split_for_order() {
+        let alice = address!("0x00000000000000000000000000000000000000a1");
+        let bob = address!("0x00000000000000000000000000000000000000b0");
+        let batch_lens = |stage: &[ScheduledTransaction]| {
+            split_for_order(stage, Some(7))
+                .iter()
+                .map(|batch| batch.len())
+                .collect::<Vec<_>>()
+        };
+
+        let stage = [scheduled(alice, 10), scheduled(bob, 10), scheduled(bob, 9)];
+        assert_eq!(batch_lens(&stage), [3]);
+        // a sender's own transactions stay in nonce order whatever their tips
+        let stage = [scheduled(alice, 10), scheduled(alice, 20)];
+        assert_eq!(batch_lens(&stage), [2]);
+        let stage = [
+            scheduled(alice, 10),
+            scheduled(bob, 20),
+            scheduled(alice, 15),
+        ];
+        assert_eq!(batch_lens(&stage), [1, 2]);
+        // tips are compared above the base fee
+        let stage = [scheduled(alice, 5), scheduled(bob, 7)];
+        assert_eq!(batch_lens(&stage), [2]);
+
+        // a lower max fee overtakes a higher priority fee once the base fee is high enough
+        let dynamic = |from, max_fee_per_gas, max_priority_fee_per_gas| {
+            let mut scheduled = scheduled(from, 0);
+            scheduled.tx = TypedTransaction::Eip1559(TxEip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+                ..Default::default()
+            });
+            scheduled
+        };
+        let stage = [dynamic(alice, 10, 5), dynamic(bob, 20, 4)];
+        assert_eq!(split_for_order(&stage, Some(2)).len(), 1);
+        assert_eq!(batch_lens(&stage), [1, 1]);
+        // without a base fee the tips are compared whole
+        let stage = [scheduled(alice, 10), dynamic(bob, 20, 9), scheduled(bob, 9)];
+        assert_eq!(split_for_order(&stage, None).len(), 1);
+        let stage = [scheduled(alice, 10), dynamic(bob, 20, 11)];
+        assert_eq!(split_for_order(&stage, None).len(), 2);
+    }
+
+    #[test]
+    fn test_check_order() {
+        let landed = |idx, block_number, position| TracedTransaction {
+            idx,
+            outcome: TxOutcome {
+                hash: TxHash::ZERO,
+                block_number,
+                position,
+                success: true,
+                gas_used: 0,
+                effective_gas_price: 0,
+                contract_address: None,
+                logs: vec![],
+            },
+            traces: BTreeMap::new(),
+        };
+        let scheduled = [
+            scheduled(Address::ZERO, 1),
+            scheduled(Address::ZERO, 1),
+            scheduled(Address::ZERO, 1),
+        ];
+
+        let transactions = [landed(0, 1, 0), landed(1, 1, 1), landed(2, 2, 0)];
+        assert!(check_order(&transactions, &scheduled, TxOrder::Declared).is_ok());
+
+        let transactions = [landed(0, 1, 1), landed(1, 1, 0), landed(2, 2, 0)];
+        let err = check_order(&transactions, &scheduled, TxOrder::Declared).unwrap_err();
+        assert!(matches!(
+            err,
+            SpectreError::OrderDeviation {
+                tx: TxLabel::Scheduled { idx: 1, .. },
+                previous: 0,
+            }
+        ));
+        assert!(check_order(&transactions, &scheduled, TxOrder::Pool).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fork_transition() {
+        let block = |number, timestamp| BlockInfo {
+            number,
+            hash: B256::ZERO,
+            state_root: B256::ZERO,
+            receipts_root: B256::ZERO,
+            timestamp,
+            gas_limit: 30_000_000,
+            gas_used: 0,
+            tx_count: 0,
+            base_fee: None,
+        };
+        let fork = Hardfork::ALL[0];
+        let transition = |target, activation| ForkTransition { target, activation };
+
+        // block forks are reached by sealing up to the block before or at the activation
+        let before = transition(ForkTarget::Before(fork), ForkCondition::Block(5));
+        assert_eq!(
+            prepare_transition(0, before, &block(1, 0)).await.unwrap(),
+            Some(4)
+        );
+        assert!(prepare_transition(0, before, &block(4, 0)).await.is_err());
+        assert!(check_transition(0, before, 4, &block(4, 0)).is_ok());
+        assert!(check_transition(0, before, 4, &block(5, 0)).is_err());
+        let after = transition(ForkTarget::After(fork), ForkCondition::Block(5));
+        assert_eq!(
+            prepare_transition(0, after, &block(4, 0)).await.unwrap(),
+            Some(5)
+        );
+        assert!(matches!(
+            prepare_transition(0, after, &block(5, 0)).await,
+            Err(SpectreError::ForkTargetUnreachable { idx: 0, .. })
+        ));
+        assert!(check_transition(0, after, 5, &block(5, 0)).is_ok());
+
+        // time forks are checked against the block the stage was expected in
+        let after = transition(ForkTarget::After(fork), ForkCondition::Timestamp(1000));
+        assert_eq!(
+            prepare_transition(0, after, &block(1, 10)).await.unwrap(),
+            None
+        );
+        assert!(prepare_transition(0, after, &block(1, 1000)).await.is_err());
+        assert!(check_transition(0, after, 2, &block(2, 1000)).is_ok());
+        assert!(matches!(
+            check_transition(0, after, 2, &block(3, 1001)),
+            Err(SpectreError::ForkTargetMissed { block: 3, .. })
+        ));
+        let before = transition(ForkTarget::Before(fork), ForkCondition::Timestamp(1000));
+        assert!(check_transition(0, before, 2, &block(2, 999)).is_ok());
+        assert!(check_transition(0, before, 2, &block(2, 1000)).is_err());
+        // the wall clock is long past the activation
+        assert!(prepare_transition(0, before, &block(1, 10)).await.is_err());
+    }
+
+    fn mock_spectre(extra: &str) -> Spectre {
+        let builder: crate::SpectreBuilder = toml::from_str(&format!(
+            r#"
+            {extra}
+            [[wallet]]
+            name = "alice"
+            [[wallet]]
+            name = "bob"
+            "#
+        ))
+        .unwrap();
+        builder.build().unwrap()
+    }
+
+    fn transfer(from: &str, to: &str, gas_price: u64, at: Option<&str>) -> String {
+        let at = at.map(|at| format!("at = \"{at}\"")).unwrap_or_default();
+        format!(
+            r#"
+            [[transactions]]
+            from = "{from}"
+            to = "{to}"
+            gas-price = "{gas_price} Gwei"
+            gas-limit = 21000
+            {at}
+            "#
+        )
+    }
+
+    #[tokio::test]
+    async fn test_trace_with() {
+        let reverting = address!("0x000000000000000000000000000000000000dead");
+        let spectre = mock_spectre(&format!(
+            "[system]\ntraces = [\"call-tracer\", \"prestate-tracer\"]\n{}{}{}",
+            transfer("alice", "bob", 10, None),
+            transfer("bob", &reverting.to_string(), 20, None),
+            transfer("alice", "bob", 15, None),
+        ));
+        let mut backend = MockBackend {
+            reverting: [reverting].into(),
+            ..Default::default()
+        };
+        let report = spectre.trace_with(&mut backend).await.unwrap();
+
+        // bob's higher tip would overtake alice, so it waits for the next block
+        let landed = report
+            .transactions
+            .iter()
+            .map(|tx| (tx.idx, tx.outcome.block_number, tx.outcome.position))
+            .collect::<Vec<_>>();
+        assert_eq!(landed, [(0, 1, 0), (1, 2, 0), (2, 2, 1)]);
+        assert_eq!(report.splits, [1]);
+        let blocks = report.blocks.iter().map(|block| block.number);
+        assert_eq!(blocks.collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(report.blocks[1].tx_count, 2);
+
+        let failed = report
+            .transactions
+            .iter()
+            .filter(|tx| !tx.outcome.success)
+            .map(|tx| tx.outcome.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, [report.transactions[1].outcome.hash]);
+        assert_eq!(*backend.traced_failures.lock().unwrap(), failed);
+        for tx in &report.transactions {
+            let trace = &tx.traces[&TraceKind::CallTracer];
+            assert_eq!(trace["hash"], tx.outcome.hash.to_string());
+            // the backend failing one kind leaves the others
+            assert!(!tx.traces.contains_key(&TraceKind::PrestateTracer));
+        }
+
+        // the miner's order is kept with `order = "pool"`
+        let spectre = mock_spectre(&format!(
+            "[system]\norder = \"pool\"\n{}{}",
+            transfer("alice", "bob", 10, None),
+            transfer("bob", "alice", 20, None),
+        ));
+        let report = spectre
+            .trace_with(&mut MockBackend::default())
+            .await
+            .unwrap();
+        assert_eq!(report.blocks.len(), 1);
+        assert_eq!(report.transactions[0].outcome.position, 1);
+    }
+
+    #[tokio::test]
+    async fn test_trace_with_struct_logs() {
+        let code = address!("0x000000000000000000000000000000000000c0de");
+        let spectre = mock_spectre(&format!(
+            r#"
+            [system]
+            traces = ["struct-logs"]
+            [[alloc]]
+            address = "{code}"
+            balance = "0 wei"
+            code = "STOP"
+            {}{}
+            "#,
+            transfer("alice", &code.to_string(), 10, None),
+            transfer("alice", "bob", 10, None),
+        ));
+        let report = spectre
+            .trace_with(&mut MockBackend::default())
+            .await
+            .unwrap();
+
+        // only the top frame of assembled code is annotated
+        let steps = &report.transactions[0].traces[&TraceKind::StructLogs]["structLogs"];
+        assert_eq!(steps[0]["source"], "line 1: STOP");
+        assert!(steps[1].get("source").is_none());
+        let steps = &report.transactions[1].traces[&TraceKind::StructLogs]["structLogs"];
+        assert!(steps[0].get("source").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_trace_with_fork_stages() {
+        let fork = Hardfork::ALL[0];
+        let chain = |block: u64| format!("[chain]\n{fork}-block = {block}\n");
+
+        // filler blocks lead up to the fork, the stage lands in its first block
+        let after = format!("after {fork}");
+        let spectre = mock_spectre(&format!(
+            "{}{}{}{}",
+            chain(4),
+            transfer("alice", "bob", 10, None),
+            transfer("alice", "bob", 10, Some(&after)),
+            transfer("bob", "alice", 10, Some(&after)),
+        ));
+        let mut backend = MockBackend::default();
+        let report = spectre.trace_with(&mut backend).await.unwrap();
+        let blocks = report.blocks.iter().map(|block| block.number);
+        assert_eq!(blocks.collect::<Vec<_>>(), [1, 4]);
+        assert_eq!(report.transactions[2].outcome.block_number, 4);
+        for (info, labels) in &backend.blocks[1..3] {
+            assert_eq!(labels, &[TxLabel::Filler { before: 1 }], "{info:?}");
+        }
+        // paid by the filler account rather than the senders of the stage
+        assert_eq!(backend.senders[1..3], [filler_signer().address(); 2]);
+        assert_eq!(backend.blocks.len(), 4);
+
+        let before = format!("before {fork}");
+        let spectre = mock_spectre(&format!(
+            "{}{}",
+            chain(3),
+            transfer("alice", "bob", 10, Some(&before)),
+        ));
+        let report = spectre
+            .trace_with(&mut MockBackend::default())
+            .await
+            .unwrap();
+        assert_eq!(report.blocks[0].number, 2);
+
+        // the block before the fork is taken by an earlier stage
+        let spectre = mock_spectre(&format!(
+            "{}{}{}",
+            chain(2),
+            transfer("alice", "bob", 10, None),
+            transfer("alice", "bob", 10, Some(&before)),
+        ));
+        assert!(matches!(
+            spectre.trace_with(&mut MockBackend::default()).await,
+            Err(SpectreError::ForkTargetUnreachable { idx: 1, .. })
+        ));
+    }
+
+    #[cfg(feature = "scroll")]
+    #[tokio::test]
+    async fn test_trace_with_dynamic_fee_stage() {
+        // senders mixing legacy and dynamic fees share the block at the fork, their tips are
+        // compared although the sequencer's base fee is not predicted
+        let spectre = mock_spectre(&format!(
+            r#"
+            [genesis]
+            timestamp = 1000
+            [chain]
+            euclidv2-time = "+1"
+            {}
+            [[transactions]]
+            type = 2
+            from = "bob"
+            to = "alice"
+            max-fee-per-gas = "20 Gwei"
+            max-priority-fee-per-gas = "2 Gwei"
+            gas-limit = 21000
+            at = "after euclidv2"
+            "#,
+            transfer("alice", "bob", 3, Some("after euclidv2")),
+        ));
+        let report = spectre
+            .trace_with(&mut MockBackend::default())
+            .await
+            .unwrap();
+        assert_eq!(report.blocks.len(), 1);
+        assert_eq!(report.blocks[0].hardfork, Some(Hardfork::EuclidV2));
+        assert!(report.splits.is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{
+    builder::{BuilderError, SpectreBuilder, TransactionBuilder, resolve_address},
+    utils::{Ether, deploy_code},
+};
+use alloy_consensus::TxType;
+use alloy_primitives::{Address, B256, Bytes, U256};
+use alloy_rpc_types_eth::{AccessList, AccessListItem};
+use alloy_signer_local::PrivateKeySigner;
+use rand::{Rng, RngCore, rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const TRANSFER_GAS_LIMIT: u64 = 21_000;
+const FALLBACK_GAS_LIMIT: u64 = 1_000_000;
+/// Keeps deployed code under the EIP-170 limit and the init code prefix in a `PUSH2`.
+const MAX_CODE_SIZE: usize = 0x6000;
+
+/// The `[generate]` section, random transactions drawn from the seeded rng.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GenerateBuilder {
+    /// Number of transactions to generate.
+    pub count: usize,
+    /// Wallets allowed to send, defaults to every wallet.
+    #[serde(default)]
+    pub senders: Option<Vec<String>>,
+    #[serde(default = "default_tx_types")]
+    pub types: Vec<u8>,
+    #[serde(default)]
+    pub weights: GenerateWeights,
+    #[serde(default = "default_max_calldata")]
+    pub max_calldata: usize,
+    #[serde(default = "default_max_code")]
+    pub max_code: usize,
+}
+
+/// Relative weights of the generated transaction kinds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GenerateWeights {
+    #[serde(default = "default_weight")]
+    pub transfer: u32,
+    #[serde(default = "default_weight")]
+    pub call: u32,
+    #[serde(default = "default_weight")]
+    pub create: u32,
+}
+
+impl Default for GenerateWeights {
+    fn default() -> Self {
+        GenerateWeights {
+            transfer: default_weight(),
+            call: default_weight(),
+            create: default_weight(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Kind {
+    Transfer,
+    Call,
+    Create,
+}
+
+impl GenerateBuilder {
+    /// Generates transactions on top of the ones already declared in the builder.
+    ///
+    /// Each sender is given a budget from its alloc balance, minus the worst case cost of the
+    /// declared transactions, and generated transactions never spend more than what is left.
+    /// Nonces are assigned in order when tracing, so they stay consistent as well.
+    pub(crate) fn generate(
+        &self,
+        rng: &mut StdRng,
+        builder: &SpectreBuilder,
+        wallets: &HashMap<String, PrivateKeySigner>,
+    ) -> Result<Vec<TransactionBuilder>, BuilderError> {
+        for &tx_type in &self.types {
+            if !matches!(
+                TxType::try_from(tx_type),
+                Ok(TxType::Legacy | TxType::Eip2930 | TxType::Eip1559)
+            ) {
+                return Err(BuilderError::GenerateTxType { tx_type });
+            }
+        }
+        if self.types.is_empty() {
+            return Err(BuilderError::GenerateTxType { tx_type: 0 });
+        }
+
+        let addresses = wallets
+            .iter()
+            .map(|(name, wallet)| (name.clone(), wallet.address()))
+            .collect::<HashMap<_, _>>();
+        let balance_of = |address: Address| {
+            builder
+                .alloc
+                .iter()
+                .find(|alloc| resolve_address(&alloc.address, &addresses) == Some(address))
+                .and_then(|alloc| alloc.balance.or(builder.defaults.account_balance))
+                .map(|balance| balance.0)
+                .unwrap_or_default()
+        };
+
+        let mut senders = self
+            .senders
+            .clone()
+            .unwrap_or_else(|| builder.wallet.iter().map(|w| w.name.clone()).collect());
+        senders.sort();
+        senders.dedup();
+        let mut budgets = senders
+            .into_iter()
+            .map(|name| {
+                let address = *addresses
+                    .get(&name)
+                    .ok_or_else(|| BuilderError::GenerateSenderNotFound { name: name.clone() })?;
+                Ok((name, balance_of(address)))
+            })
+            .collect::<Result<Vec<(String, U256)>, BuilderError>>()?;
+        for tx in &builder.transactions {
+            if let Some((_, budget)) = budgets.iter_mut().find(|(name, _)| *name == tx.from) {
+                *budget = budget.saturating_sub(self.worst_case_cost(tx, builder));
+            }
+        }
+
+        let contracts = builder
+            .alloc
+            .iter()
+            .filter(|alloc| alloc.code.is_some())
+            .map(|alloc| alloc.address.clone())
+            .collect::<Vec<_>>();
+        let recipients = builder
+            .wallet
+            .iter()
+            .map(|wallet| wallet.name.clone())
+            .chain(builder.alloc.iter().map(|alloc| alloc.address.clone()))
+            .collect::<Vec<_>>();
+        let weights = [
+            (Kind::Transfer, self.weights.transfer),
+            (
+                Kind::Call,
+                if contracts.is_empty() {
+                    0
+                } else {
+                    self.weights.call
+                },
+            ),
+            (Kind::Create, self.weights.create),
+        ];
+
+        let mut transactions = Vec::with_capacity(self.count);
+        for _ in 0..self.count {
+            let kind = weights
+                .choose_weighted(rng, |(_, weight)| *weight)
+                .map_err(|_| BuilderError::GenerateNoWeights)?
+                .0;
+            let transaction_type = *self.types.choose(rng).unwrap();
+            let mut tx = TransactionBuilder {
+                transaction_type,
+                ..Default::default()
+            };
+            match kind {
+                Kind::Transfer => {
+                    tx.to = Some(if recipients.is_empty() || rng.gen_ratio(1, 4) {
+                        random_address(rng).to_string()
+                    } else {
+                        recipients.choose(rng).unwrap().clone()
+                    });
+                    // the recipient may be a contract, only plain accounts get the exact limit
+                    if !contracts.contains(tx.to.as_ref().unwrap()) {
+                        tx.gas_limit = Some(TRANSFER_GAS_LIMIT);
+                    }
+                }
+                Kind::Call => {
+                    tx.to = Some(contracts.choose(rng).unwrap().clone());
+                    tx.input = Some(random_bytes(rng, self.max_calldata));
+                }
+                Kind::Create => {
+                    let runtime = random_runtime(rng, self.max_code.min(MAX_CODE_SIZE));
+                    tx.input = Some(deploy_code(&runtime).expect("bounded by MAX_CODE_SIZE"));
+                }
+            }
+            if transaction_type != 0 && rng.gen_bool(0.5) {
+                tx.access_list = Some(random_access_list(rng, &contracts, &addresses));
+            }
+            if let (Some(gas_limit), Some(access_list)) = (&mut tx.gas_limit, &tx.access_list) {
+                // keep exact transfers above the intrinsic gas of the access list
+                *gas_limit += access_list
+                    .iter()
+                    .map(|item| 2400 + 1900 * item.storage_keys.len() as u64)
+                    .sum::<u64>();
+            }
+            if tx.gas_limit.is_none() {
+                tx.gas_limit = Some(builder.defaults.tx_gas_limit.unwrap_or(FALLBACK_GAS_LIMIT));
+            }
+
+            let fee = self.worst_case_cost(&tx, builder);
+            let funded = (0..budgets.len())
+                .filter(|&idx| budgets[idx].1 >= fee)
+                .collect::<Vec<_>>();
+            let Some(&sender) = funded.choose(rng) else {
+                return Err(BuilderError::GenerateNoFunds {
+                    generated: transactions.len(),
+                });
+            };
+            let (from, budget) = &mut budgets[sender];
+            *budget -= fee;
+            tx.from = from.clone();
+            if !matches!(kind, Kind::Create) && !budget.is_zero() && rng.gen_bool(0.75) {
+                // spend at most a quarter of what is left, so later transactions stay funded
+                let value = U256::from(rng.next_u64()) % (*budget / U256::from(4) + U256::from(1));
+                *budget -= value;
+                tx.value = Some(Ether(value));
+            }
+            transactions.push(tx);
+        }
+        Ok(transactions)
+    }
+
+    /// `value + gas_limit * max_price`, falling back to the builder defaults.
+    fn worst_case_cost(&self, tx: &TransactionBuilder, builder: &SpectreBuilder) -> U256 {
+        let defaults = &builder.defaults;
+        let gas_limit = tx
+            .gas_limit
+            .or(defaults.tx_gas_limit)
+            .unwrap_or(FALLBACK_GAS_LIMIT);
+        let price = match tx.transaction_type {
+            0x02 => tx.max_fee_per_gas.or(defaults.tx_max_fee_per_gas),
+            _ => tx.gas_price.or(defaults.tx_gas_price),
+        }
+        .unwrap_or_default()
+        .0;
+        tx.value.unwrap_or_default().0 + U256::from(gas_limit) * price
+    }
+}
+
+fn random_bytes(rng: &mut StdRng, max_len: usize) -> Bytes {
+    let mut bytes = vec![0u8; rng.gen_range(0..=max_len)];
+    rng.fill_bytes(&mut bytes);
+    bytes.into()
+}
+
+/// Random code a create can deploy, EIP-3541 rejects code starting with 0xEF.
+fn random_runtime(rng: &mut StdRng, max_len: usize) -> Bytes {
+    let mut runtime = random_bytes(rng, max_len).to_vec();
+    if let Some(first @ 0xef) = runtime.first_mut() {
+        *first = 0x00;
+    }
+    runtime.into()
+}
+
+fn random_address(rng: &mut StdRng) -> Address {
+    let mut address = Address::ZERO;
+    rng.fill_bytes(address.as_mut_slice());
+    address
+}
+
+fn random_access_list(
+    rng: &mut StdRng,
+    contracts: &[String],
+    addresses: &HashMap<String, Address>,
+) -> AccessList {
+    let items = (0..rng.gen_range(1..=3))
+        .map(|_| AccessListItem {
+            address: contracts
+                .choose(rng)
+                .and_then(|contract| resolve_address(contract, addresses))
+                .unwrap_or_else(|| random_address(rng)),
+            storage_keys: (0..rng.gen_range(0..=3))
+                .map(|_| B256::from(U256::from(rng.gen_range(0u64..8)).to_be_bytes()))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    AccessList(items)
+}
+
+fn default_tx_types() -> Vec<u8> {
+    vec![0x00, 0x01, 0x02]
+}
+
+const fn default_weight() -> u32 {
+    1
+}
+
+const fn default_max_calldata() -> usize {
+    128
+}
+
+const fn default_max_code() -> usize {
+    64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const BUILDER: &str = r#"
+        [system]
+        random-seed = 42
+
+        [defaults]
+        tx-gas-price = "1 Gwei"
+        tx-max-fee-per-gas = "2 Gwei"
+        tx-max-priority-fee-per-gas = "1 Gwei"
+
+        [[wallet]]
+        name = "alice"
+        [[wallet]]
+        name = "bob"
+
+        [[alloc]]
+        address = "alice"
+        balance = "1 Ether"
+        [[alloc]]
+        address = "bob"
+        balance = "1 Ether"
+        [[alloc]]
+        address = "0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef"
+        balance = "0 wei"
+        code = "0x00"
+
+        [generate]
+        count = 32
+    "#;
+
+    #[test]
+    fn test_generate_is_reproducible() {
+        let builder: SpectreBuilder = toml::from_str(BUILDER).unwrap();
+        let expanded = builder.clone().expand().unwrap();
+        assert!(expanded.generate.is_none());
+        assert_eq!(expanded.transactions.len(), 32);
+
+        let printed = toml::to_string(&expanded).unwrap();
+        assert_eq!(
+            printed,
+            toml::to_string(&builder.expand().unwrap()).unwrap()
+        );
+        let reparsed: SpectreBuilder = toml::from_str(&printed).unwrap();
+        assert_eq!(
+            toml::to_string(&reparsed.expand().unwrap()).unwrap(),
+            printed
+        );
+    }
+
+    #[test]
+    fn test_generate_stays_funded() {
+        let builder: SpectreBuilder = toml::from_str(BUILDER).unwrap();
+        let expanded = builder.expand().unwrap();
+        let generate: GenerateBuilder = toml::from_str("count = 0").unwrap();
+        for sender in ["alice", "bob"] {
+            let spent = expanded
+                .transactions
+                .iter()
+                .filter(|tx| tx.from == sender)
+                .map(|tx| generate.worst_case_cost(tx, &expanded))
+                .fold(U256::ZERO, |acc, cost| acc + cost);
+            assert!(spent <= U256::from(10).pow(U256::from(18)));
+        }
+        expanded.build().unwrap();
+    }
+
+    #[test]
+    fn test_random_runtime() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..4096 {
+            let runtime = random_runtime(&mut rng, 2);
+            assert_ne!(runtime.first(), Some(&0xef));
+        }
+    }
+}
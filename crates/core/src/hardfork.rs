@@ -0,0 +1,259 @@
+use alloy_genesis::ChainConfig;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseHardforkError {
+    #[error("unknown hardfork {0:?}")]
+    Unknown(String),
+    #[error("invalid fork target {0:?}, expected \"before <fork>\" or \"after <fork>\"")]
+    InvalidTarget(String),
+}
+
+/// When a fork activates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ForkCondition {
+    Block(u64),
+    Timestamp(u64),
+}
+
+/// Places a transaction in the last block before, or the first block after, a fork.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ForkTarget {
+    Before(Hardfork),
+    After(Hardfork),
+}
+
+/// Named hardfork presets for `[chain] hardfork = "..."`.
+///
+/// A preset enables every fork up to and including the named one at genesis,
+/// and disables the later ones.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Hardfork {
+    #[cfg(not(feature = "scroll"))]
+    London,
+    #[cfg(not(feature = "scroll"))]
+    Shanghai,
+    #[cfg(not(feature = "scroll"))]
+    Cancun,
+    #[cfg(not(feature = "scroll"))]
+    Prague,
+    #[cfg(not(feature = "scroll"))]
+    Osaka,
+
+    #[cfg(feature = "scroll")]
+    Curie,
+    #[cfg(feature = "scroll")]
+    Darwin,
+    #[cfg(feature = "scroll")]
+    DarwinV2,
+    #[cfg(feature = "scroll")]
+    Euclid,
+    #[cfg(feature = "scroll")]
+    EuclidV2,
+}
+
+impl Hardfork {
+    /// All presets, in activation order.
+    #[cfg(not(feature = "scroll"))]
+    pub const ALL: &'static [Hardfork] = &[
+        Hardfork::London,
+        Hardfork::Shanghai,
+        Hardfork::Cancun,
+        Hardfork::Prague,
+        Hardfork::Osaka,
+    ];
+    /// All presets, in activation order.
+    #[cfg(feature = "scroll")]
+    pub const ALL: &'static [Hardfork] = &[
+        Hardfork::Curie,
+        Hardfork::Darwin,
+        Hardfork::DarwinV2,
+        Hardfork::Euclid,
+        Hardfork::EuclidV2,
+    ];
+
+    /// The name used in builder files.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::London => "london",
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Shanghai => "shanghai",
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Cancun => "cancun",
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Prague => "prague",
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Osaka => "osaka",
+            #[cfg(feature = "scroll")]
+            Hardfork::Curie => "curie",
+            #[cfg(feature = "scroll")]
+            Hardfork::Darwin => "darwin",
+            #[cfg(feature = "scroll")]
+            Hardfork::DarwinV2 => "darwinv2",
+            #[cfg(feature = "scroll")]
+            Hardfork::Euclid => "euclid",
+            #[cfg(feature = "scroll")]
+            Hardfork::EuclidV2 => "euclidv2",
+        }
+    }
+
+    /// Returns when this fork activates in the chain config, `None` if it is disabled.
+    pub fn activation(&self, config: &ChainConfig) -> Option<ForkCondition> {
+        #[cfg(feature = "scroll")]
+        let extra = |key: &str| {
+            config
+                .extra_fields
+                .get_deserialized::<u64>(key)
+                .and_then(Result::ok)
+        };
+        match self {
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::London => config.london_block.map(ForkCondition::Block),
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Shanghai => config.shanghai_time.map(ForkCondition::Timestamp),
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Cancun => config.cancun_time.map(ForkCondition::Timestamp),
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Prague => config.prague_time.map(ForkCondition::Timestamp),
+            #[cfg(not(feature = "scroll"))]
+            Hardfork::Osaka => config.osaka_time.map(ForkCondition::Timestamp),
+            #[cfg(feature = "scroll")]
+            Hardfork::Curie => extra("curieBlock").map(ForkCondition::Block),
+            #[cfg(feature = "scroll")]
+            Hardfork::Darwin => extra("darwinTime").map(ForkCondition::Timestamp),
+            #[cfg(feature = "scroll")]
+            Hardfork::DarwinV2 => extra("darwinv2Time").map(ForkCondition::Timestamp),
+            #[cfg(feature = "scroll")]
+            Hardfork::Euclid => extra("euclidTime").map(ForkCondition::Timestamp),
+            #[cfg(feature = "scroll")]
+            Hardfork::EuclidV2 => extra("euclidv2Time").map(ForkCondition::Timestamp),
+        }
+    }
+
+    /// Returns the latest fork whose rules apply to the given block.
+    pub fn active_at(config: &ChainConfig, number: u64, timestamp: u64) -> Option<Hardfork> {
+        Hardfork::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|fork| match fork.activation(config) {
+                Some(ForkCondition::Block(block)) => number >= block,
+                Some(ForkCondition::Timestamp(time)) => timestamp >= time,
+                None => false,
+            })
+    }
+}
+
+impl Display for ForkCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForkCondition::Block(number) => write!(f, "block #{number}"),
+            ForkCondition::Timestamp(timestamp) => write!(f, "timestamp {timestamp}"),
+        }
+    }
+}
+
+impl ForkTarget {
+    pub const fn hardfork(&self) -> Hardfork {
+        match self {
+            ForkTarget::Before(fork) | ForkTarget::After(fork) => *fork,
+        }
+    }
+}
+
+/// The latest preset, which matches the builder defaults.
+impl Default for Hardfork {
+    fn default() -> Self {
+        *Hardfork::ALL.last().unwrap()
+    }
+}
+
+impl Display for Hardfork {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Hardfork {
+    type Err = ParseHardforkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hardfork::ALL
+            .iter()
+            .find(|fork| fork.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| ParseHardforkError::Unknown(s.to_string()))
+    }
+}
+
+impl Display for ForkTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForkTarget::Before(fork) => write!(f, "before {fork}"),
+            ForkTarget::After(fork) => write!(f, "after {fork}"),
+        }
+    }
+}
+
+impl FromStr for ForkTarget {
+    type Err = ParseHardforkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(char::is_whitespace) {
+            Some(("before", fork)) => fork.trim().parse().map(ForkTarget::Before),
+            Some(("after", fork)) => fork.trim().parse().map(ForkTarget::After),
+            _ => Err(ParseHardforkError::InvalidTarget(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for ForkTarget {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ForkTarget {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fork_target() {
+        let fork = *Hardfork::ALL.last().unwrap();
+        assert_eq!(
+            format!("before {fork}").parse::<ForkTarget>().unwrap(),
+            ForkTarget::Before(fork)
+        );
+        assert_eq!(
+            format!("after  {fork}").parse::<ForkTarget>().unwrap(),
+            ForkTarget::After(fork)
+        );
+        assert!(matches!(
+            "after frontier".parse::<ForkTarget>(),
+            Err(ParseHardforkError::Unknown(_))
+        ));
+        assert!(matches!(
+            fork.name().parse::<ForkTarget>(),
+            Err(ParseHardforkError::InvalidTarget(_))
+        ));
+    }
+}
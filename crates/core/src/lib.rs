@@ -1,13 +1,32 @@
 #[macro_use]
 extern crate tracing;
 
+pub mod artifact;
+pub mod asm;
+pub mod backend;
 pub mod builder;
 mod core;
+mod generate;
+mod hardfork;
+pub mod mainnet;
+pub mod opcode;
+mod scenario;
+mod shrink;
+pub mod stack;
 mod utils;
+pub mod validate;
+pub mod verify;
 
 pub use builder::{BuilderError, SpectreBuilder};
-pub use core::Spectre;
+pub use core::{
+    RejectReason, Spectre, SpectreError, TraceReport, TracedBlock, TracedTransaction, TxLabel,
+    TxOrder,
+};
+pub use generate::{GenerateBuilder, GenerateWeights};
+pub use hardfork::{ForkTarget, Hardfork};
 pub use sbv_primitives::types::BlockWitness;
+pub use scenario::{Scenario, UseBuilder};
+pub use shrink::shrink;
 
 #[cfg(test)]
 #[ctor::ctor]
@@ -0,0 +1,229 @@
+//! Mainnet mode: witnesses of blocks already on a chain, dumped from an RPC endpoint.
+
+use crate::{
+    backend::BlockInfo,
+    core::{SpectreError, TracedBlock},
+};
+use alloy_consensus::BlockHeader;
+use alloy_json_rpc::RpcError;
+use alloy_network::{BlockResponse, HeaderResponse};
+use alloy_provider::{Provider, ProviderBuilder, RootProvider};
+use sbv_primitives::types::Network;
+use sbv_utils::rpc::ProviderExt;
+use std::{future::Future, ops::RangeInclusive, time::Duration};
+use tokio::task::JoinSet;
+
+/// How blocks are requested from the endpoint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DumpOptions {
+    /// Blocks requested at the same time.
+    pub concurrency: usize,
+    /// Retries of a request failing in transport before giving up.
+    pub retries: u32,
+    /// Wait before the first retry, doubled after each one.
+    pub backoff: Duration,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        DumpOptions {
+            concurrency: 4,
+            retries: 5,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Connects to an endpoint given as an `http(s)://` URL or an IPC socket path.
+pub async fn connect(url: &str) -> Result<RootProvider<Network>, SpectreError> {
+    Ok(ProviderBuilder::<_, _, Network>::default()
+        .on_builtin(url)
+        .await?)
+}
+
+/// Parses a block number, or an inclusive range written `start-end`.
+pub fn parse_block_range(s: &str) -> Result<RangeInclusive<u64>, SpectreError> {
+    let invalid = || SpectreError::InvalidBlockRange(s.to_string());
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let start = start.trim().parse().map_err(|_| invalid())?;
+    let end = end.trim().parse().map_err(|_| invalid())?;
+    if start > end {
+        return Err(invalid());
+    }
+    Ok(start..=end)
+}
+
+/// Dumps the witness of every block in `blocks`, in order.
+///
+/// Hardforks are left unlabelled, the chain config is not known from the endpoint.
+pub async fn dump_blocks<P>(
+    provider: P,
+    blocks: RangeInclusive<u64>,
+    options: DumpOptions,
+) -> Result<Vec<TracedBlock>, SpectreError>
+where
+    P: Provider<Network> + Clone + 'static,
+{
+    let mut tasks = JoinSet::new();
+    let mut traced = vec![];
+    for number in blocks {
+        if tasks.len() >= options.concurrency.max(1) {
+            traced.push(join_next(&mut tasks).await?);
+        }
+        let provider = provider.clone();
+        tasks.spawn(async move { dump_block(&provider, number, options).await });
+    }
+    while !tasks.is_empty() {
+        traced.push(join_next(&mut tasks).await?);
+    }
+    traced.sort_by_key(|block| block.number);
+    Ok(traced)
+}
+
+async fn join_next(
+    tasks: &mut JoinSet<Result<TracedBlock, SpectreError>>,
+) -> Result<TracedBlock, SpectreError> {
+    match tasks.join_next().await.expect("no block requested") {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+async fn dump_block<P: Provider<Network>>(
+    provider: &P,
+    number: u64,
+    options: DumpOptions,
+) -> Result<TracedBlock, SpectreError> {
+    let block = retry(options, number, move || async move {
+        Ok(provider.get_block_by_number(number.into()).await?)
+    })
+    .await?
+    .ok_or(SpectreError::MissingBlock(number))?;
+    let witness = retry(options, number, move || async move {
+        Ok(provider.dump_block_witness(number.into()).await?)
+    })
+    .await?
+    .ok_or(SpectreError::MissingWitness(number))?;
+    debug!(number, "witness dumped");
+
+    let info = BlockInfo {
+        number,
+        hash: block.header().hash(),
+        state_root: block.header().state_root(),
+        receipts_root: block.header().receipts_root(),
+        timestamp: block.header().timestamp(),
+        gas_limit: block.header().gas_limit(),
+        gas_used: block.header().gas_used(),
+        tx_count: block.transactions().len(),
+        base_fee: block.header().base_fee_per_gas(),
+    };
+    Ok(TracedBlock::new(info, None, witness))
+}
+
+/// Runs `request` until it succeeds or fails other than in transport, backing off between
+/// attempts.
+async fn retry<T, F, Fut>(
+    options: DumpOptions,
+    number: u64,
+    mut request: F,
+) -> Result<T, SpectreError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, SpectreError>>,
+{
+    let mut backoff = options.backoff;
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(SpectreError::Rpc(RpcError::Transport(e))) if attempt < options.retries => {
+                attempt += 1;
+                warn!(number, attempt, ?backoff, "request failed, retrying: {e}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpectreBuilder, backend::GethBackend};
+    use alloy_transport::TransportErrorKind;
+
+    #[test]
+    fn test_parse_block_range() {
+        assert_eq!(parse_block_range("7").unwrap(), 7..=7);
+        assert_eq!(parse_block_range("7-9").unwrap(), 7..=9);
+        assert!(parse_block_range("9-7").is_err());
+        assert!(parse_block_range("latest").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let options = DumpOptions {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let flaky = |fail_until: u32| {
+            let mut calls = 0;
+            move || {
+                calls += 1;
+                let attempt = calls;
+                async move {
+                    if attempt < fail_until {
+                        Err(SpectreError::Rpc(TransportErrorKind::custom_str("flaky")))
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            }
+        };
+        assert_eq!(retry(options, 0, flaky(3)).await.unwrap(), 3);
+        assert!(retry(options, 0, flaky(4)).await.is_err());
+
+        let mut calls = 0;
+        let result: Result<(), _> = retry(options, 0, || {
+            calls += 1;
+            async { Err(SpectreError::MissingWitness(0)) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs geth"]
+    async fn test_dump_blocks() {
+        let config: SpectreBuilder =
+            toml::from_str(include_str!("../../../examples/full.toml")).unwrap();
+        let mut backend = GethBackend::new(config.system.geth_path.clone());
+        let traced = config
+            .build()
+            .unwrap()
+            .trace_with(&mut backend)
+            .await
+            .unwrap()
+            .blocks;
+
+        let provider = backend.provider().unwrap().clone();
+        let blocks = traced[0].number..=traced.last().unwrap().number;
+        let options = DumpOptions {
+            concurrency: 2,
+            ..Default::default()
+        };
+        let dumped = dump_blocks(provider, blocks, options).await.unwrap();
+        for block in &traced {
+            let same = dumped.iter().find(|dumped| dumped.number == block.number);
+            let same = same.expect("traced block not dumped");
+            assert_eq!(same.hash, block.hash);
+            assert_eq!(same.tx_count, block.tx_count);
+            assert_eq!(
+                serde_json::to_value(&same.witness).unwrap(),
+                serde_json::to_value(&block.witness).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::fmt::{Display, Formatter};
+
+/// The EVM version an opcode was introduced in, named after solc's `evmVersion`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EvmVersion {
+    Frontier,
+    Homestead,
+    Byzantium,
+    Constantinople,
+    Istanbul,
+    London,
+    Shanghai,
+    Cancun,
+    /// Adds no opcodes, only the BLS12-381 precompiles.
+    Prague,
+    Osaka,
+}
+
+impl EvmVersion {
+    /// The lowercase name, as used by solc.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            EvmVersion::Frontier => "frontier",
+            EvmVersion::Homestead => "homestead",
+            EvmVersion::Byzantium => "byzantium",
+            EvmVersion::Constantinople => "constantinople",
+            EvmVersion::Istanbul => "istanbul",
+            EvmVersion::London => "london",
+            EvmVersion::Shanghai => "shanghai",
+            EvmVersion::Cancun => "cancun",
+            EvmVersion::Prague => "prague",
+            EvmVersion::Osaka => "osaka",
+        }
+    }
+}
+
+impl Display for EvmVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An entry of the opcode table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OpCode {
+    pub mnemonic: &'static str,
+    pub byte: u8,
+    /// Number of immediate bytes following the opcode, only `PUSHn` has any.
+    pub immediate: u8,
+    /// Number of stack items popped.
+    pub inputs: u8,
+    /// Number of stack items pushed.
+    pub outputs: u8,
+    pub since: EvmVersion,
+}
+
+macro_rules! opcodes {
+    ($($byte:literal => $mnemonic:ident($immediate:literal, $inputs:literal, $outputs:literal, $since:ident),)*) => {
+        /// Every defined opcode, ordered by byte.
+        pub const OPCODES: &[OpCode] = &[$(
+            OpCode {
+                mnemonic: stringify!($mnemonic),
+                byte: $byte,
+                immediate: $immediate,
+                inputs: $inputs,
+                outputs: $outputs,
+                since: EvmVersion::$since,
+            },
+        )*];
+    };
+}
+
+// byte => MNEMONIC(immediate bytes, stack inputs, stack outputs, introduced in)
+opcodes! {
+    0x00 => STOP(0, 0, 0, Frontier),
+    0x01 => ADD(0, 2, 1, Frontier),
+    0x02 => MUL(0, 2, 1, Frontier),
+    0x03 => SUB(0, 2, 1, Frontier),
+    0x04 => DIV(0, 2, 1, Frontier),
+    0x05 => SDIV(0, 2, 1, Frontier),
+    0x06 => MOD(0, 2, 1, Frontier),
+    0x07 => SMOD(0, 2, 1, Frontier),
+    0x08 => ADDMOD(0, 3, 1, Frontier),
+    0x09 => MULMOD(0, 3, 1, Frontier),
+    0x0a => EXP(0, 2, 1, Frontier),
+    0x0b => SIGNEXTEND(0, 2, 1, Frontier),
+    0x10 => LT(0, 2, 1, Frontier),
+    0x11 => GT(0, 2, 1, Frontier),
+    0x12 => SLT(0, 2, 1, Frontier),
+    0x13 => SGT(0, 2, 1, Frontier),
+    0x14 => EQ(0, 2, 1, Frontier),
+    0x15 => ISZERO(0, 1, 1, Frontier),
+    0x16 => AND(0, 2, 1, Frontier),
+    0x17 => OR(0, 2, 1, Frontier),
+    0x18 => XOR(0, 2, 1, Frontier),
+    0x19 => NOT(0, 1, 1, Frontier),
+    0x1a => BYTE(0, 2, 1, Frontier),
+    0x1b => SHL(0, 2, 1, Constantinople),
+    0x1c => SHR(0, 2, 1, Constantinople),
+    0x1d => SAR(0, 2, 1, Constantinople),
+    0x1e => CLZ(0, 1, 1, Osaka),
+    0x20 => KECCAK256(0, 2, 1, Frontier),
+    0x30 => ADDRESS(0, 0, 1, Frontier),
+    0x31 => BALANCE(0, 1, 1, Frontier),
+    0x32 => ORIGIN(0, 0, 1, Frontier),
+    0x33 => CALLER(0, 0, 1, Frontier),
+    0x34 => CALLVALUE(0, 0, 1, Frontier),
+    0x35 => CALLDATALOAD(0, 1, 1, Frontier),
+    0x36 => CALLDATASIZE(0, 0, 1, Frontier),
+    0x37 => CALLDATACOPY(0, 3, 0, Frontier),
+    0x38 => CODESIZE(0, 0, 1, Frontier),
+    0x39 => CODECOPY(0, 3, 0, Frontier),
+    0x3a => GASPRICE(0, 0, 1, Frontier),
+    0x3b => EXTCODESIZE(0, 1, 1, Frontier),
+    0x3c => EXTCODECOPY(0, 4, 0, Frontier),
+    0x3d => RETURNDATASIZE(0, 0, 1, Byzantium),
+    0x3e => RETURNDATACOPY(0, 3, 0, Byzantium),
+    0x3f => EXTCODEHASH(0, 1, 1, Constantinople),
+    0x40 => BLOCKHASH(0, 1, 1, Frontier),
+    0x41 => COINBASE(0, 0, 1, Frontier),
+    0x42 => TIMESTAMP(0, 0, 1, Frontier),
+    0x43 => NUMBER(0, 0, 1, Frontier),
+    0x44 => PREVRANDAO(0, 0, 1, Frontier),
+    0x45 => GASLIMIT(0, 0, 1, Frontier),
+    0x46 => CHAINID(0, 0, 1, Istanbul),
+    0x47 => SELFBALANCE(0, 0, 1, Istanbul),
+    0x48 => BASEFEE(0, 0, 1, London),
+    0x49 => BLOBHASH(0, 1, 1, Cancun),
+    0x4a => BLOBBASEFEE(0, 0, 1, Cancun),
+    0x50 => POP(0, 1, 0, Frontier),
+    0x51 => MLOAD(0, 1, 1, Frontier),
+    0x52 => MSTORE(0, 2, 0, Frontier),
+    0x53 => MSTORE8(0, 2, 0, Frontier),
+    0x54 => SLOAD(0, 1, 1, Frontier),
+    0x55 => SSTORE(0, 2, 0, Frontier),
+    0x56 => JUMP(0, 1, 0, Frontier),
+    0x57 => JUMPI(0, 2, 0, Frontier),
+    0x58 => PC(0, 0, 1, Frontier),
+    0x59 => MSIZE(0, 0, 1, Frontier),
+    0x5a => GAS(0, 0, 1, Frontier),
+    0x5b => JUMPDEST(0, 0, 0, Frontier),
+    0x5c => TLOAD(0, 1, 1, Cancun),
+    0x5d => TSTORE(0, 2, 0, Cancun),
+    0x5e => MCOPY(0, 3, 0, Cancun),
+    0x5f => PUSH0(0, 0, 1, Shanghai),
+    0x60 => PUSH1(1, 0, 1, Frontier),
+    0x61 => PUSH2(2, 0, 1, Frontier),
+    0x62 => PUSH3(3, 0, 1, Frontier),
+    0x63 => PUSH4(4, 0, 1, Frontier),
+    0x64 => PUSH5(5, 0, 1, Frontier),
+    0x65 => PUSH6(6, 0, 1, Frontier),
+    0x66 => PUSH7(7, 0, 1, Frontier),
+    0x67 => PUSH8(8, 0, 1, Frontier),
+    0x68 => PUSH9(9, 0, 1, Frontier),
+    0x69 => PUSH10(10, 0, 1, Frontier),
+    0x6a => PUSH11(11, 0, 1, Frontier),
+    0x6b => PUSH12(12, 0, 1, Frontier),
+    0x6c => PUSH13(13, 0, 1, Frontier),
+    0x6d => PUSH14(14, 0, 1, Frontier),
+    0x6e => PUSH15(15, 0, 1, Frontier),
+    0x6f => PUSH16(16, 0, 1, Frontier),
+    0x70 => PUSH17(17, 0, 1, Frontier),
+    0x71 => PUSH18(18, 0, 1, Frontier),
+    0x72 => PUSH19(19, 0, 1, Frontier),
+    0x73 => PUSH20(20, 0, 1, Frontier),
+    0x74 => PUSH21(21, 0, 1, Frontier),
+    0x75 => PUSH22(22, 0, 1, Frontier),
+    0x76 => PUSH23(23, 0, 1, Frontier),
+    0x77 => PUSH24(24, 0, 1, Frontier),
+    0x78 => PUSH25(25, 0, 1, Frontier),
+    0x79 => PUSH26(26, 0, 1, Frontier),
+    0x7a => PUSH27(27, 0, 1, Frontier),
+    0x7b => PUSH28(28, 0, 1, Frontier),
+    0x7c => PUSH29(29, 0, 1, Frontier),
+    0x7d => PUSH30(30, 0, 1, Frontier),
+    0x7e => PUSH31(31, 0, 1, Frontier),
+    0x7f => PUSH32(32, 0, 1, Frontier),
+    0x80 => DUP1(0, 1, 2, Frontier),
+    0x81 => DUP2(0, 2, 3, Frontier),
+    0x82 => DUP3(0, 3, 4, Frontier),
+    0x83 => DUP4(0, 4, 5, Frontier),
+    0x84 => DUP5(0, 5, 6, Frontier),
+    0x85 => DUP6(0, 6, 7, Frontier),
+    0x86 => DUP7(0, 7, 8, Frontier),
+    0x87 => DUP8(0, 8, 9, Frontier),
+    0x88 => DUP9(0, 9, 10, Frontier),
+    0x89 => DUP10(0, 10, 11, Frontier),
+    0x8a => DUP11(0, 11, 12, Frontier),
+    0x8b => DUP12(0, 12, 13, Frontier),
+    0x8c => DUP13(0, 13, 14, Frontier),
+    0x8d => DUP14(0, 14, 15, Frontier),
+    0x8e => DUP15(0, 15, 16, Frontier),
+    0x8f => DUP16(0, 16, 17, Frontier),
+    0x90 => SWAP1(0, 2, 2, Frontier),
+    0x91 => SWAP2(0, 3, 3, Frontier),
+    0x92 => SWAP3(0, 4, 4, Frontier),
+    0x93 => SWAP4(0, 5, 5, Frontier),
+    0x94 => SWAP5(0, 6, 6, Frontier),
+    0x95 => SWAP6(0, 7, 7, Frontier),
+    0x96 => SWAP7(0, 8, 8, Frontier),
+    0x97 => SWAP8(0, 9, 9, Frontier),
+    0x98 => SWAP9(0, 10, 10, Frontier),
+    0x99 => SWAP10(0, 11, 11, Frontier),
+    0x9a => SWAP11(0, 12, 12, Frontier),
+    0x9b => SWAP12(0, 13, 13, Frontier),
+    0x9c => SWAP13(0, 14, 14, Frontier),
+    0x9d => SWAP14(0, 15, 15, Frontier),
+    0x9e => SWAP15(0, 16, 16, Frontier),
+    0x9f => SWAP16(0, 17, 17, Frontier),
+    0xa0 => LOG0(0, 2, 0, Frontier),
+    0xa1 => LOG1(0, 3, 0, Frontier),
+    0xa2 => LOG2(0, 4, 0, Frontier),
+    0xa3 => LOG3(0, 5, 0, Frontier),
+    0xa4 => LOG4(0, 6, 0, Frontier),
+    0xf0 => CREATE(0, 3, 1, Frontier),
+    0xf1 => CALL(0, 7, 1, Frontier),
+    0xf2 => CALLCODE(0, 7, 1, Frontier),
+    0xf3 => RETURN(0, 2, 0, Frontier),
+    0xf4 => DELEGATECALL(0, 6, 1, Homestead),
+    0xf5 => CREATE2(0, 4, 1, Constantinople),
+    0xfa => STATICCALL(0, 6, 1, Byzantium),
+    0xfd => REVERT(0, 2, 0, Byzantium),
+    0xfe => INVALID(0, 0, 0, Frontier),
+    0xff => SELFDESTRUCT(0, 1, 0, Frontier),
+}
+
+/// Older names still accepted by the assembler.
+const ALIASES: &[(&str, u8)] = &[("SHA3", 0x20), ("DIFFICULTY", 0x44)];
+
+static BY_BYTE: [Option<OpCode>; 256] = {
+    let mut table = [None; 256];
+    let mut idx = 0;
+    while idx < OPCODES.len() {
+        table[OPCODES[idx].byte as usize] = Some(OPCODES[idx]);
+        idx += 1;
+    }
+    table
+};
+
+impl OpCode {
+    /// Looks up an opcode by byte, `None` for undefined bytes.
+    pub fn from_byte(byte: u8) -> Option<&'static OpCode> {
+        BY_BYTE[byte as usize].as_ref()
+    }
+
+    /// Looks up an opcode by mnemonic, case-insensitive.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<&'static OpCode> {
+        OPCODES
+            .iter()
+            .find(|opcode| opcode.mnemonic.eq_ignore_ascii_case(mnemonic))
+            .or_else(|| {
+                ALIASES
+                    .iter()
+                    .find(|(alias, _)| alias.eq_ignore_ascii_case(mnemonic))
+                    .and_then(|(_, byte)| OpCode::from_byte(*byte))
+            })
+    }
+}
+
+impl Display for OpCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.mnemonic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opcodes outside the `PUSHn`, `DUPn`, `SWAPn` and `LOGn` ranges, as listed in the
+    /// Yellow Paper appendix H and the EIPs introducing later ones.
+    const FIXED: &[(u8, &str)] = &[
+        (0x00, "STOP"),
+        (0x01, "ADD"),
+        (0x02, "MUL"),
+        (0x03, "SUB"),
+        (0x04, "DIV"),
+        (0x05, "SDIV"),
+        (0x06, "MOD"),
+        (0x07, "SMOD"),
+        (0x08, "ADDMOD"),
+        (0x09, "MULMOD"),
+        (0x0a, "EXP"),
+        (0x0b, "SIGNEXTEND"),
+        (0x10, "LT"),
+        (0x11, "GT"),
+        (0x12, "SLT"),
+        (0x13, "SGT"),
+        (0x14, "EQ"),
+        (0x15, "ISZERO"),
+        (0x16, "AND"),
+        (0x17, "OR"),
+        (0x18, "XOR"),
+        (0x19, "NOT"),
+        (0x1a, "BYTE"),
+        (0x1b, "SHL"),
+        (0x1c, "SHR"),
+        (0x1d, "SAR"),
+        (0x1e, "CLZ"),
+        (0x20, "KECCAK256"),
+        (0x30, "ADDRESS"),
+        (0x31, "BALANCE"),
+        (0x32, "ORIGIN"),
+        (0x33, "CALLER"),
+        (0x34, "CALLVALUE"),
+        (0x35, "CALLDATALOAD"),
+        (0x36, "CALLDATASIZE"),
+        (0x37, "CALLDATACOPY"),
+        (0x38, "CODESIZE"),
+        (0x39, "CODECOPY"),
+        (0x3a, "GASPRICE"),
+        (0x3b, "EXTCODESIZE"),
+        (0x3c, "EXTCODECOPY"),
+        (0x3d, "RETURNDATASIZE"),
+        (0x3e, "RETURNDATACOPY"),
+        (0x3f, "EXTCODEHASH"),
+        (0x40, "BLOCKHASH"),
+        (0x41, "COINBASE"),
+        (0x42, "TIMESTAMP"),
+        (0x43, "NUMBER"),
+        (0x44, "PREVRANDAO"),
+        (0x45, "GASLIMIT"),
+        (0x46, "CHAINID"),
+        (0x47, "SELFBALANCE"),
+        (0x48, "BASEFEE"),
+        (0x49, "BLOBHASH"),
+        (0x4a, "BLOBBASEFEE"),
+        (0x50, "POP"),
+        (0x51, "MLOAD"),
+        (0x52, "MSTORE"),
+        (0x53, "MSTORE8"),
+        (0x54, "SLOAD"),
+        (0x55, "SSTORE"),
+        (0x56, "JUMP"),
+        (0x57, "JUMPI"),
+        (0x58, "PC"),
+        (0x59, "MSIZE"),
+        (0x5a, "GAS"),
+        (0x5b, "JUMPDEST"),
+        (0x5c, "TLOAD"),
+        (0x5d, "TSTORE"),
+        (0x5e, "MCOPY"),
+        (0x5f, "PUSH0"),
+        (0xf0, "CREATE"),
+        (0xf1, "CALL"),
+        (0xf2, "CALLCODE"),
+        (0xf3, "RETURN"),
+        (0xf4, "DELEGATECALL"),
+        (0xf5, "CREATE2"),
+        (0xfa, "STATICCALL"),
+        (0xfd, "REVERT"),
+        (0xfe, "INVALID"),
+        (0xff, "SELFDESTRUCT"),
+    ];
+
+    fn expected(byte: u8) -> Option<String> {
+        match byte {
+            0x60..=0x7f => Some(format!("PUSH{}", byte - 0x5f)),
+            0x80..=0x8f => Some(format!("DUP{}", byte - 0x7f)),
+            0x90..=0x9f => Some(format!("SWAP{}", byte - 0x8f)),
+            0xa0..=0xa4 => Some(format!("LOG{}", byte - 0xa0)),
+            _ => FIXED
+                .iter()
+                .find(|(fixed, _)| *fixed == byte)
+                .map(|(_, mnemonic)| mnemonic.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_opcode_table() {
+        assert!(OPCODES.windows(2).all(|pair| pair[0].byte < pair[1].byte));
+        for byte in 0..=u8::MAX {
+            let opcode = OpCode::from_byte(byte);
+            assert_eq!(
+                opcode.map(|opcode| opcode.mnemonic.to_string()),
+                expected(byte),
+                "byte {byte:#04x}"
+            );
+            if let Some(opcode) = opcode {
+                assert_eq!(OpCode::from_mnemonic(opcode.mnemonic), Some(opcode));
+                assert_eq!(
+                    OpCode::from_mnemonic(&opcode.mnemonic.to_ascii_lowercase()),
+                    Some(opcode)
+                );
+                let immediate = if (0x60..=0x7f).contains(&byte) {
+                    byte - 0x5f
+                } else {
+                    0
+                };
+                assert_eq!(opcode.immediate, immediate, "{opcode}");
+            }
+        }
+        assert_eq!(OpCode::from_mnemonic("SHA3").unwrap().byte, 0x20);
+        assert_eq!(OpCode::from_mnemonic("DIFFICULTY").unwrap().byte, 0x44);
+        assert_eq!(OpCode::from_mnemonic("PUSH33"), None);
+        assert_eq!(OpCode::from_mnemonic("PUSHX"), None);
+    }
+
+    #[test]
+    fn test_opcode_stack() {
+        let stack = |mnemonic| {
+            let opcode = OpCode::from_mnemonic(mnemonic).unwrap();
+            (opcode.inputs, opcode.outputs)
+        };
+        assert_eq!(stack("DUP16"), (16, 17));
+        assert_eq!(stack("SWAP16"), (17, 17));
+        assert_eq!(stack("LOG4"), (6, 0));
+        assert_eq!(stack("CALL"), (7, 1));
+        assert_eq!(stack("DELEGATECALL"), (6, 1));
+        assert_eq!(stack("MCOPY"), (3, 0));
+        assert_eq!(
+            OpCode::from_mnemonic("BLOBBASEFEE").unwrap().since,
+            EvmVersion::Cancun
+        );
+    }
+}
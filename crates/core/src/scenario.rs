@@ -0,0 +1,417 @@
+use crate::{
+    builder::{AllocBuilder, BuilderError, TransactionBuilder, resolve_address},
+    utils::{Ether, deploy_code},
+};
+use alloy_primitives::{Address, B256, Bytes, U256, address, keccak256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+/// ERC-20 runtime, balances at `keccak(holder . 0)`, allowances at
+/// `keccak(spender . keccak(owner . 1))` and the total supply at slot 2, same as solc lays out
+/// `mapping` members declared in that order.
+const ERC20: &str = include_str!("bytecode/erc20.hex");
+/// WETH9 interface on top of the ERC-20 layout, the total supply is the contract balance.
+const WETH: &str = include_str!("bytecode/weth.hex");
+/// Arachnid's deterministic deployment proxy, called with `salt . init_code`.
+const CREATE2_FACTORY: &str = "0x7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe03601600081602082378035828234f58015156039578182fd5b8082525050506014600cf3";
+const CREATE2_FACTORY_ADDRESS: Address = address!("4e59b44847b379578588920ca78fbf26c0b4956c");
+/// Increments slot 0 and returns the new value.
+const COUNTER: &str = "0x6000546001018060005560005260206000f3";
+
+const TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+const TRANSFER_FROM: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+const APPROVE: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+const DEPOSIT: [u8; 4] = [0xd0, 0xe3, 0x0d, 0xb0];
+const WITHDRAW: [u8; 4] = [0x2e, 0x1a, 0x7d, 0x4d];
+
+const CALL_GAS_LIMIT: u64 = 100_000;
+const CREATE_GAS_LIMIT: u64 = 200_000;
+
+/// Built-in scenarios for `[[use]] scenario = "..."`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scenario {
+    /// A token with `amount` minted to every holder, who transfer and approve each other.
+    Erc20,
+    /// Every holder deposits `amount`, transfers half of it on and withdraws a quarter.
+    Weth,
+    /// The CREATE2 factory at its canonical address, every holder deploys a counter through it.
+    Create2Factory,
+    /// An EIP-1167 minimal proxy in front of a counter, every holder calls it once.
+    Proxy,
+}
+
+/// A `[[use]]` entry, pulls a built-in scenario into the builder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UseBuilder {
+    pub scenario: Scenario,
+    /// Name transactions can refer to the deployed contract by.
+    pub name: String,
+    /// Wallets interacting with the contract, they need to be funded for gas.
+    #[serde(default)]
+    pub holders: Vec<String>,
+    /// Token balance per holder, or deposit for WETH.
+    #[serde(default)]
+    pub amount: Option<Ether>,
+}
+
+impl Scenario {
+    pub const ALL: &'static [Scenario] = &[
+        Scenario::Erc20,
+        Scenario::Weth,
+        Scenario::Create2Factory,
+        Scenario::Proxy,
+    ];
+
+    /// The name used in builder files.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Scenario::Erc20 => "erc20",
+            Scenario::Weth => "weth",
+            Scenario::Create2Factory => "create2-factory",
+            Scenario::Proxy => "proxy",
+        }
+    }
+
+    /// A builder file using this scenario, for `spectre new --template`.
+    pub const fn template(&self) -> &'static str {
+        match self {
+            Scenario::Erc20 => include_str!("../../../examples/scenarios/erc20.toml"),
+            Scenario::Weth => include_str!("../../../examples/scenarios/weth.toml"),
+            Scenario::Create2Factory => {
+                include_str!("../../../examples/scenarios/create2-factory.toml")
+            }
+            Scenario::Proxy => include_str!("../../../examples/scenarios/proxy.toml"),
+        }
+    }
+
+    const fn needs_holders(&self) -> bool {
+        matches!(self, Scenario::Erc20 | Scenario::Weth)
+    }
+}
+
+impl Display for Scenario {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scenario::ALL
+            .iter()
+            .find(|scenario| scenario.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown scenario {s:?}"))
+    }
+}
+
+impl UseBuilder {
+    /// Expands into named allocs and the transactions exercising them.
+    pub(crate) fn expand(
+        self,
+        idx: usize,
+        names: &HashMap<String, Address>,
+    ) -> Result<(Vec<AllocBuilder>, Vec<TransactionBuilder>), BuilderError> {
+        if self.scenario.needs_holders() && self.holders.is_empty() {
+            return Err(BuilderError::UseNoHolders {
+                idx,
+                scenario: self.scenario,
+            });
+        }
+        let holders = self
+            .holders
+            .iter()
+            .map(|name| {
+                resolve_address(name, names)
+                    .map(|address| (name.clone(), address))
+                    .ok_or_else(|| BuilderError::UseHolderNotFound {
+                        idx,
+                        name: name.clone(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let next = |i: usize| holders[(i + 1) % holders.len()].1;
+        let call = |from: &str, input: Bytes| TransactionBuilder {
+            from: from.to_string(),
+            to: Some(self.name.clone()),
+            gas_limit: Some(CALL_GAS_LIMIT),
+            input: Some(input),
+            ..Default::default()
+        };
+
+        let mut transactions = vec![];
+        match self.scenario {
+            Scenario::Erc20 => {
+                let amount = self
+                    .amount
+                    .map(|amount| amount.0)
+                    .unwrap_or(U256::from(1000) * U256::from(10).pow(U256::from(18)));
+                let mut token = contract(&self.name, named_address(&self.name), ERC20);
+                token
+                    .storage
+                    .insert(U256::from(2), amount * U256::from(holders.len()));
+                for (_, address) in &holders {
+                    token.storage.insert(balance_slot(*address), amount);
+                }
+                for (i, (from, _)) in holders.iter().enumerate() {
+                    let input = calldata(TRANSFER, &[word(next(i)), amount / U256::from(4)]);
+                    transactions.push(call(from, input));
+                }
+                if let [(owner, owner_address), (spender, spender_address), ..] = &holders[..] {
+                    let input =
+                        calldata(APPROVE, &[word(*spender_address), amount / U256::from(2)]);
+                    transactions.push(call(owner, input));
+                    let input = calldata(
+                        TRANSFER_FROM,
+                        &[
+                            word(*owner_address),
+                            word(*spender_address),
+                            amount / U256::from(4),
+                        ],
+                    );
+                    transactions.push(call(spender, input));
+                }
+                Ok((vec![token], transactions))
+            }
+            Scenario::Weth => {
+                let amount = self
+                    .amount
+                    .map(|amount| amount.0)
+                    .unwrap_or(U256::from(10).pow(U256::from(18)));
+                let weth = contract(&self.name, named_address(&self.name), WETH);
+                for (i, (from, _)) in holders.iter().enumerate() {
+                    let mut deposit = call(from, calldata(DEPOSIT, &[]));
+                    deposit.value = Some(Ether(amount));
+                    transactions.push(deposit);
+                    let input = calldata(TRANSFER, &[word(next(i)), amount / U256::from(2)]);
+                    transactions.push(call(from, input));
+                    let input = calldata(WITHDRAW, &[amount / U256::from(4)]);
+                    transactions.push(call(from, input));
+                }
+                Ok((vec![weth], transactions))
+            }
+            Scenario::Create2Factory => {
+                let factory = contract(&self.name, CREATE2_FACTORY_ADDRESS, CREATE2_FACTORY);
+                let counter = Bytes::from_str(COUNTER).unwrap();
+                for (i, (from, _)) in holders.iter().enumerate() {
+                    let mut input = B256::from(U256::from(i).to_be_bytes()).to_vec();
+                    input.extend_from_slice(&deploy_code(&counter).unwrap());
+                    let mut deploy = call(from, input.into());
+                    deploy.gas_limit = Some(CREATE_GAS_LIMIT);
+                    transactions.push(deploy);
+                }
+                Ok((vec![factory], transactions))
+            }
+            Scenario::Proxy => {
+                let implementation_name = format!("{}-impl", self.name);
+                let implementation_address = named_address(&implementation_name);
+                let implementation =
+                    contract(&implementation_name, implementation_address, COUNTER);
+                let proxy = contract(
+                    &self.name,
+                    named_address(&self.name),
+                    &format!(
+                        "0x363d3d373d3d3d363d73{:x}5af43d82803e903d91602b57fd5bf3",
+                        implementation_address
+                    ),
+                );
+                for (from, _) in &holders {
+                    transactions.push(call(from, Bytes::new()));
+                }
+                Ok((vec![implementation, proxy], transactions))
+            }
+        }
+    }
+}
+
+/// Deterministic address of a named scenario contract.
+fn named_address(name: &str) -> Address {
+    Address::from_slice(&keccak256(format!("spectre:{name}"))[12..])
+}
+
+fn contract(name: &str, address: Address, code: &str) -> AllocBuilder {
+    AllocBuilder {
+        address: address.to_string(),
+        name: Some(name.to_string()),
+        nonce: None,
+        balance: Some(Ether::default()),
+        code: Some(code.trim().to_string()),
+        code_file: None,
+        storage: BTreeMap::new(),
+        storage_file: None,
+        artifact: None,
+        variables: BTreeMap::new(),
+    }
+}
+
+fn word(address: Address) -> U256 {
+    U256::from_be_slice(address.as_slice())
+}
+
+fn balance_slot(holder: Address) -> U256 {
+    let mut key = [0u8; 64];
+    key[12..32].copy_from_slice(holder.as_slice());
+    U256::from_be_bytes(keccak256(key).0)
+}
+
+fn calldata(selector: [u8; 4], args: &[U256]) -> Bytes {
+    let mut input = selector.to_vec();
+    for arg in args {
+        input.extend_from_slice(&arg.to_be_bytes::<32>());
+    }
+    input.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        SpectreBuilder, TraceReport,
+        asm::{compile_mnemonic, disassemble},
+        backend::RevmBackend,
+    };
+
+    #[test]
+    fn test_templates_build() {
+        for scenario in Scenario::ALL {
+            let builder: SpectreBuilder = toml::from_str(scenario.template()).unwrap();
+            assert_eq!(builder.scenarios[0].scenario, *scenario);
+            let expanded = builder.clone().expand().unwrap();
+            assert!(expanded.scenarios.is_empty());
+            assert!(
+                expanded
+                    .alloc
+                    .iter()
+                    .any(|alloc| alloc.name.as_deref() == Some(builder.scenarios[0].name.as_str()))
+            );
+            builder.build().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_bytecode_disassembles() {
+        for code in [ERC20, WETH, CREATE2_FACTORY, COUNTER] {
+            let code = Bytes::from_str(code.trim()).unwrap();
+            let source = disassemble(&code);
+            assert!(!source.contains("//"), "{source}");
+            assert_eq!(compile_mnemonic(&source).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_erc20_layout() {
+        let holder = address!("00000000000000000000000000000000000a11ce");
+        let names = HashMap::from([("alice".to_string(), holder)]);
+        let (alloc, transactions) = UseBuilder {
+            scenario: Scenario::Erc20,
+            name: "token".to_string(),
+            holders: vec!["alice".to_string()],
+            amount: Some(Ether(U256::from(100))),
+        }
+        .expand(0, &names)
+        .unwrap();
+
+        // keccak256(abi.encode(holder, 0)), as solc computes `balanceOf[holder]`
+        let slot = balance_slot(holder);
+        assert_eq!(
+            slot,
+            U256::from_be_bytes(
+                keccak256([B256::left_padding_from(holder.as_slice()).0, B256::ZERO.0,].concat()).0
+            )
+        );
+        assert_eq!(alloc[0].storage[&slot], U256::from(100));
+        assert_eq!(alloc[0].storage[&U256::from(2)], U256::from(100));
+        // a single holder transfers to itself and has nobody to approve
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0].input.as_ref().unwrap()[..4],
+            keccak256("transfer(address,uint256)")[..4]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_address() {
+        let mut builder: SpectreBuilder =
+            toml::from_str(Scenario::Create2Factory.template()).unwrap();
+        let mut other = builder.scenarios[0].clone();
+        other.name = "other-factory".to_string();
+        builder.scenarios.push(other);
+        assert!(matches!(
+            builder.build(),
+            Err(BuilderError::AllocAddressTaken {
+                address: CREATE2_FACTORY_ADDRESS,
+                ..
+            })
+        ));
+    }
+
+    /// Data of the logs emitted for `event`.
+    fn events(report: &TraceReport, event: &str) -> Vec<Bytes> {
+        let topic = keccak256(event);
+        report
+            .transactions
+            .iter()
+            .flat_map(|tx| &tx.outcome.logs)
+            .filter(|log| log.topics().first() == Some(&topic))
+            .map(|log| log.data.data.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_scenarios_trace() {
+        let trace = |scenario: Scenario| async move {
+            let builder: SpectreBuilder = toml::from_str(scenario.template()).unwrap();
+            let mut backend = RevmBackend::default();
+            let report = builder
+                .build()
+                .unwrap()
+                .trace_with(&mut backend)
+                .await
+                .unwrap();
+            for tx in &report.transactions {
+                assert!(
+                    tx.outcome.success,
+                    "{scenario}: transaction#{} failed",
+                    tx.idx
+                );
+            }
+            for verification in report.verify() {
+                assert!(verification.is_ok(), "{scenario}: {verification:?}");
+            }
+            (report, backend)
+        };
+        let amount = |ether: u64, divisor: u64| {
+            let amount = U256::from(ether) * U256::from(10).pow(U256::from(18));
+            Bytes::from((amount / U256::from(divisor)).to_be_bytes::<32>())
+        };
+
+        // two transfers and a transferFrom of a quarter each, one approval of half
+        let (report, _) = trace(Scenario::Erc20).await;
+        let transfer = "Transfer(address,address,uint256)";
+        assert_eq!(events(&report, transfer), vec![amount(1000, 4); 3]);
+        let approval = events(&report, "Approval(address,address,uint256)");
+        assert_eq!(approval, [amount(1000, 2)]);
+
+        let (report, _) = trace(Scenario::Weth).await;
+        let deposits = events(&report, "Deposit(address,uint256)");
+        assert_eq!(deposits, vec![amount(1, 1); 2]);
+        assert_eq!(events(&report, transfer), vec![amount(1, 2); 2]);
+        let withdrawals = events(&report, "Withdrawal(address,uint256)");
+        assert_eq!(withdrawals, vec![amount(1, 4); 2]);
+
+        // the proxy delegates to the counter, which counts in the proxy storage
+        let (report, backend) = trace(Scenario::Proxy).await;
+        let count = U256::from(report.transactions.len());
+        assert_eq!(backend.storage(named_address("proxy"), U256::ZERO), count);
+        let implementation = named_address("proxy-impl");
+        assert_eq!(backend.storage(implementation, U256::ZERO), U256::ZERO);
+    }
+}
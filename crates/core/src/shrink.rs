@@ -0,0 +1,133 @@
+use crate::SpectreBuilder;
+use std::future::Future;
+
+/// Delta-debugs a failing builder down to a smaller one that still fails.
+///
+/// The builder should be [expanded](SpectreBuilder::expand) first, so every candidate is
+/// deterministic. `fails` is called with each candidate and returns whether it still fails;
+/// candidates that don't build or trace should be reported as not failing.
+///
+/// Shrinking drops transactions, trims calldata from the end and prunes allocs, in that order.
+pub async fn shrink<F, Fut>(mut builder: SpectreBuilder, mut fails: F) -> SpectreBuilder
+where
+    F: FnMut(SpectreBuilder) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let transactions = std::mem::take(&mut builder.transactions);
+    let transactions = ddmin(transactions, 1, |transactions| {
+        let mut candidate = builder.clone();
+        candidate.transactions = transactions;
+        fails(candidate)
+    })
+    .await;
+    builder.transactions = transactions;
+    debug!(
+        transactions = builder.transactions.len(),
+        "shrunk transactions"
+    );
+
+    for idx in 0..builder.transactions.len() {
+        let mut len = builder.transactions[idx]
+            .input
+            .as_ref()
+            .map(|input| input.len())
+            .unwrap_or_default();
+        while len > 0 {
+            let mut candidate = builder.clone();
+            let input = candidate.transactions[idx].input.as_mut().unwrap();
+            *input = input.slice(..len / 2);
+            if !fails(candidate.clone()).await {
+                break;
+            }
+            builder = candidate;
+            len /= 2;
+        }
+    }
+    debug!("trimmed calldata");
+
+    let alloc = std::mem::take(&mut builder.alloc);
+    let alloc = ddmin(alloc, 0, |alloc| {
+        let mut candidate = builder.clone();
+        candidate.alloc = alloc;
+        fails(candidate)
+    })
+    .await;
+    builder.alloc = alloc;
+    debug!(alloc = builder.alloc.len(), "pruned allocs");
+
+    builder
+}
+
+/// Removes chunks of `items` as long as the rest still fails, keeping at least `min_len` items.
+async fn ddmin<T, F, Fut>(mut items: Vec<T>, min_len: usize, mut fails: F) -> Vec<T>
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut granularity = 2;
+    while items.len() > min_len {
+        let chunk = items.len().div_ceil(granularity);
+        let mut reduced = false;
+        for start in (0..items.len()).step_by(chunk) {
+            let end = (start + chunk).min(items.len());
+            let complement = items[..start]
+                .iter()
+                .chain(&items[end..])
+                .cloned()
+                .collect::<Vec<_>>();
+            if complement.len() < min_len {
+                continue;
+            }
+            if fails(complement.clone()).await {
+                items = complement;
+                granularity = (granularity - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if granularity >= items.len() {
+                break;
+            }
+            granularity = (granularity * 2).min(items.len());
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::TransactionBuilder;
+    use alloy_primitives::Bytes;
+
+    #[tokio::test]
+    async fn test_shrink() {
+        let mut builder = SpectreBuilder::default();
+        builder.transactions = (0..16)
+            .map(|idx| TransactionBuilder {
+                from: format!("wallet{idx}"),
+                input: Some(Bytes::from(vec![idx as u8; 64])),
+                ..Default::default()
+            })
+            .collect();
+
+        // fails as long as wallet3 and wallet11 send, and wallet11 sends a non-empty input
+        let shrunk = shrink(builder, |candidate| async move {
+            let sends = |name: &str| candidate.transactions.iter().find(|tx| tx.from == name);
+            sends("wallet3").is_some()
+                && sends("wallet11").is_some_and(|tx| !tx.input.as_ref().unwrap().is_empty())
+        })
+        .await;
+
+        let senders = shrunk
+            .transactions
+            .iter()
+            .map(|tx| tx.from.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(senders, ["wallet3", "wallet11"]);
+        assert_eq!(shrunk.transactions[0].input.as_ref().unwrap().len(), 0);
+        assert_eq!(shrunk.transactions[1].input.as_ref().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::asm::{Decoded, decode};
+use alloy_primitives::U256;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{Display, Formatter},
+    ops::Range,
+};
+
+/// Stack limit of the EVM.
+const MAX_STACK: usize = 1024;
+/// Bounds the analysis of code whose paths keep producing new stack shapes.
+const MAX_STATES: usize = 100_000;
+
+/// A problem found by [`analyze`], at the offset of the offending instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackIssue {
+    pub offset: usize,
+    pub kind: StackIssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StackIssueKind {
+    /// The instruction pops more items than the stack holds.
+    Underflow {
+        mnemonic: &'static str,
+        needed: usize,
+        height: usize,
+    },
+    /// The instruction pushes past 1024 items.
+    Overflow { mnemonic: &'static str },
+    /// A `JUMP` or `JUMPI` to an offset that is not a `JUMPDEST`.
+    InvalidJump { target: U256 },
+    /// No path reaches the instruction.
+    Unreachable,
+}
+
+/// An abstract stack: its height, and the values pushed as constants.
+type Stack = Vec<Option<U256>>;
+
+/// Runs every path of the code from offset 0 on an abstract stack, following jumps whose target
+/// is a pushed constant.
+///
+/// Bytes in `data` are never executed, so they are neither decoded as instructions nor reported
+/// unreachable, and neither is a label right before them. Unreachable code is only reported when
+/// every jump could be followed, apart from code right after a terminating instruction which can
+/// never run.
+pub fn analyze(code: &[u8], data: &[Range<usize>]) -> Vec<StackIssue> {
+    let in_data = |offset: usize| data.iter().any(|range| range.contains(&offset));
+    let ops = decode(code)
+        .into_iter()
+        .filter_map(|item| match item {
+            Decoded::Op {
+                offset,
+                opcode,
+                immediate,
+            } if !in_data(offset) && (opcode.mnemonic != "JUMPDEST" || !in_data(offset + 1)) => {
+                Some((offset, (opcode, immediate)))
+            }
+            _ => None,
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut issues = vec![];
+    let mut reported = HashSet::new();
+    let mut report = |offset: usize, kind: StackIssueKind| {
+        if reported.insert((offset, kind.clone())) {
+            issues.push(StackIssue { offset, kind });
+        }
+    };
+
+    let mut visited = HashSet::new();
+    let mut reached = HashSet::new();
+    let mut dynamic_jump = false;
+    let mut pending: Vec<(usize, Stack)> = vec![(0, vec![])];
+    while let Some((mut offset, mut stack)) = pending.pop() {
+        if visited.len() >= MAX_STATES {
+            // too many shapes to tell what is unreachable
+            dynamic_jump = true;
+            break;
+        }
+        if !visited.insert((offset, stack.clone())) {
+            continue;
+        }
+        // runs one path until it ends, queueing the other side of branches
+        while let Some((opcode, immediate)) = ops.get(&offset) {
+            reached.insert(offset);
+            let inputs = opcode.inputs as usize;
+            if stack.len() < inputs {
+                report(
+                    offset,
+                    StackIssueKind::Underflow {
+                        mnemonic: opcode.mnemonic,
+                        needed: inputs,
+                        height: stack.len(),
+                    },
+                );
+                // carries on as if the missing items were there, to find later issues too
+                stack.splice(0..0, (stack.len()..inputs).map(|_| None));
+            }
+
+            let next = offset + 1 + immediate.len();
+            match opcode.mnemonic {
+                "JUMP" | "JUMPI" => {
+                    let target = stack.pop().unwrap();
+                    if opcode.mnemonic == "JUMPI" {
+                        stack.pop();
+                        pending.push((next, stack.clone()));
+                    }
+                    match target {
+                        Some(target) => {
+                            let jumpdest = usize::try_from(target).ok().filter(|target| {
+                                ops.get(target)
+                                    .is_some_and(|(opcode, _)| opcode.mnemonic == "JUMPDEST")
+                            });
+                            match jumpdest {
+                                Some(target) => pending.push((target, stack)),
+                                None => report(offset, StackIssueKind::InvalidJump { target }),
+                            }
+                        }
+                        None => dynamic_jump = true,
+                    }
+                    break;
+                }
+                "STOP" | "RETURN" | "REVERT" | "INVALID" | "SELFDESTRUCT" => break,
+                mnemonic if opcode.immediate > 0 || mnemonic == "PUSH0" => {
+                    stack.push(Some(U256::from_be_slice(immediate)));
+                }
+                mnemonic if mnemonic.starts_with("DUP") => {
+                    stack.push(stack[stack.len() - inputs]);
+                }
+                mnemonic if mnemonic.starts_with("SWAP") => {
+                    let top = stack.len() - 1;
+                    stack.swap(top, top + 1 - inputs);
+                }
+                _ => {
+                    stack.truncate(stack.len() - inputs);
+                    stack.extend((0..opcode.outputs).map(|_| None));
+                }
+            }
+            if stack.len() > MAX_STACK {
+                report(
+                    offset,
+                    StackIssueKind::Overflow {
+                        mnemonic: opcode.mnemonic,
+                    },
+                );
+                break;
+            }
+            offset = next;
+        }
+    }
+
+    // code after a terminating instruction is unreachable whatever the jumps do
+    let mut previous_ends = false;
+    let mut unreachable = false;
+    for (offset, (opcode, _)) in &ops {
+        let candidate = !dynamic_jump || (previous_ends && opcode.mnemonic != "JUMPDEST");
+        if reached.contains(offset) || !candidate {
+            unreachable = false;
+        } else if !unreachable {
+            report(*offset, StackIssueKind::Unreachable);
+            unreachable = true;
+        }
+        previous_ends = matches!(
+            opcode.mnemonic,
+            "STOP" | "RETURN" | "REVERT" | "INVALID" | "SELFDESTRUCT" | "JUMP"
+        ) || (previous_ends && opcode.mnemonic != "JUMPDEST");
+    }
+    issues.sort_by_key(|issue| issue.offset);
+    issues
+}
+
+impl Display for StackIssueKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackIssueKind::Underflow {
+                mnemonic,
+                needed,
+                height,
+            } => write!(
+                f,
+                "stack underflow, {mnemonic} needs {needed} items with {height} on the stack"
+            ),
+            StackIssueKind::Overflow { mnemonic } => {
+                write!(
+                    f,
+                    "stack overflow, {mnemonic} pushes past {MAX_STACK} items"
+                )
+            }
+            StackIssueKind::InvalidJump { target } => {
+                write!(f, "jump to {target:#x}, which is not a JUMPDEST")
+            }
+            StackIssueKind::Unreachable => f.write_str("unreachable code"),
+        }
+    }
+}
+
+impl Display for StackIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at pc {:#06x}", self.kind, self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::compile_mnemonic;
+
+    fn kinds(source: &str) -> Vec<(usize, StackIssueKind)> {
+        let code = compile_mnemonic(source).unwrap();
+        analyze(&code, &[])
+            .into_iter()
+            .map(|issue| (issue.offset, issue.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_analyze() {
+        // a loop counting down, then a subroutine called twice
+        let source = r#"
+PUSH1 0x03
+@loop:
+PUSH1 0x01
+SWAP1
+SUB
+DUP1
+PUSH @loop
+JUMPI
+POP
+PUSH @first
+PUSH1 0x05
+PUSH @double
+JUMP
+@first:
+POP
+PUSH @second
+PUSH1 0x06
+PUSH @double
+JUMP
+@second:
+POP
+STOP
+@double:
+DUP1
+ADD
+SWAP1
+JUMP
+"#;
+        assert_eq!(kinds(source), []);
+
+        // a missing PUSH
+        assert_eq!(
+            kinds("PUSH1 0x01\nADD\nADD\nSTOP"),
+            [
+                (
+                    2,
+                    StackIssueKind::Underflow {
+                        mnemonic: "ADD",
+                        needed: 2,
+                        height: 1
+                    }
+                ),
+                (
+                    3,
+                    StackIssueKind::Underflow {
+                        mnemonic: "ADD",
+                        needed: 2,
+                        height: 1
+                    }
+                )
+            ]
+        );
+        assert_eq!(
+            kinds("@loop:\nPUSH0\nPUSH @loop\nJUMP"),
+            [(2, StackIssueKind::Overflow { mnemonic: "PUSH1" })]
+        );
+        assert_eq!(
+            kinds("PUSH1 0x04\nJUMP\nSTOP\nSTOP"),
+            [
+                (
+                    2,
+                    StackIssueKind::InvalidJump {
+                        target: U256::from(4)
+                    }
+                ),
+                (3, StackIssueKind::Unreachable)
+            ]
+        );
+        assert_eq!(
+            kinds("STOP\n@skipped:\nSTOP"),
+            [(1, StackIssueKind::Unreachable)]
+        );
+        // after a dynamic jump only code behind a terminator is known to be unreachable
+        assert_eq!(
+            kinds("CALLVALUE\nJUMP\nSTOP\n@maybe:\nSTOP"),
+            [(2, StackIssueKind::Unreachable)]
+        );
+
+        let code = compile_mnemonic("STOP\n@data:\nDATA 0x0102").unwrap();
+        assert_eq!(analyze(&code, &[Range { start: 2, end: 4 }]), []);
+    }
+}
@@ -1,14 +1,12 @@
 use alloy_primitives::{
     Bytes, U256,
     bytes::{BufMut, BytesMut},
-    ruint,
     utils::{ParseUnits, Unit, parse_units},
 };
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
+    collections::BTreeMap,
     fmt::{Debug, Formatter},
-    ops::Shr,
-    str::FromStr,
 };
 
 #[derive(Default, Copy, Clone, PartialEq, Eq)]
@@ -37,6 +35,15 @@ impl<'de> Deserialize<'de> for Ether {
     }
 }
 
+impl Serialize for Ether {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{} wei", self.0))
+    }
+}
+
 impl Debug for Ether {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         const UNITS: &[(Unit, &str, U256)] = &[
@@ -82,7 +89,7 @@ impl Debug for Ether {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum BoolOr<T> {
     Bool(bool),
@@ -105,211 +112,158 @@ impl<T: Default> BoolOr<T> {
     }
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum CompileError {
-    #[error("invalid opcode {opcode} at line {line}")]
-    InvalidOpcode { opcode: String, line: usize },
-    #[error("missing value for opcode {opcode} at line {line}")]
-    MissingValue { opcode: String, line: usize },
-    #[error("invalid push value at line {line}: {error}")]
-    InvalidPushValue {
-        line: usize,
-        error: ruint::ParseError,
-    },
-    #[error("invalid push value length at line {line}: expected {expected}, got {length}")]
-    InvalidPushValueLength {
-        line: usize,
-        expected: usize,
-        length: usize,
-    },
+/// A fork activation timestamp, either absolute or `"+seconds"` after the genesis timestamp.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ForkTime {
+    Absolute(u64),
+    Relative(u64),
 }
 
-pub fn compile_mnemonic(codes: &str) -> Result<Bytes, CompileError> {
-    let mut code = BytesMut::new();
-    for (idx, line) in codes.split('\n').enumerate() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with("//") {
-            continue;
+impl Default for ForkTime {
+    fn default() -> Self {
+        ForkTime::Absolute(0)
+    }
+}
+
+impl ForkTime {
+    pub fn resolve(self, genesis_timestamp: u64) -> u64 {
+        match self {
+            ForkTime::Absolute(timestamp) => timestamp,
+            ForkTime::Relative(offset) => genesis_timestamp.saturating_add(offset),
         }
-        let mut line = line.split_whitespace();
-        let opcode = line.next().unwrap().to_ascii_uppercase();
-        match opcode.as_str() {
-            "STOP" => code.put_u8(0x00),
-            "ADD" => code.put_u8(0x01),
-            "MUL" => code.put_u8(0x02),
-            "SUB" => code.put_u8(0x03),
-            "DIV" => code.put_u8(0x04),
-            "SDIV" => code.put_u8(0x05),
-            "MOD" => code.put_u8(0x06),
-            "SMOD" => code.put_u8(0x07),
-            "ADDMOD" => code.put_u8(0x08),
-            "MULMOD" => code.put_u8(0x09),
-            "EXP" => code.put_u8(0x0a),
-            "SIGNEXTEND" => code.put_u8(0x0b),
-            "LT" => code.put_u8(0x10),
-            "GT" => code.put_u8(0x11),
-            "SLT" => code.put_u8(0x12),
-            "SGT" => code.put_u8(0x13),
-            "EQ" => code.put_u8(0x14),
-            "ISZERO" => code.put_u8(0x15),
-            "AND" => code.put_u8(0x16),
-            "OR" => code.put_u8(0x17),
-            "XOR" => code.put_u8(0x18),
-            "NOT" => code.put_u8(0x19),
-            "BYTE" => code.put_u8(0x1a),
-            "SHL" => code.put_u8(0x1b),
-            "SHR" => code.put_u8(0x1c),
-            "SAR" => code.put_u8(0x1d),
-            "SHA3" => code.put_u8(0x20),
-            "ADDRESS" => code.put_u8(0x30),
-            "BALANCE" => code.put_u8(0x31),
-            "ORIGIN" => code.put_u8(0x32),
-            "CALLER" => code.put_u8(0x33),
-            "CALLVALUE" => code.put_u8(0x34),
-            "CALLDATALOAD" => code.put_u8(0x35),
-            "CALLDATASIZE" => code.put_u8(0x36),
-            "CALLDATACOPY" => code.put_u8(0x37),
-            "CODESIZE" => code.put_u8(0x38),
-            "CODECOPY" => code.put_u8(0x39),
-            "GASPRICE" => code.put_u8(0x3a),
-            "EXTCODESIZE" => code.put_u8(0x3b),
-            "EXTCODECOPY" => code.put_u8(0x3c),
-            "RETURNDATASIZE" => code.put_u8(0x3d),
-            "RETURNDATACOPY" => code.put_u8(0x3e),
-            "EXTCODEHASH" => code.put_u8(0x3f),
-            "BLOCKHASH" => code.put_u8(0x40),
-            "COINBASE" => code.put_u8(0x41),
-            "TIMESTAMP" => code.put_u8(0x42),
-            "NUMBER" => code.put_u8(0x43),
-            "DIFFICULTY" => code.put_u8(0x44),
-            "GASLIMIT" => code.put_u8(0x45),
-            "CHAINID" => code.put_u8(0x46),
-            "SELFBALANCE" => code.put_u8(0x47),
-            "POP" => code.put_u8(0x50),
-            "MLOAD" => code.put_u8(0x51),
-            "MSTORE" => code.put_u8(0x52),
-            "MSTORE8" => code.put_u8(0x53),
-            "SLOAD" => code.put_u8(0x54),
-            "SSTORE" => code.put_u8(0x55),
-            "JUMP" => code.put_u8(0x56),
-            "JUMPI" => code.put_u8(0x57),
-            "PC" => code.put_u8(0x58),
-            "MSIZE" => code.put_u8(0x59),
-            "GAS" => code.put_u8(0x5a),
-            "JUMPDEST" => code.put_u8(0x5b),
-            "TLOAD" => code.put_u8(0x5c),
-            "TSTORE" => code.put_u8(0x5d),
-            _ if opcode.starts_with("PUSH") => {
-                let n = opcode[4..].parse::<usize>().unwrap();
-                code.put_u8(0x60 + n as u8);
-                if n == 0 {
-                    continue;
-                }
-                if n > 32 {
-                    return Err(CompileError::InvalidOpcode { line: idx, opcode });
-                }
-                let value = line.next().ok_or_else(|| CompileError::MissingValue {
-                    opcode: opcode.clone(),
-                    line: idx,
-                })?;
-                let value = U256::from_str(value).map_err(|e| CompileError::InvalidPushValue {
-                    line: idx,
-                    error: e,
-                })?;
-                if !value.shr(8 * n).is_zero() {
-                    let length = (256 - value.leading_zeros()) / 8;
-                    return Err(CompileError::InvalidPushValueLength {
-                        line: idx,
-                        expected: n,
-                        length,
-                    });
-                }
-                let value: [u8; 32] = value.to_be_bytes();
-                code.extend(&value[32 - n..]);
-            }
-            _ if opcode.starts_with("DUP") => {
-                let n = opcode[3..].parse::<u8>().unwrap();
-                if n == 0 || n > 16 {
-                    return Err(CompileError::InvalidOpcode { opcode, line: idx });
-                }
-                code.put_u8(0x7f + n);
-            }
-            _ if opcode.starts_with("SWAP") => {
-                let n = opcode[4..].parse::<u8>().unwrap();
-                if n == 0 || n > 16 {
-                    return Err(CompileError::InvalidOpcode { opcode, line: idx });
-                }
-                code.put_u8(0x8f + n);
-            }
-            _ if opcode.starts_with("LOG") => {
-                let n = opcode[3..].parse::<u8>().unwrap();
-                if n > 4 {
-                    return Err(CompileError::InvalidOpcode { opcode, line: idx });
-                }
-                code.put_u8(0xa0 + n);
-            }
-            "CREATE" => code.put_u8(0xf0),
-            "CALL" => code.put_u8(0xf1),
-            "CALLCODE" => code.put_u8(0xf2),
-            "RETURN" => code.put_u8(0xf3),
-            "DELEGATECALL" => code.put_u8(0xf4),
-            "CREATE2" => code.put_u8(0xf5),
-            "STATICCALL" => code.put_u8(0xfa),
-            "REVERT" => code.put_u8(0xfd),
-            "INVALID" => code.put_u8(0xfe),
-            "SELFDESTRUCT" => code.put_u8(0xff),
-            _ => return Err(CompileError::InvalidOpcode { opcode, line: idx }),
+    }
+}
+
+impl Serialize for ForkTime {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ForkTime::Absolute(timestamp) => serializer.serialize_u64(*timestamp),
+            ForkTime::Relative(offset) => serializer.serialize_str(&format!("+{offset}")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ForkTime {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Absolute(u64),
+            Relative(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Absolute(timestamp) => Ok(ForkTime::Absolute(timestamp)),
+            Raw::Relative(s) => s
+                .strip_prefix('+')
+                .and_then(|offset| offset.parse().ok())
+                .map(ForkTime::Relative)
+                .ok_or_else(|| serde::de::Error::custom("expected timestamp or \"+seconds\"")),
         }
     }
+}
+
+/// Wraps runtime code in init code that copies it to memory and returns it.
+///
+/// ```text
+/// PUSH2 len, DUP1, PUSH1 0x0c, PUSH1 0, CODECOPY, PUSH1 0, RETURN
+/// ```
+pub fn deploy_code(runtime: &[u8]) -> Result<Bytes, CodeTooLarge> {
+    init_code(runtime, &BTreeMap::new())
+}
+
+/// Same as [`deploy_code`], with the init code first writing the non-zero storage slots.
+///
+/// ```text
+/// PUSH value, PUSH slot, SSTORE, ..., PUSH2 len, DUP1, PUSH offset, PUSH1 0, CODECOPY, ...
+/// ```
+pub fn init_code(runtime: &[u8], storage: &BTreeMap<U256, U256>) -> Result<Bytes, CodeTooLarge> {
+    if runtime.len() > u16::MAX as usize {
+        return Err(CodeTooLarge(runtime.len()));
+    }
+    let mut code = BytesMut::new();
+    for (slot, value) in storage.iter().filter(|(_, value)| !value.is_zero()) {
+        put_push(&mut code, *value);
+        put_push(&mut code, *slot);
+        code.put_u8(0x55);
+    }
+    // PUSH2 len, DUP1, PUSHn offset, PUSH1 0, CODECOPY, PUSH1 0, RETURN
+    let offset = code.len() + 11;
+    let offset = offset + U256::from(offset + 1).byte_len();
+    code.put_u8(0x61);
+    code.put_u16(runtime.len() as u16);
+    code.put_u8(0x80);
+    put_push(&mut code, U256::from(offset));
+    code.put_slice(&[0x60, 0x00, 0x39, 0x60, 0x00, 0xf3]);
+    code.put_slice(runtime);
     Ok(Bytes::from(code.freeze()))
 }
 
-pub const fn default_true() -> bool {
-    true
+/// The runtime code is longer than the `PUSH2` length of the init code can express.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("runtime code of {0} bytes is too large to deploy")]
+pub struct CodeTooLarge(pub usize);
+
+/// Pushes with the smallest `PUSHn`, avoiding `PUSH0` which needs Shanghai.
+fn put_push(code: &mut BytesMut, value: U256) {
+    let width = value.byte_len().max(1);
+    code.put_u8(0x5f + width as u8);
+    code.put_slice(&value.to_be_bytes::<32>()[32 - width..]);
 }
 
 pub const fn default_zero() -> u64 {
     0
 }
 
-pub const fn default_enabled() -> BoolOr<u64> {
-    BoolOr::Value(0)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_compile_mnemonic() {
-        let code = compile_mnemonic(
-            r#"
-            PUSH1 0x60
-            PUSH1 0x40
-            MSTORE
-            PUSH1 0x00
-            RETURN
-            "#,
-        )
-        .unwrap();
+    fn test_deploy_code() {
+        let code = deploy_code(&[0x60, 0x2a]).unwrap();
         assert_eq!(
             code,
-            Bytes::from_static(&[0x61, 0x60, 0x61, 0x40, 0x52, 0x61, 0x00, 0xf3])
+            Bytes::from_static(&[
+                0x61, 0x00, 0x02, 0x80, 0x60, 0x0c, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3, 0x60, 0x2a
+            ])
         );
 
-        let code = compile_mnemonic(
-            r#"
-            PUSH0
-            PUSH1 0x01
-            DUP1
-            SWAP1
-            LOG0
-            "#,
-        )
-        .unwrap();
+        let storage = BTreeMap::from([
+            (U256::from(1), U256::from(0x1234)),
+            (U256::from(2), U256::ZERO),
+        ]);
+        let code = init_code(&[0x00], &storage).unwrap();
         assert_eq!(
             code,
-            Bytes::from_static(&[0x60, 0x61, 0x01, 0x80, 0x90, 0xa0])
+            Bytes::from_static(&[
+                0x61, 0x12, 0x34, 0x60, 0x01, 0x55, 0x61, 0x00, 0x01, 0x80, 0x60, 0x12, 0x60, 0x00,
+                0x39, 0x60, 0x00, 0xf3, 0x00
+            ])
+        );
+        assert_eq!(code[0x12..], [0x00]);
+
+        // the offset itself needs a wider push past 255 bytes
+        let storage = (0..40)
+            .map(|slot| (U256::from(slot), U256::MAX))
+            .collect::<BTreeMap<_, _>>();
+        let code = init_code(&[0xfe], &storage).unwrap();
+        assert_eq!(code[code.len() - 1], 0xfe);
+        let offset = code.len() - 1;
+        assert_eq!(code[offset - 10..offset - 8], [0x80, 0x61]);
+        assert_eq!(
+            u16::from_be_bytes([code[offset - 8], code[offset - 7]]) as usize,
+            offset
+        );
+
+        assert_eq!(
+            deploy_code(&vec![0x00; 0x10000]),
+            Err(CodeTooLarge(0x10000))
         );
     }
 }
@@ -0,0 +1,360 @@
+#[cfg(feature = "scroll")]
+use crate::hardfork::Hardfork;
+use crate::{
+    asm::{Decoded, Line, SourceMap, decode},
+    opcode::{EvmVersion, OpCode},
+    stack::{StackIssue, analyze},
+};
+use alloy_genesis::ChainConfig;
+use alloy_primitives::{Address, U256};
+use std::fmt::{Display, Formatter};
+
+/// Precompiles by address, with the EVM version introducing them.
+const PRECOMPILES: &[(u64, &str, EvmVersion)] = &[
+    (0x01, "ecrecover", EvmVersion::Frontier),
+    (0x02, "sha256", EvmVersion::Frontier),
+    (0x03, "ripemd160", EvmVersion::Frontier),
+    (0x04, "identity", EvmVersion::Frontier),
+    (0x05, "modexp", EvmVersion::Byzantium),
+    (0x06, "ecadd", EvmVersion::Byzantium),
+    (0x07, "ecmul", EvmVersion::Byzantium),
+    (0x08, "ecpairing", EvmVersion::Byzantium),
+    (0x09, "blake2f", EvmVersion::Istanbul),
+    (0x0a, "point evaluation", EvmVersion::Cancun),
+    (0x0b, "bls12 g1add", EvmVersion::Prague),
+    (0x0c, "bls12 g1msm", EvmVersion::Prague),
+    (0x0d, "bls12 g2add", EvmVersion::Prague),
+    (0x0e, "bls12 g2msm", EvmVersion::Prague),
+    (0x0f, "bls12 pairing", EvmVersion::Prague),
+    (0x10, "bls12 map fp to g1", EvmVersion::Prague),
+    (0x11, "bls12 map fp2 to g2", EvmVersion::Prague),
+    (0x100, "p256verify", EvmVersion::Osaka),
+];
+
+/// Why a feature used by code is not available.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Requirement {
+    /// Available once the named fork is active.
+    Fork(&'static str),
+    /// Not available on this chain at all.
+    Unsupported,
+}
+
+/// The EVM features of a block, derived from the chain config.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EvmRules {
+    pub version: EvmVersion,
+    /// Scroll changes some opcodes and precompiles on top of the Ethereum rules.
+    #[cfg(feature = "scroll")]
+    pub hardfork: Option<Hardfork>,
+}
+
+/// An opcode or precompile call not available under some [`EvmRules`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeIssue {
+    pub offset: usize,
+    /// The mnemonic, or the name of the precompile.
+    pub feature: String,
+    pub requirement: Requirement,
+}
+
+/// Where validated code comes from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CodeOrigin {
+    Alloc(Address),
+    /// The `deploy-code` of a transaction.
+    Deployment(usize),
+}
+
+/// A problem with code found while building.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeWarning {
+    pub origin: CodeOrigin,
+    /// The mnemonic line at the offset of the problem, for assembled code.
+    pub line: Option<Line>,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    Unavailable(CodeIssue),
+    /// Only checked for assembled code.
+    Stack(StackIssue),
+}
+
+impl EvmRules {
+    /// Returns the rules of the given block.
+    pub fn at(config: &ChainConfig, number: u64, timestamp: u64) -> Self {
+        let block = |block: Option<u64>| block.is_some_and(|block| number >= block);
+        let time = |time: Option<u64>| time.is_some_and(|time| timestamp >= time);
+        let version = [
+            (EvmVersion::Osaka, time(config.osaka_time)),
+            (EvmVersion::Prague, time(config.prague_time)),
+            (EvmVersion::Cancun, time(config.cancun_time)),
+            (EvmVersion::Shanghai, time(config.shanghai_time)),
+            (EvmVersion::London, block(config.london_block)),
+            (EvmVersion::Istanbul, block(config.istanbul_block)),
+            (
+                EvmVersion::Constantinople,
+                block(config.constantinople_block) || block(config.petersburg_block),
+            ),
+            (EvmVersion::Byzantium, block(config.byzantium_block)),
+            (EvmVersion::Homestead, block(config.homestead_block)),
+        ]
+        .into_iter()
+        .find_map(|(version, active)| active.then_some(version))
+        .unwrap_or(EvmVersion::Frontier);
+
+        EvmRules {
+            version,
+            #[cfg(feature = "scroll")]
+            hardfork: Hardfork::active_at(config, number, timestamp),
+        }
+    }
+
+    /// Returns why the opcode cannot run, `None` if it can.
+    pub fn opcode(&self, opcode: &OpCode) -> Option<Requirement> {
+        #[cfg(feature = "scroll")]
+        match opcode.mnemonic {
+            "BLOBHASH" | "BLOBBASEFEE" | "SELFDESTRUCT" => return Some(Requirement::Unsupported),
+            // enabled by Curie, whatever the EVM version of the chain config
+            "BASEFEE" | "TLOAD" | "TSTORE" | "MCOPY" => {
+                return (self.hardfork < Some(Hardfork::Curie))
+                    .then_some(Requirement::Fork(Hardfork::Curie.name()));
+            }
+            _ => {}
+        }
+        (opcode.since > self.version).then_some(Requirement::Fork(opcode.since.name()))
+    }
+
+    /// Returns why a call to the precompile at `address` fails, `None` if it is available or
+    /// the address is not a precompile.
+    pub fn precompile(&self, address: Address) -> Option<(&'static str, Requirement)> {
+        let address = u64::try_from(U256::from_be_slice(address.as_slice())).ok()?;
+        let &(_, name, since) = PRECOMPILES.iter().find(|(a, ..)| *a == address)?;
+        #[cfg(feature = "scroll")]
+        match address {
+            0x03 | 0x09..=0x11 => return Some((name, Requirement::Unsupported)),
+            0x100 => {
+                return (self.hardfork < Some(Hardfork::EuclidV2))
+                    .then_some((name, Requirement::Fork(Hardfork::EuclidV2.name())));
+            }
+            _ => {}
+        }
+        (since > self.version).then_some((name, Requirement::Fork(since.name())))
+    }
+}
+
+/// Finds opcodes and precompile calls in the code that the rules do not offer.
+///
+/// Precompile calls are only recognized when the address and gas are pushed right before the
+/// call, as in `PUSH1 0x01 GAS STATICCALL`. Calls computing the address, or pushing it further
+/// away, are not checked, so no warning does not mean the code calls no unavailable precompile.
+pub fn check_code(code: &[u8], rules: &EvmRules) -> Vec<CodeIssue> {
+    let decoded = decode(code);
+    let mut issues = vec![];
+    for (idx, item) in decoded.iter().enumerate() {
+        let Decoded::Op { offset, opcode, .. } = item else {
+            continue;
+        };
+        if let Some(requirement) = rules.opcode(opcode) {
+            issues.push(CodeIssue {
+                offset: *offset,
+                feature: opcode.mnemonic.to_string(),
+                requirement,
+            });
+        }
+
+        if !matches!(
+            opcode.mnemonic,
+            "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL"
+        ) || idx < 2
+        {
+            continue;
+        }
+        let gas = matches!(
+            &decoded[idx - 1],
+            Decoded::Op { opcode, .. } if opcode.immediate > 0 || opcode.mnemonic == "GAS"
+        );
+        if let Decoded::Op {
+            opcode, immediate, ..
+        } = &decoded[idx - 2]
+            && gas
+            && opcode.immediate > 0
+            && immediate.len() <= 20
+            && let Some((name, requirement)) =
+                rules.precompile(Address::left_padding_from(immediate))
+        {
+            issues.push(CodeIssue {
+                offset: *offset,
+                feature: format!("precompile {name}"),
+                requirement,
+            });
+        }
+    }
+    issues
+}
+
+/// Checks code for features the rules do not offer and, when it was assembled from mnemonics,
+/// for stack errors.
+pub(crate) fn check_built_code(
+    code: &[u8],
+    source_map: Option<&SourceMap>,
+    rules: &EvmRules,
+    origin: CodeOrigin,
+) -> Vec<CodeWarning> {
+    let line = |offset: usize| source_map?.line(offset).cloned();
+    let mut warnings = check_code(code, rules)
+        .into_iter()
+        .map(|issue| (issue.offset, WarningKind::Unavailable(issue)))
+        .collect::<Vec<_>>();
+    if let Some(source_map) = source_map {
+        let data = source_map
+            .spans()
+            .iter()
+            .filter(|span| span.data)
+            .map(|span| span.offset..span.offset + span.len)
+            .collect::<Vec<_>>();
+        warnings.extend(
+            analyze(code, &data)
+                .into_iter()
+                .map(|issue| (issue.offset, WarningKind::Stack(issue))),
+        );
+    }
+    warnings.sort_by_key(|(offset, _)| *offset);
+    warnings
+        .into_iter()
+        .map(|(offset, kind)| CodeWarning {
+            origin,
+            line: line(offset),
+            kind,
+        })
+        .collect()
+}
+
+impl Display for Requirement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Requirement::Fork(fork) => write!(f, "needs {fork}"),
+            Requirement::Unsupported => f.write_str("is not supported on this chain"),
+        }
+    }
+}
+
+impl Display for CodeIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at pc {:#06x} {}",
+            self.feature, self.offset, self.requirement
+        )
+    }
+}
+
+impl Display for CodeWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.origin {
+            CodeOrigin::Alloc(address) => write!(f, "alloc {address}: ")?,
+            CodeOrigin::Deployment(idx) => write!(f, "transaction#{idx}: ")?,
+        }
+        match &self.kind {
+            WarningKind::Unavailable(issue) => write!(f, "{issue}")?,
+            WarningKind::Stack(issue) => write!(f, "{issue}")?,
+        }
+        if let Some(line) = &self.line {
+            write!(f, " ({line})")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::compile_mnemonic;
+
+    #[cfg(not(feature = "scroll"))]
+    #[test]
+    fn test_check_code() {
+        let config = ChainConfig {
+            london_block: Some(0),
+            shanghai_time: Some(0),
+            cancun_time: Some(100),
+            ..Default::default()
+        };
+        let code = compile_mnemonic(
+            "PUSH0\nTLOAD\nPUSH1 0x0a\nGAS\nSTATICCALL\nPUSH1 0x01\nGAS\nSTATICCALL",
+        )
+        .unwrap();
+
+        let rules = EvmRules::at(&config, 1, 50);
+        assert_eq!(rules.version, EvmVersion::Shanghai);
+        let issues = check_code(&code, &rules);
+        assert_eq!(
+            issues,
+            [
+                CodeIssue {
+                    offset: 1,
+                    feature: "TLOAD".to_string(),
+                    requirement: Requirement::Fork("cancun"),
+                },
+                CodeIssue {
+                    offset: 5,
+                    feature: "precompile point evaluation".to_string(),
+                    requirement: Requirement::Fork("cancun"),
+                },
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "TLOAD at pc 0x0001 needs cancun".to_string()
+        );
+
+        assert!(check_code(&code, &EvmRules::at(&config, 1, 100)).is_empty());
+        let rules = EvmRules::at(&ChainConfig::default(), 0, 0);
+        assert_eq!(rules.version, EvmVersion::Frontier);
+        assert_eq!(check_code(&code, &rules).len(), 5);
+    }
+
+    #[cfg(feature = "scroll")]
+    #[test]
+    fn test_check_code() {
+        let mut config = ChainConfig {
+            london_block: Some(0),
+            shanghai_time: Some(0),
+            ..Default::default()
+        };
+        config
+            .extra_fields
+            .insert("curieBlock".to_string(), 10.into());
+        let code = compile_mnemonic(
+            "PUSH0\nTLOAD\nBLOBHASH\nPUSH1 0x03\nGAS\nSTATICCALL\nBASEFEE\nPUSH0\nDUP1\nDUP1\nMCOPY",
+        )
+        .unwrap();
+
+        let issues = check_code(&code, &EvmRules::at(&config, 0, 0));
+        let requirements = issues
+            .iter()
+            .map(|issue| (issue.feature.as_str(), issue.requirement))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requirements,
+            [
+                ("TLOAD", Requirement::Fork("curie")),
+                ("BLOBHASH", Requirement::Unsupported),
+                ("precompile ripemd160", Requirement::Unsupported),
+                ("BASEFEE", Requirement::Fork("curie")),
+                ("MCOPY", Requirement::Fork("curie")),
+            ]
+        );
+        assert_eq!(check_code(&code, &EvmRules::at(&config, 10, 0)).len(), 2);
+
+        // later forks keep them
+        config
+            .extra_fields
+            .insert("darwinTime".to_string(), 100.into());
+        let rules = EvmRules::at(&config, 10, 100);
+        assert_eq!(rules.hardfork, Some(Hardfork::Darwin));
+        assert_eq!(check_code(&code, &rules).len(), 2);
+    }
+}
@@ -0,0 +1,259 @@
+//! Stateless verification of dumped witnesses.
+//!
+//! Each block is re-executed in-process from its [`BlockWitness`] alone, the way a prover
+//! consumes it, and the result is compared with the block the node sealed.
+
+use crate::{
+    backend::TxOutcome,
+    core::{TraceReport, TracedBlock},
+};
+use alloy_consensus::{
+    Eip658Value, Receipt, ReceiptWithBloom, TxReceipt, Typed2718,
+    proofs::ordered_trie_root_with_encoder,
+};
+use alloy_genesis::Genesis;
+use alloy_primitives::{B256, Log};
+use alloy_rlp::{BufMut, Encodable};
+use sbv_core::{EvmDatabase, EvmExecutor};
+use sbv_kv::nohash::NoHashMap;
+#[cfg(not(feature = "scroll"))]
+use sbv_primitives::chainspec::ChainSpec;
+#[cfg(feature = "scroll")]
+use sbv_primitives::chainspec::ScrollChainSpec as ChainSpec;
+use sbv_primitives::{BlockWitness as _, ext::BlockWitnessRethExt, types::BlockWitness};
+use sbv_trie::BlockWitnessTrieExt;
+use std::sync::Arc;
+
+/// Where re-executing a witness disagrees with the sealed block.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Mismatch {
+    #[error("re-execution failed: {0}")]
+    Execution(String),
+    #[error("state root {actual}, the block has {expected}")]
+    StateRoot { expected: B256, actual: B256 },
+    #[error("receipts root {actual}, the block has {expected}")]
+    ReceiptsRoot { expected: B256, actual: B256 },
+    #[error("{actual} gas used, the block has {expected}")]
+    GasUsed { expected: u64, actual: u64 },
+    #[error("transaction#{idx}: {actual} gas used, its receipt has {expected}")]
+    TxGasUsed {
+        idx: usize,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("transaction#{idx}: success {actual}, its receipt has {expected}")]
+    TxStatus {
+        idx: usize,
+        expected: bool,
+        actual: bool,
+    },
+    #[error("transaction#{idx}: {actual} logs differing from the {expected} of its receipt")]
+    TxLogs {
+        idx: usize,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+/// Outcome of verifying the witness of one block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockVerification {
+    pub number: u64,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl BlockVerification {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl TraceReport {
+    /// Re-executes the witness of every traced block, comparing roots and gas with the sealed
+    /// block and receipts with the traced transactions.
+    pub fn verify(&self) -> Vec<BlockVerification> {
+        let genesis = Genesis {
+            config: self.chain_config.clone(),
+            ..Default::default()
+        };
+        let chain_spec = Arc::new(ChainSpec::from(genesis));
+        self.blocks
+            .iter()
+            .map(|block| {
+                let transactions = self
+                    .transactions
+                    .iter()
+                    .filter(|tx| tx.outcome.block_number == block.number)
+                    .map(|tx| (tx.idx, &tx.outcome))
+                    .collect::<Vec<_>>();
+                verify_block(block, chain_spec.clone(), &transactions)
+            })
+            .collect()
+    }
+}
+
+/// Verifies the witness of `block`. `transactions` are the ones Spectre sent, by builder index;
+/// other transactions of the block are only checked through the roots.
+pub fn verify_block(
+    block: &TracedBlock,
+    chain_spec: Arc<ChainSpec>,
+    transactions: &[(usize, &TxOutcome)],
+) -> BlockVerification {
+    let mut verification = BlockVerification {
+        number: block.number,
+        mismatches: vec![],
+    };
+    let (state_root, receipts) = match execute(&block.witness, chain_spec) {
+        Ok(executed) => executed,
+        Err(e) => {
+            verification.mismatches.push(Mismatch::Execution(e));
+            return verification;
+        }
+    };
+    debug!(number = block.number, "witness re-executed");
+
+    if state_root != block.state_root {
+        verification.mismatches.push(Mismatch::StateRoot {
+            expected: block.state_root,
+            actual: state_root,
+        });
+    }
+    let actual = receipts_root(&receipts);
+    if actual != block.receipts_root {
+        verification.mismatches.push(Mismatch::ReceiptsRoot {
+            expected: block.receipts_root,
+            actual,
+        });
+    }
+    let gas_used = receipts
+        .last()
+        .map(|receipt| receipt.cumulative_gas_used())
+        .unwrap_or_default();
+    if gas_used != block.gas_used {
+        verification.mismatches.push(Mismatch::GasUsed {
+            expected: block.gas_used,
+            actual: gas_used,
+        });
+    }
+
+    for &(idx, outcome) in transactions {
+        let position = outcome.position as usize;
+        let Some(receipt) = receipts.get(position) else {
+            verification.mismatches.push(Mismatch::Execution(format!(
+                "transaction#{idx} at position {position} not executed"
+            )));
+            continue;
+        };
+        let before = match position {
+            0 => 0,
+            _ => receipts[position - 1].cumulative_gas_used(),
+        };
+        let gas_used = receipt.cumulative_gas_used() - before;
+        if gas_used != outcome.gas_used {
+            verification.mismatches.push(Mismatch::TxGasUsed {
+                idx,
+                expected: outcome.gas_used,
+                actual: gas_used,
+            });
+        }
+        if receipt.status() != outcome.success {
+            verification.mismatches.push(Mismatch::TxStatus {
+                idx,
+                expected: outcome.success,
+                actual: receipt.status(),
+            });
+        }
+        if receipt.logs() != outcome.logs.as_slice() {
+            verification.mismatches.push(Mismatch::TxLogs {
+                idx,
+                expected: outcome.logs.len(),
+                actual: receipt.logs().len(),
+            });
+        }
+    }
+    verification
+}
+
+/// Re-executes the block of `witness` on the state it proves, returning the post-state root and
+/// the receipts.
+fn execute(
+    witness: &BlockWitness,
+    chain_spec: Arc<ChainSpec>,
+) -> Result<(B256, Vec<impl TxReceipt<Log = Log> + Typed2718>), String> {
+    let mut code_db = NoHashMap::default();
+    witness.import_codes(&mut code_db);
+    let mut nodes_provider = NoHashMap::default();
+    witness
+        .import_nodes(&mut nodes_provider)
+        .map_err(|e| e.to_string())?;
+    let mut db = EvmDatabase::new_from_root(
+        &code_db,
+        witness.pre_state_root(),
+        &nodes_provider,
+        &NoHashMap::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    let block = witness.build_reth_block().map_err(|e| e.to_string())?;
+    let output = EvmExecutor::new(chain_spec, &db, &block)
+        .execute()
+        .map_err(|e| e.to_string())?;
+    db.update(&nodes_provider, output.state.state.iter())
+        .map_err(|e| e.to_string())?;
+    Ok((db.commit_changes(), output.receipts))
+}
+
+/// Root of the receipts trie, encoding each receipt as its EIP-2718 envelope.
+pub(crate) fn receipts_root<R: TxReceipt<Log = Log> + Typed2718>(receipts: &[R]) -> B256 {
+    let encoded = receipts
+        .iter()
+        .map(|receipt| {
+            let with_bloom = ReceiptWithBloom::new(
+                Receipt {
+                    status: Eip658Value::Eip658(receipt.status()),
+                    cumulative_gas_used: receipt.cumulative_gas_used(),
+                    logs: receipt.logs().to_vec(),
+                },
+                receipt.bloom(),
+            );
+            let mut out = vec![];
+            if !receipt.is_legacy() {
+                out.push(receipt.ty());
+            }
+            with_bloom.encode(&mut out);
+            out
+        })
+        .collect::<Vec<_>>();
+    ordered_trie_root_with_encoder(&encoded, |receipt, out| out.put_slice(receipt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpectreBuilder, backend::Backend};
+    use alloy_consensus::{ReceiptEnvelope, proofs::calculate_receipt_root};
+
+    #[test]
+    fn test_receipts_root() {
+        let receipt = |cumulative_gas_used| Receipt {
+            status: Eip658Value::Eip658(true),
+            cumulative_gas_used,
+            logs: vec![],
+        };
+        let receipts = [
+            ReceiptEnvelope::Legacy(receipt(21_000).with_bloom()),
+            ReceiptEnvelope::Eip1559(receipt(42_000).with_bloom()),
+        ];
+        assert_eq!(receipts_root(&receipts), calculate_receipt_root(&receipts));
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let mut config: SpectreBuilder =
+            toml::from_str(include_str!("../../../examples/full.toml")).unwrap();
+        config.system.backend = Backend::Revm;
+        let report = config.build().unwrap().trace().await.unwrap();
+        for verification in report.verify() {
+            assert!(verification.is_ok(), "{verification:?}");
+        }
+    }
+}
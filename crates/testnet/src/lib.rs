@@ -16,6 +16,8 @@ use tokio::{
 };
 
 const MINER_PASSWORD: &str = "testnet";
+/// The lowest gas price, in wei, the spawned node pools and mines.
+pub const MIN_GAS_PRICE: u128 = 48_700_001;
 
 /// Test net builder error.
 #[derive(Debug, thiserror::Error)]
@@ -168,10 +170,8 @@ impl<'a> TestNetBuilder<'a> {
                 "--verbosity=5",
                 "--txpool.globalqueue=4096",
                 "--txpool.globalslots=40960",
-                "--txpool.pricelimit=48700001",
                 "--txpool.nolocals",
                 "--miner.gaslimit=10000000",
-                "--miner.gasprice=48700001",
                 "--rpc.gascap=0",
                 "--gpo.ignoreprice=1",
                 // "--http",
@@ -183,6 +183,8 @@ impl<'a> TestNetBuilder<'a> {
                 // "--allow-insecure-unlock",
                 // "--mine",
             ])
+            .arg(format!("--txpool.pricelimit={MIN_GAS_PRICE}"))
+            .arg(format!("--miner.gasprice={MIN_GAS_PRICE}"))
             .arg("--datadir")
             .arg(&geth_data_dir)
             // .arg("--unlock")
@@ -246,6 +248,16 @@ struct TestNetProviderInner {
 }
 
 impl TestNetProvider {
+    /// Path of the IPC socket, for attaching to the node.
+    pub fn ipc_path(&self) -> PathBuf {
+        self.0.temp_dir.path().join("data").join("geth.ipc")
+    }
+
+    /// Address and keystore password of the account sealing blocks.
+    pub fn miner_credentials(&self) -> (Address, &'static str) {
+        (self.0.signer_addr, MINER_PASSWORD)
+    }
+
     pub async fn stop_miner(&self) -> TransportResult<()> {
         let no_params = serde_json::value::to_raw_value(&()).unwrap();
         self.raw_request_dyn("miner_stop".into(), &no_params)